@@ -1,41 +1,90 @@
 mod line;
 mod position;
 mod size;
-use line::Line;
+use line::{Line, indent_guides, show_whitespace};
 mod annotated_string;
 use annotated_string::{AnnotatedString, AnnotationType};
 pub mod highlight;
 mod terminal;
-use crossterm::event::{Event, KeyEvent, KeyEventKind, read};
+use crossterm::event::{Event, KeyEvent, KeyEventKind, poll, read};
 use position::Position;
 use size::Size;
 mod document_status;
 use document_status::DocumentStatus;
 use std::{
-    env,
-    io::Error,
+    env, fs,
+    io::{BufRead, BufReader, Error},
     panic::{set_hook, take_hook},
+    path::PathBuf,
+    process, thread,
+    time::{Duration, Instant},
 };
 use terminal::Terminal;
 mod command;
-use ui_components::{CommandBar, MessageBar, StatusBar, UIComponent, View};
+mod ex_command;
+mod file_walker;
+mod folding;
+mod fuzzy;
+mod macros;
+mod modal;
+mod palette;
+mod path_completion;
+mod session;
+mod shell_command;
+mod swap_file;
+use ex_command::{ExCommand, Setting};
+use folding::FoldCommand;
+use highlight::config_file::{ConfigError, load_editor_config};
+use macros::MacroStore;
+use modal::{ModalAction, ModalState, Mode};
+use palette::{Action, PaletteEntry};
+use path_completion::{PathCompletion, expand_tilde};
+use session::Session;
+use shell_command::{
+    ShellCommand, ShellMode, describe_status, diff_command, strip_trailing_newline,
+    summarize_output,
+};
+use swap_file::SwapSettings;
+use ui_components::{CommandBar, CompletionList, Finder, MessageBar, StatusBar, UIComponent, View};
 mod ui_components;
 use self::command::{
     Command::{self, Edit, Move, System},
     Edit::InsertNewline,
     MoveDirection,
-    System::{Dismiss, Quit, Resize, Save, Search},
+    System::{
+        CommandLine, Dismiss, JumpBack, JumpForward, Open, Palette, PlayMacro, Quit, RecordMacro,
+        Resize, Save, Search, Shell,
+    },
 };
 
 pub const NAME: &str = env!("CARGO_PKG_NAME");
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const QUIT_TIMES: u8 = 2;
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const HIGHLIGHT_POLL_INTERVAL: Duration = Duration::from_millis(30);
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+const CRASH_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(1);
+const NO_SESSION_FLAG: &str = "--no-session";
+const MAX_FINDER_FILES: usize = 50_000;
+const MAX_FINDER_MATCHES: usize = 200;
+const PALETTE_NAME_WIDTH: usize = 32;
+const MAX_MACRO_REPEAT: usize = 10_000;
 
 #[derive(Eq, PartialEq, Default)]
 enum PromptType {
     Search,
     Save,
+    Shell,
+    FileChanged,
+    Recover,
+    ConfirmOverwrite,
+    ConfirmCreateDir,
+    Open,
+    Palette,
+    CommandLine,
+    MacroName,
+    PlayMacro,
     #[default]
     None,
 }
@@ -57,29 +106,104 @@ pub struct Editor {
     prompt_type: PromptType,
     title: String,
     quit_times: u8,
+    last_disk_check: Option<Instant>,
+    save_after_change_prompt: bool,
+    swap_settings: Option<SwapSettings>,
+    last_swap_write: Option<Instant>,
+    /// The edit version of the buffer last written to the swap file.
+    swap_file_version: Option<u64>,
+    last_crash_snapshot: Option<Instant>,
+    /// The edit version of the buffer in the crash snapshot, if there is one.
+    crash_snapshot_version: Option<u64>,
+    recovery_swap: Option<PathBuf>,
+    session: Option<Session>,
+    search_history: Vec<String>,
+    save_history: Vec<String>,
+    command_history: Vec<String>,
+    completion_list: CompletionList,
+    path_completion: Option<PathCompletion>,
+    pending_save_path: Option<PathBuf>,
+    finder: Finder,
+    finder_files: Vec<String>,
+    palette_entries: Vec<PaletteEntry>,
+    palette_matches: Vec<usize>,
+    modal: Option<ModalState>,
+    recording: Option<Vec<Command>>,
+    last_macro: Vec<Command>,
+    macros: MacroStore,
+    /// Set while a macro plays, so that a macro which plays a macro cannot
+    /// recurse until the stack overflows.
+    playing_macro: bool,
 }
 
 impl Editor {
     pub fn new() -> Result<Self, Error> {
         let current_hook = take_hook();
         set_hook(Box::new(move |panic_info| {
+            swap_file::write_crash_snapshot();
             let _ = Terminal::terminate();
             current_hook(panic_info);
         }));
         Terminal::initialize()?;
 
         let mut editor = Self::default();
+        editor.swap_settings = SwapSettings::load().filter(|settings| settings.enabled);
+        let config = load_editor_config(None).ok();
+        if let Some(max_size_kb) = config
+            .as_ref()
+            .and_then(|config| config.highlighting.as_ref())
+            .and_then(|highlighting| highlighting.max_file_size_kb)
+        {
+            editor
+                .view
+                .set_max_highlight_size(max_size_kb.saturating_mul(1024));
+        }
+        if let Some(editing) = config.and_then(|config| config.editing) {
+            if editing.modal == Some(true) {
+                editor.modal = Some(ModalState::default());
+            }
+            editor
+                .view
+                .set_show_whitespace(editing.show_whitespace.unwrap_or(false));
+            editor
+                .view
+                .set_indent_guides(editing.indent_guides.unwrap_or(false));
+        }
+        editor.macros = MacroStore::load();
         let size = Terminal::size().unwrap_or_default();
         editor.resize(size);
-        editor.update_message("HELP: Ctrl-S = save | Ctrl-Q = quit | Ctrl-F = find");
+        editor.update_message(
+            "HELP: Ctrl-S = save | Ctrl-Q = quit | Ctrl-F = find | Ctrl-O = open | Ctrl-P = commands",
+        );
 
-        let args: Vec<String> = env::args().collect();
-        if let Some(file_name) = args.get(1)
-            && editor.view.load(file_name).is_err()
-        {
-            editor.update_message(&format!("ERROR: Could not open file: {file_name}"));
+        let args: Vec<String> = env::args().skip(1).collect();
+        if !args.iter().any(|arg| arg == NO_SESSION_FLAG) {
+            let session = Session::load();
+            editor.search_history.clone_from(&session.search_history);
+            editor.save_history.clone_from(&session.save_history);
+            editor.session = Some(session);
         }
 
+        let file_name = args
+            .iter()
+            .find(|arg| !arg.starts_with("--"))
+            .cloned()
+            .or_else(|| {
+                editor
+                    .session
+                    .as_ref()
+                    .and_then(|session| session.open_buffers.first().cloned())
+            });
+        if let Some(file_name) = file_name {
+            if editor.view.load(&file_name).is_ok() {
+                editor.restore_file_state();
+            } else {
+                editor.update_message(&format!("ERROR: Could not open file: {file_name}"));
+            }
+        }
+        editor.run_startup_script();
+        editor.offer_swap_recovery();
+
         editor.refresh_status();
         Ok(editor)
     }
@@ -90,8 +214,16 @@ impl Editor {
             if self.should_quit {
                 break;
             }
-            match read() {
-                Ok(event) => self.evaluate_event(event),
+            // Wake up often enough to show background highlighting as it
+            // comes in.
+            let poll_interval = if self.view.is_highlighting() {
+                HIGHLIGHT_POLL_INTERVAL
+            } else {
+                EVENT_POLL_INTERVAL
+            };
+            match poll(poll_interval).and_then(|ready| ready.then(read).transpose()) {
+                Ok(Some(event)) => self.evaluate_event(event),
+                Ok(None) => {}
                 #[cfg(debug_assertions)]
                 Err(err) => {
                     panic!("Could not read event: {err:?}");
@@ -99,8 +231,9 @@ impl Editor {
                 #[cfg(not(debug_assertions))]
                 Err(_) => {}
             }
-            let status = self.view.get_status();
-            self.status_bar.update_status(status);
+            self.view.receive_highlights();
+            self.run_periodic_tasks();
+            self.refresh_status();
         }
     }
 
@@ -135,10 +268,31 @@ impl Editor {
             self.resize(size);
             return;
         }
+        // Typing into the macro prompts is part of controlling the
+        // recording, not of the macro itself.
+        if let Some(recording) = &mut self.recording
+            && macros::is_recordable(command)
+            && !matches!(
+                self.prompt_type,
+                PromptType::MacroName | PromptType::PlayMacro
+            )
+        {
+            recording.push(command);
+        }
 
         match self.prompt_type {
             PromptType::Search => self.process_command_during_search(command),
             PromptType::Save => self.process_command_during_save(command),
+            PromptType::Shell => self.process_command_during_shell(command),
+            PromptType::FileChanged => self.process_command_during_file_changed(command),
+            PromptType::Recover => self.process_command_during_recover(command),
+            PromptType::ConfirmOverwrite => self.process_command_during_confirm_overwrite(command),
+            PromptType::ConfirmCreateDir => self.process_command_during_confirm_create_dir(command),
+            PromptType::Open => self.process_command_during_open(command),
+            PromptType::Palette => self.process_command_during_palette(command),
+            PromptType::CommandLine => self.process_command_during_command_line(command),
+            PromptType::MacroName => self.process_command_during_macro_name(command),
+            PromptType::PlayMacro => self.process_command_during_play_macro(command),
             PromptType::None => self.process_command_no_prompt(command),
         }
     }
@@ -147,6 +301,106 @@ impl Editor {
     // CommandDispatch
     // =========================================
     fn process_command_no_prompt(&mut self, command: Command) {
+        match self.modal.as_mut().map(|modal| modal.handle(command)) {
+            None => self.dispatch_command(command),
+            Some(Some(ModalAction::Command(command))) => self.dispatch_command(command),
+            Some(Some(action)) => self.execute_modal_action(action),
+            Some(None) => {}
+        }
+    }
+
+    fn execute_modal_action(&mut self, action: ModalAction) {
+        self.reset_quit_times();
+        match action {
+            ModalAction::Command(command) => self.dispatch_command(command),
+            ModalAction::Move { motion, count } => self.view.apply_motion(motion, count),
+            ModalAction::Operate {
+                operator,
+                motion,
+                count,
+            } => self.view.apply_operator(operator, motion, count),
+            ModalAction::OperateLines { operator, count } => {
+                self.view.operate_on_current_lines(operator, count);
+            }
+            ModalAction::OperateSelection(operator) => self.view.operate_on_selection(operator),
+            ModalAction::Paste { before } => self.view.paste_register(before),
+            ModalAction::Insert(at) => self.view.start_insert(at),
+            ModalAction::EnterVisual => self.view.start_visual(),
+            ModalAction::EnterNormal => self.view.enter_normal(),
+            ModalAction::SetMark(name) => self.set_mark(name),
+            ModalAction::JumpToMark(name) => self.jump_to_mark(name),
+            ModalAction::Fold(command) => self.fold(command),
+        }
+    }
+
+    fn set_show_whitespace(&mut self, enabled: bool) {
+        self.view.set_show_whitespace(enabled);
+        self.update_message(if enabled {
+            "Whitespace rendering on."
+        } else {
+            "Whitespace rendering off."
+        });
+    }
+
+    fn set_indent_guides(&mut self, enabled: bool) {
+        self.view.set_indent_guides(enabled);
+        self.update_message(if enabled {
+            "Indent guides on."
+        } else {
+            "Indent guides off."
+        });
+    }
+
+    fn set_theme(&mut self, name: &str) {
+        match terminal::load_theme(name) {
+            Ok(()) => {}
+            Err(ConfigError::FileNotFound) => {
+                self.update_message(&format!("ERROR: No theme named {name}"));
+                return;
+            }
+            Err(_) => {
+                self.update_message(&format!("ERROR: Could not read theme {name}"));
+                return;
+            }
+        }
+        // Every component draws with the old colors until it is redrawn.
+        self.resize(self.terminal_size);
+        self.update_message(&format!("Theme {name}."));
+    }
+
+    fn fold(&mut self, command: FoldCommand) {
+        if !self.view.fold(command) {
+            self.update_message("No fold at the caret.");
+        }
+    }
+
+    fn set_mark(&mut self, name: char) {
+        if self.view.set_mark(name) {
+            self.update_message(&format!("Mark {name} set."));
+        } else {
+            self.update_message(&format!("ERROR: Mark names are letters, not {name:?}"));
+        }
+    }
+
+    fn jump_to_mark(&mut self, name: char) {
+        if !self.view.jump_to_mark(name) {
+            self.update_message(&format!("ERROR: Mark {name} is not set"));
+        }
+    }
+
+    fn set_modal(&mut self, enabled: bool) {
+        if enabled {
+            self.modal.get_or_insert_with(ModalState::default);
+            self.update_message("Modal editing on. Press i to insert, Esc for normal mode.");
+        } else if let Some(modal) = self.modal.take() {
+            if modal.mode() == Mode::Visual {
+                self.view.enter_normal();
+            }
+            self.update_message("Modal editing off.");
+        }
+    }
+
+    fn dispatch_command(&mut self, command: Command) {
         if matches!(command, System(Quit)) {
             self.handle_quit();
             return;
@@ -157,6 +411,22 @@ impl Editor {
             System(Quit | Resize(_) | Dismiss) => {}
             System(Search) => self.set_prompt(PromptType::Search),
             System(Save) => self.handle_save(),
+            System(Shell) => self.set_prompt(PromptType::Shell),
+            System(Open) => self.set_prompt(PromptType::Open),
+            System(Palette) => self.set_prompt(PromptType::Palette),
+            System(CommandLine) => self.set_prompt(PromptType::CommandLine),
+            System(RecordMacro) => self.toggle_macro_recording(),
+            System(PlayMacro) => self.set_prompt(PromptType::PlayMacro),
+            System(JumpBack) => {
+                if !self.view.jump_back() {
+                    self.update_message("No earlier location in the jump list.");
+                }
+            }
+            System(JumpForward) => {
+                if !self.view.jump_forward() {
+                    self.update_message("No later location in the jump list.");
+                }
+            }
             Edit(edit_command) => self.view.handle_edit_command(edit_command),
 
             Move(move_command) => self.view.handle_move_command(move_command),
@@ -170,54 +440,696 @@ impl Editor {
                 self.view.dismiss_search();
             }
             Edit(InsertNewline) => {
+                push_history(&mut self.search_history, &self.command_bar.value());
                 self.set_prompt(PromptType::None);
                 self.view.exit_search();
             }
             Edit(edit_command) => {
                 self.command_bar.handle_edit_command(edit_command);
-                let query = self.command_bar.value();
-                self.view.search(&query);
+                self.search_for_prompt_value();
             }
-            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Right | MoveDirection::Down) => {
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::PageDown) => {
                 self.view.search_next();
+                self.update_search_counter();
             }
-            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Up | MoveDirection::Left) => {
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::PageUp) => {
                 self.view.search_prev();
+                self.update_search_counter();
             }
-            System(Quit | Resize(_) | Search | Save) | Move(_) => {}
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Up) => {
+                self.command_bar.history_prev();
+                self.search_for_prompt_value();
+            }
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Down) => {
+                self.command_bar.history_next();
+                self.search_for_prompt_value();
+            }
+            Move(move_cmd) => self.command_bar.handle_move_command(move_cmd),
+            System(
+                Quit | Resize(_) | Search | Save | Shell | Open | Palette | CommandLine
+                | RecordMacro | PlayMacro | JumpBack | JumpForward,
+            ) => {}
         }
     }
 
+    fn search_for_prompt_value(&mut self) {
+        let query = self.command_bar.value();
+        self.view.search(&query);
+        self.update_search_counter();
+    }
+
+    fn update_search_counter(&mut self) {
+        let status = match self.view.search_match_counter() {
+            Some((_, 0)) => "no matches".to_string(),
+            Some((0, total)) => format!("{total} matches"),
+            Some((current, total)) => format!("match {current} of {total}"),
+            None => String::new(),
+        };
+        self.command_bar.set_status(&status);
+    }
+
     fn process_command_during_save(&mut self, command: Command) {
+        if matches!(command, Edit(command::Edit::Insert('\t'))) {
+            self.complete_prompt_path();
+            return;
+        }
+        self.clear_completion();
         match command {
-            System(Quit | Resize(_) | Search | Save) | Move(_) => {} // Not applicable during save, Resize already handled at this stage
+            System(
+                Quit | Resize(_) | Search | Save | Shell | Open | Palette | CommandLine
+                | RecordMacro | PlayMacro | JumpBack | JumpForward,
+            ) => {} // Not applicable during save, Resize already handled at this stage
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Up) => {
+                self.command_bar.history_prev();
+            }
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Down) => {
+                self.command_bar.history_next();
+            }
+            Move(move_cmd) => self.command_bar.handle_move_command(move_cmd),
             System(Dismiss) => {
                 self.set_prompt(PromptType::None);
                 self.update_message("Save aborted.");
             }
             Edit(InsertNewline) => {
                 let file_name = self.command_bar.value();
-                self.save(Some(&file_name));
+                push_history(&mut self.save_history, &file_name);
+                self.set_prompt(PromptType::None);
+                self.confirm_save_as(&file_name);
+            }
+            Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
+        }
+    }
+
+    fn process_command_during_confirm_overwrite(&mut self, command: Command) {
+        match command {
+            Edit(command::Edit::Insert('y' | 'Y')) => {
+                self.set_prompt(PromptType::None);
+                if let Some(path) = self.pending_save_path.take() {
+                    self.save_to_path(&path);
+                }
+            }
+            Edit(command::Edit::Insert('n' | 'N')) | System(Dismiss) => {
+                self.set_prompt(PromptType::None);
+                self.pending_save_path = None;
+                self.update_message("Save aborted.");
+            }
+            System(_) | Edit(_) | Move(_) => {}
+        }
+    }
+
+    fn process_command_during_confirm_create_dir(&mut self, command: Command) {
+        match command {
+            Edit(command::Edit::Insert('y' | 'Y')) => {
+                self.set_prompt(PromptType::None);
+                if let Some(path) = self.pending_save_path.take() {
+                    self.create_dir_and_save(&path);
+                }
+            }
+            Edit(command::Edit::Insert('n' | 'N')) | System(Dismiss) => {
+                self.set_prompt(PromptType::None);
+                self.pending_save_path = None;
+                self.update_message("Save aborted.");
+            }
+            System(_) | Edit(_) | Move(_) => {}
+        }
+    }
+
+    fn process_command_during_open(&mut self, command: Command) {
+        if matches!(command, Edit(command::Edit::Insert('\t'))) {
+            self.complete_prompt_path();
+            self.update_finder();
+            return;
+        }
+        self.clear_completion();
+        match command {
+            System(
+                Quit | Resize(_) | Search | Save | Shell | Open | Palette | CommandLine
+                | RecordMacro | PlayMacro | JumpBack | JumpForward,
+            ) => {}
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Up) => {
+                self.finder.select_prev();
+                self.update_finder_preview();
+            }
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Down) => {
+                self.finder.select_next();
+                self.update_finder_preview();
+            }
+            Move(move_cmd) => self.command_bar.handle_move_command(move_cmd),
+            System(Dismiss) => {
+                self.set_prompt(PromptType::None);
+                self.update_message("Open aborted.");
+            }
+            Edit(InsertNewline) => {
+                // A path typed out in full wins over the best fuzzy match, so
+                // files outside the working directory can be opened too.
+                let value = self.command_bar.value();
+                let typed_path = expand_tilde(&value);
+                let path = if typed_path.is_file() {
+                    Some(typed_path)
+                } else {
+                    self.finder.selected_item().map(PathBuf::from)
+                };
+                self.set_prompt(PromptType::None);
+                match path {
+                    Some(path) => self.open_file(&path, false),
+                    None => self.update_message("Open aborted: no matching file."),
+                }
+            }
+            Edit(edit_command) => {
+                self.command_bar.handle_edit_command(edit_command);
+                self.update_finder();
+            }
+        }
+    }
+
+    fn update_finder(&mut self) {
+        let query = self.command_bar.value();
+        let matches = fuzzy::rank(&query, &self.finder_files)
+            .into_iter()
+            .take(MAX_FINDER_MATCHES)
+            .map(|idx| self.finder_files[idx].clone())
+            .collect();
+        self.finder.set_items(matches, self.finder_files.len());
+        self.update_finder_preview();
+    }
+
+    fn update_finder_preview(&mut self) {
+        let preview = self
+            .finder
+            .selected_item()
+            .map(|path| match fs::File::open(path) {
+                Ok(file) => BufReader::new(file)
+                    .lines()
+                    .take(self.finder.preview_height())
+                    .map_while(Result::ok)
+                    .map(|line| line.replace('\t', "    "))
+                    .collect(),
+                Err(err) => vec![format!("Could not read file: {err}")],
+            })
+            .unwrap_or_default();
+        self.finder.set_preview(preview);
+    }
+
+    fn process_command_during_palette(&mut self, command: Command) {
+        match command {
+            System(
+                Quit | Resize(_) | Search | Save | Shell | Open | Palette | CommandLine
+                | RecordMacro | PlayMacro | JumpBack | JumpForward,
+            ) => {}
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Up) => {
+                self.finder.select_prev();
+                self.update_palette_preview();
+            }
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Down) => {
+                self.finder.select_next();
+                self.update_palette_preview();
+            }
+            Move(move_cmd) => self.command_bar.handle_move_command(move_cmd),
+            System(Dismiss) => self.set_prompt(PromptType::None),
+            Edit(InsertNewline) => {
+                let action = self
+                    .palette_matches
+                    .get(self.finder.selected_index())
+                    .map(|&idx| self.palette_entries[idx].action);
+                self.set_prompt(PromptType::None);
+                if let Some(action) = action {
+                    self.run_palette_action(action);
+                }
+            }
+            Edit(edit_command) => {
+                self.command_bar.handle_edit_command(edit_command);
+                self.update_palette();
+            }
+        }
+    }
+
+    fn update_palette(&mut self) {
+        let query = self.command_bar.value();
+        let names: Vec<&str> = self
+            .palette_entries
+            .iter()
+            .map(|entry| entry.name)
+            .collect();
+        self.palette_matches = fuzzy::rank(&query, &names);
+        let items = self
+            .palette_matches
+            .iter()
+            .map(|&idx| {
+                let entry = &self.palette_entries[idx];
+                format!(
+                    "{:<PALETTE_NAME_WIDTH$}{}",
+                    entry.name,
+                    entry.key.as_deref().unwrap_or("")
+                )
+            })
+            .collect();
+        self.finder.set_items(items, self.palette_entries.len());
+        self.update_palette_preview();
+    }
+
+    fn update_palette_preview(&mut self) {
+        let preview = self
+            .palette_matches
+            .get(self.finder.selected_index())
+            .map(|&idx| {
+                let entry = &self.palette_entries[idx];
+                let key = entry
+                    .key
+                    .as_deref()
+                    .map_or("Key: not bound, run it from here".to_string(), |key| {
+                        format!("Key: {key}")
+                    });
+                vec![entry.name.to_string(), String::new(), key]
+            })
+            .unwrap_or_default();
+        self.finder.set_preview(preview);
+    }
+
+    fn run_palette_action(&mut self, action: Action) {
+        match action {
+            Action::Command(command) => self.process_command(command),
+            Action::SaveAs => self.set_prompt(PromptType::Save),
+            Action::Reload => {
+                if self.view.get_status().is_modified {
+                    self.update_message(
+                        "WARNING! File has unsaved changes. Save them before reloading.",
+                    );
+                } else if self.view.reload().is_ok() {
+                    self.update_message("File reloaded from disk.");
+                } else {
+                    self.update_message("ERROR: Could not reload file.");
+                }
+            }
+            Action::Format => match self.format_buffer(None) {
+                Ok(()) => self.update_message("Buffer formatted."),
+                Err(err) => self.update_message(&format!("Formatter failed: {err}")),
+            },
+            Action::DiffWithDisk => self.show_disk_diff(),
+            Action::ToggleModal => self.set_modal(self.modal.is_none()),
+            Action::ToggleWhitespace => self.set_show_whitespace(!show_whitespace()),
+            Action::ToggleIndentGuides => self.set_indent_guides(!indent_guides()),
+            Action::Fold(command) => self.fold(command),
+        }
+    }
+
+    fn process_command_during_command_line(&mut self, command: Command) {
+        match command {
+            System(
+                Quit | Resize(_) | Search | Save | Shell | Open | Palette | CommandLine
+                | RecordMacro | PlayMacro | JumpBack | JumpForward,
+            ) => {}
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Up) => {
+                self.command_bar.history_prev();
+            }
+            Move(move_cmd) if matches!(move_cmd.direction, MoveDirection::Down) => {
+                self.command_bar.history_next();
+            }
+            Move(move_cmd) => self.command_bar.handle_move_command(move_cmd),
+            System(Dismiss) => self.set_prompt(PromptType::None),
+            Edit(InsertNewline) => {
+                let input = self.command_bar.value();
+                push_history(&mut self.command_history, &input);
+                self.set_prompt(PromptType::None);
+                match ExCommand::parse(&input) {
+                    Ok(ex_command) => self.execute_ex_command(ex_command),
+                    Err(err) => self.update_message(&format!("ERROR: {err}")),
+                }
+            }
+            Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
+        }
+    }
+
+    fn execute_ex_command(&mut self, ex_command: ExCommand) {
+        match ex_command {
+            ExCommand::Write(None) => self.handle_save(),
+            ExCommand::Write(Some(file_name)) => self.confirm_save_as(&file_name),
+            ExCommand::WriteQuit => {
+                self.handle_save();
+                if !self.in_prompt() && !self.view.get_status().is_modified {
+                    self.should_quit = true;
+                }
+            }
+            ExCommand::Quit { force } => {
+                if force || !self.view.get_status().is_modified {
+                    self.should_quit = true;
+                } else {
+                    self.update_message("ERROR: No write since last change (add ! to override)");
+                }
+            }
+            ExCommand::Edit { file_name, force } => {
+                self.open_file(&expand_tilde(&file_name), force);
+            }
+            ExCommand::Set(Setting::Modal(enabled)) => self.set_modal(enabled),
+            ExCommand::Set(Setting::FoldLevel(level)) => self.fold(FoldCommand::Level(level)),
+            ExCommand::Set(Setting::Whitespace(enabled)) => self.set_show_whitespace(enabled),
+            ExCommand::Set(Setting::IndentGuides(enabled)) => self.set_indent_guides(enabled),
+            ExCommand::Fold(command) => self.fold(command),
+            ExCommand::Theme(None) => {
+                self.update_message(&format!("Themes: {}", terminal::theme_names().join(", ")));
+            }
+            ExCommand::Theme(Some(name)) => self.set_theme(&name),
+            ExCommand::Language(None) => {
+                let language = self
+                    .view
+                    .get_status()
+                    .language_name
+                    .unwrap_or_else(|| "none".to_string());
+                self.update_message(&format!(
+                    "Language: {language}. Available: {}",
+                    self.view.language_names().join(", ")
+                ));
+            }
+            ExCommand::Language(Some(name)) => match self.view.set_language(&name) {
+                Some(language) => self.update_message(&format!("Language {language}.")),
+                None => self.update_message(&format!("ERROR: No language named {name}")),
+            },
+            ExCommand::Set(Setting::TabWidth(width)) => {
+                self.view.set_tab_width(width);
+                self.update_message(&format!("tabwidth={width}"));
+            }
+            ExCommand::Substitute {
+                range,
+                pattern,
+                replacement,
+                global,
+            } => {
+                let status = self.view.get_status();
+                match range.resolve(status.current_line_idx, status.total_lines) {
+                    Ok(lines) => {
+                        match self.view.substitute(lines, &pattern, &replacement, global) {
+                            0 => self.update_message(&format!("Pattern not found: {pattern}")),
+                            1 => self.update_message("1 line changed"),
+                            changed => self.update_message(&format!("{changed} lines changed")),
+                        }
+                    }
+                    Err(err) => self.update_message(&format!("ERROR: {err}")),
+                }
+            }
+            ExCommand::PlayMacro { name, count } => self.play_macro(name.as_deref(), count),
+            ExCommand::Mark(name) => self.set_mark(name),
+            ExCommand::JumpToMark(name) => self.jump_to_mark(name),
+            ExCommand::DeleteMark(name) => {
+                if !self.view.delete_mark(name) {
+                    self.update_message(&format!("ERROR: Mark {name} is not set"));
+                }
+            }
+            ExCommand::Goto(line) => {
+                let total_lines = self.view.get_status().total_lines;
+                if line > total_lines {
+                    self.update_message(&format!(
+                        "ERROR: Line {line} is past the end of the buffer"
+                    ));
+                } else {
+                    self.view.goto_line(line - 1);
+                }
+            }
+        }
+    }
+
+    /// Runs the commands in the startup script, reporting the first error.
+    fn run_startup_script(&mut self) {
+        let Some(contents) = ex_command::load_startup_script() else {
+            return;
+        };
+        let mut first_error = None;
+        for result in ex_command::parse_script(&contents) {
+            match result {
+                Ok(ex_command) => self.execute_ex_command(ex_command),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        if let Some(err) = first_error {
+            self.update_message(&format!("ERROR in startup script, {err}"));
+        }
+    }
+
+    fn process_command_during_macro_name(&mut self, command: Command) {
+        match command {
+            Move(move_cmd) => self.command_bar.handle_move_command(move_cmd),
+            System(Dismiss) => {
+                self.set_prompt(PromptType::None);
+                self.update_message("Macro recorded. Press Ctrl-Y to play it.");
+            }
+            Edit(InsertNewline) => {
+                let name = self.command_bar.value().trim().to_string();
+                self.set_prompt(PromptType::None);
+                if name.is_empty() {
+                    self.update_message("Macro recorded. Press Ctrl-Y to play it.");
+                } else if name.contains(char::is_whitespace) || name.parse::<usize>().is_ok() {
+                    self.update_message("ERROR: Macro names can't contain spaces or be numbers.");
+                } else {
+                    match self.macros.insert(&name, self.last_macro.clone()) {
+                        Ok(()) => self.update_message(&format!("Macro saved as {name}.")),
+                        Err(err) => {
+                            self.update_message(&format!("ERROR: Could not save macros: {err}"));
+                        }
+                    }
+                }
+            }
+            Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
+            System(_) => {}
+        }
+    }
+
+    fn process_command_during_play_macro(&mut self, command: Command) {
+        match command {
+            Move(move_cmd) => self.command_bar.handle_move_command(move_cmd),
+            System(Dismiss) => self.set_prompt(PromptType::None),
+            Edit(InsertNewline) => {
+                let input = self.command_bar.value();
+                self.set_prompt(PromptType::None);
+                match ex_command::parse_play_arguments(&input) {
+                    Ok((name, count)) => self.play_macro(name.as_deref(), count),
+                    Err(err) => self.update_message(&format!("ERROR: {err}")),
+                }
+            }
+            Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
+            System(_) => {}
+        }
+    }
+
+    fn toggle_macro_recording(&mut self) {
+        match self.recording.take() {
+            None => {
+                self.recording = Some(Vec::new());
+                self.update_message("Recording macro. Press Ctrl-T again to stop.");
+            }
+            Some(commands) if commands.is_empty() => {
+                self.update_message("Macro recording stopped. Nothing was recorded.");
+            }
+            Some(commands) => {
+                self.last_macro = commands;
+                self.set_prompt(PromptType::MacroName);
+            }
+        }
+    }
+
+    /// Replays a macro through `process_command`, so it behaves exactly as if
+    /// its keys were typed again, prompts included.
+    fn play_macro(&mut self, name: Option<&str>, count: usize) {
+        if self.playing_macro {
+            self.update_message("ERROR: Macros cannot play macros");
+            return;
+        }
+        if self.recording.is_some() {
+            self.update_message("ERROR: Cannot play a macro while recording one");
+            return;
+        }
+        let commands = match name {
+            Some(name) => {
+                let Some(commands) = self.macros.get(name) else {
+                    self.update_message(&format!("ERROR: No macro named {name}"));
+                    return;
+                };
+                commands.to_vec()
+            }
+            None if self.last_macro.is_empty() => {
+                self.update_message("ERROR: No macro recorded yet. Press Ctrl-T to record one.");
+                return;
+            }
+            None => self.last_macro.clone(),
+        };
+        self.playing_macro = true;
+        'repeat: for _ in 0..count.min(MAX_MACRO_REPEAT) {
+            for &command in &commands {
+                self.process_command(command);
+                if self.should_quit {
+                    break 'repeat;
+                }
+            }
+        }
+        self.playing_macro = false;
+    }
+
+    fn process_command_during_shell(&mut self, command: Command) {
+        match command {
+            System(
+                Quit | Resize(_) | Search | Save | Shell | Open | Palette | CommandLine
+                | RecordMacro | PlayMacro | JumpBack | JumpForward,
+            ) => {}
+            Move(move_cmd) => self.command_bar.handle_move_command(move_cmd),
+            System(Dismiss) => {
+                self.set_prompt(PromptType::None);
+                self.update_message("Command aborted.");
+            }
+            Edit(InsertNewline) => {
+                let input = self.command_bar.value();
                 self.set_prompt(PromptType::None);
+                self.run_shell_command(&input);
             }
             Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
         }
     }
 
+    fn process_command_during_file_changed(&mut self, command: Command) {
+        match command {
+            Edit(command::Edit::Insert('r' | 'R')) => {
+                self.set_prompt(PromptType::None);
+                self.save_after_change_prompt = false;
+                match self.view.reload() {
+                    Ok(()) => self.update_message("File reloaded from disk."),
+                    Err(err) => {
+                        self.update_message(&format!("ERROR: Could not reload file: {err}"));
+                    }
+                }
+            }
+            Edit(command::Edit::Insert('k' | 'K')) => {
+                self.set_prompt(PromptType::None);
+                self.view.acknowledge_disk_change();
+                if self.save_after_change_prompt {
+                    self.save_after_change_prompt = false;
+                    self.save(None);
+                } else {
+                    self.update_message("Keeping your version; saving will overwrite the file.");
+                }
+            }
+            Edit(command::Edit::Insert('d' | 'D')) => self.show_disk_diff(),
+            System(Dismiss) => {
+                self.set_prompt(PromptType::None);
+                self.view.acknowledge_disk_change();
+                if self.save_after_change_prompt {
+                    self.save_after_change_prompt = false;
+                    self.update_message("Save aborted.");
+                }
+            }
+            System(_) | Edit(_) | Move(_) => {}
+        }
+    }
+
+    fn process_command_during_recover(&mut self, command: Command) {
+        let Some(swap_path) = self.recovery_swap.clone() else {
+            self.set_prompt(PromptType::None);
+            return;
+        };
+        match command {
+            Edit(command::Edit::Insert('r' | 'R')) => {
+                self.set_prompt(PromptType::None);
+                self.recovery_swap = None;
+                match fs::read_to_string(&swap_path) {
+                    Ok(contents) => {
+                        self.view.replace_buffer(strip_trailing_newline(&contents));
+                        self.update_message("Recovered unsaved changes from swap file.");
+                    }
+                    Err(err) => {
+                        self.update_message(&format!("ERROR: Could not read swap file: {err}"));
+                    }
+                }
+            }
+            Edit(command::Edit::Insert('d' | 'D')) | System(Dismiss) => {
+                self.set_prompt(PromptType::None);
+                self.recovery_swap = None;
+                let _ = fs::remove_file(&swap_path);
+                self.update_message("Swap file discarded.");
+            }
+            Edit(command::Edit::Insert('v' | 'V')) => {
+                if let Some(path) = self.view.file_path().map(std::path::Path::to_path_buf) {
+                    self.run_interactive_shell_command(&diff_command(&path, &swap_path));
+                }
+            }
+            System(_) | Edit(_) | Move(_) => {}
+        }
+    }
+
     // =========================================
     // PromptHandling
     // =========================================
     fn set_prompt(&mut self, prompt_type: PromptType) {
+        self.command_bar.clear_value();
+        self.clear_completion();
+        if matches!(self.prompt_type, PromptType::Open | PromptType::Palette) {
+            self.finder_files.clear();
+            self.palette_matches.clear();
+            self.view.mark_redraw(true);
+        }
         match prompt_type {
             PromptType::None => self.message_bar.mark_redraw(true),
-            PromptType::Save => self.command_bar.set_prompt("Save as: "),
+            PromptType::Save => {
+                self.command_bar.set_prompt("Save as: ");
+                self.command_bar.set_history(&self.save_history);
+            }
+            PromptType::Shell => self
+                .command_bar
+                .set_prompt("Run (|pipe <insert ?show !interactive): "),
+            PromptType::FileChanged => self
+                .command_bar
+                .set_prompt("File changed on disk: (r)eload, (k)eep mine, (d)iff? "),
+            PromptType::ConfirmOverwrite => self
+                .command_bar
+                .set_prompt("File already exists. Overwrite? (y/n) "),
+            PromptType::ConfirmCreateDir => {
+                let dir = self
+                    .pending_save_path
+                    .as_deref()
+                    .and_then(std::path::Path::parent)
+                    .map(|dir| dir.display().to_string())
+                    .unwrap_or_default();
+                self.command_bar.set_prompt(&format!(
+                    "Directory {dir} does not exist. Create it? (y/n) "
+                ));
+            }
+            PromptType::Recover => self
+                .command_bar
+                .set_prompt("Unsaved changes found: (r)ecover, (d)iscard, (v)iew diff? "),
+            PromptType::Open => {
+                self.command_bar
+                    .set_prompt("Open (Up/Down: select, Tab: complete path): ");
+                self.finder_files = env::current_dir()
+                    .map(|dir| file_walker::walk_files(&dir, MAX_FINDER_FILES))
+                    .unwrap_or_default();
+                self.update_finder();
+            }
+            PromptType::Palette => {
+                self.command_bar.set_prompt("Command: ");
+                if self.palette_entries.is_empty() {
+                    self.palette_entries = palette::entries();
+                }
+                self.update_palette();
+            }
+            PromptType::MacroName => self
+                .command_bar
+                .set_prompt("Name this macro (Enter to keep it unnamed): "),
+            PromptType::PlayMacro => {
+                let names = self.macros.names().join(", ");
+                let prompt = if names.is_empty() {
+                    "Play macro ([count], Enter = last): ".to_string()
+                } else {
+                    format!("Play macro ([name] [count], Enter = last; {names}): ")
+                };
+                self.command_bar.set_prompt(&prompt);
+            }
+            PromptType::CommandLine => {
+                self.command_bar.set_prompt(":");
+                self.command_bar.set_history(&self.command_history);
+            }
             PromptType::Search => {
                 self.view.enter_search();
                 self.command_bar
-                    .set_prompt("Search (Esc to cancel, Arrows to navigate): ");
+                    .set_prompt("Search (Ctrl-Up/Down: prev/next, Up/Down: history): ");
+                self.command_bar.set_history(&self.search_history);
             }
         }
-        self.command_bar.clear_value();
         self.prompt_type = prompt_type;
     }
 
@@ -225,27 +1137,257 @@ impl Editor {
         !self.prompt_type.is_none()
     }
 
+    fn complete_prompt_path(&mut self) {
+        if let Some(completion) = &mut self.path_completion {
+            if let Some(candidate) = completion.next() {
+                self.command_bar.set_value(candidate);
+            }
+        } else {
+            let value = self.command_bar.value();
+            let completion = PathCompletion::new(&value);
+            match completion.candidates() {
+                [] => self.command_bar.set_status("no matches"),
+                [candidate] => self.command_bar.set_value(candidate),
+                _ => {
+                    let prefix = completion.common_prefix();
+                    if prefix.len() > value.len() {
+                        self.command_bar.set_value(&prefix);
+                    }
+                    self.path_completion = Some(completion);
+                }
+            }
+        }
+
+        if let Some(completion) = &self.path_completion {
+            self.completion_list
+                .set_items(completion.candidates(), completion.selected());
+        }
+    }
+
+    fn clear_completion(&mut self) {
+        self.path_completion = None;
+        self.command_bar.set_status("");
+        if self.completion_list.is_visible() {
+            self.completion_list.clear();
+            self.status_bar.mark_redraw(true);
+        }
+    }
+
     // =========================================
     // SystemCommands
     // =========================================
     fn handle_save(&mut self) {
         if self.view.is_file_loaded() {
+            if self.view.is_changed_on_disk() {
+                self.save_after_change_prompt = true;
+                self.set_prompt(PromptType::FileChanged);
+                return;
+            }
             self.save(None);
         } else {
             self.set_prompt(PromptType::Save);
         }
     }
 
+    /// Saves under a new name, asking before overwriting a different existing file.
+    fn confirm_save_as(&mut self, file_name: &str) {
+        if file_name.trim().is_empty() {
+            self.update_message("Save aborted.");
+            return;
+        }
+        let path = expand_tilde(file_name);
+        let is_current_file = self
+            .view
+            .file_path()
+            .is_some_and(|current| current.canonicalize().ok() == path.canonicalize().ok());
+        if path.exists() && !is_current_file {
+            self.pending_save_path = Some(path);
+            self.set_prompt(PromptType::ConfirmOverwrite);
+        } else {
+            self.save_to_path(&path);
+        }
+    }
+
+    /// Saves to `path`, asking first when its directory does not exist yet.
+    fn save_to_path(&mut self, path: &std::path::Path) {
+        if path
+            .parent()
+            .is_some_and(|parent| !parent.as_os_str().is_empty() && !parent.exists())
+        {
+            self.pending_save_path = Some(path.to_path_buf());
+            self.set_prompt(PromptType::ConfirmCreateDir);
+            return;
+        }
+        self.save(Some(&path.to_string_lossy()));
+    }
+
+    fn create_dir_and_save(&mut self, path: &std::path::Path) {
+        if let Some(parent) = path.parent()
+            && let Err(err) = fs::create_dir_all(parent)
+        {
+            self.update_message(&format!(
+                "ERROR: Could not create directory {}: {err}",
+                parent.display()
+            ));
+            return;
+        }
+        self.save(Some(&path.to_string_lossy()));
+    }
+
     fn save(&mut self, file_name: Option<&str>) {
+        let format_error = self.format_buffer(file_name).err();
+        let previous_path = self.view.file_path().map(std::path::Path::to_path_buf);
         let result = if let Some(name) = file_name {
             self.view.save_as(name)
         } else {
             self.view.save()
         };
-        if result.is_ok() {
-            self.update_message("File saved successfully.");
+        if result.is_ok()
+            && let Some(settings) = &self.swap_settings
+        {
+            settings.remove(previous_path.as_deref());
+            settings.remove(self.view.file_path());
+        }
+        self.refresh_crash_snapshot();
+        match (result, format_error) {
+            (Ok(()), None) => self.update_message("File saved successfully."),
+            (Ok(()), Some(err)) => {
+                self.update_message(&format!("File saved, but formatter failed: {err}"));
+            }
+            (Err(_), _) => self.update_message("Error writing file!"),
+        }
+    }
+
+    /// Runs the language's formatter over the buffer. The buffer is only
+    /// replaced when the formatter succeeds; failures never block the save.
+    fn format_buffer(&mut self, file_name: Option<&str>) -> Result<(), String> {
+        let Some(formatter) = self.view.formatter_command(file_name) else {
+            return Ok(());
+        };
+        let shell_command = ShellCommand {
+            mode: ShellMode::Pipe,
+            command: formatter,
+        };
+        let text = self.view.buffer_text();
+        let output = shell_command
+            .run(Some(&text))
+            .map_err(|err| format!("{}: {err}", shell_command.command))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} ({})",
+                summarize_output(&output.stderr),
+                describe_status(output.status)
+            ));
+        }
+        if output.stdout.is_empty() && !text.is_empty() {
+            return Err(format!("{} produced no output", shell_command.command));
+        }
+        if output.stdout != text {
+            self.view
+                .replace_buffer(strip_trailing_newline(&output.stdout));
+        }
+        Ok(())
+    }
+
+    fn run_shell_command(&mut self, input: &str) {
+        let Some(shell_command) = ShellCommand::parse(input) else {
+            self.update_message("No command given.");
+            return;
+        };
+
+        if shell_command.mode == ShellMode::Interactive {
+            self.run_interactive_shell_command(&shell_command);
+            return;
+        }
+
+        let stdin_text =
+            (shell_command.mode == ShellMode::Pipe).then(|| self.view.selection_or_buffer_text());
+        let output = match shell_command.run(stdin_text.as_deref()) {
+            Ok(output) => output,
+            Err(err) => {
+                self.update_message(&format!("ERROR: Could not run command: {err}"));
+                return;
+            }
+        };
+
+        if !output.status.success() {
+            self.update_message(&format!(
+                "Command failed ({}): {}",
+                describe_status(output.status),
+                summarize_output(&output.stderr)
+            ));
+            return;
+        }
+
+        let stdout = strip_trailing_newline(&output.stdout);
+        match shell_command.mode {
+            ShellMode::Pipe => self.view.replace_selection_or_buffer(stdout),
+            ShellMode::Insert => self.view.insert_text(stdout),
+            ShellMode::Show | ShellMode::Interactive => {}
+        }
+
+        let message = if shell_command.mode == ShellMode::Show {
+            summarize_output(&output.stdout)
+        } else if output.stderr.trim().is_empty() {
+            format!("Ran: {}", shell_command.command)
+        } else {
+            summarize_output(&output.stderr)
+        };
+        self.update_message(&message);
+    }
+
+    fn run_interactive_shell_command(&mut self, shell_command: &ShellCommand) {
+        let _ = Terminal::terminate();
+        let result = shell_command.run_interactive();
+        let _ = Terminal::initialize();
+        self.resize(Terminal::size().unwrap_or_default());
+        match result {
+            Ok(status) => self.update_message(&format!(
+                "{} ({})",
+                shell_command.command,
+                describe_status(status)
+            )),
+            Err(err) => self.update_message(&format!("ERROR: Could not run command: {err}")),
+        }
+    }
+
+    fn show_disk_diff(&mut self) {
+        let Some(path) = self.view.file_path().map(std::path::Path::to_path_buf) else {
+            return;
+        };
+        let file_name = path.file_name().map_or_else(
+            || "buffer".to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let temp_path = env::temp_dir().join(format!("{NAME}-{}-{file_name}", process::id()));
+        if let Err(err) = fs::write(&temp_path, self.view.buffer_text()) {
+            self.update_message(&format!("ERROR: Could not write temporary file: {err}"));
+            return;
+        }
+        self.run_interactive_shell_command(&diff_command(&path, &temp_path));
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    fn open_file(&mut self, path: &std::path::Path, force: bool) {
+        if self.view.get_status().is_modified && !force {
+            self.update_message(
+                "WARNING! File has unsaved changes. Save before opening another file.",
+            );
+            return;
+        }
+        if let Some(session) = &mut self.session
+            && let Some(current) = self.view.file_path()
+        {
+            session.remember_file_state(current, self.view.file_state());
+        }
+        let file_name = path.to_string_lossy();
+        if self.view.load(&file_name).is_ok() {
+            self.restore_file_state();
+            self.recovery_swap = None;
+            self.offer_swap_recovery();
+            self.update_message(&format!("Opened {file_name}"));
         } else {
-            self.update_message("Error writing file!");
+            self.update_message(&format!("ERROR: Could not open file: {file_name}"));
         }
     }
 
@@ -269,11 +1411,132 @@ impl Editor {
         }
     }
 
+    // =========================================
+    // Session
+    // =========================================
+    fn restore_file_state(&mut self) {
+        let state = self.session.as_ref().and_then(|session| {
+            self.view
+                .file_path()
+                .and_then(|path| session.file_state(path))
+        });
+        if let Some(state) = state {
+            self.view.restore_file_state(state);
+        }
+    }
+
+    fn save_session(&mut self) {
+        let Some(session) = &mut self.session else {
+            return;
+        };
+        session.set_histories(&self.search_history, &self.save_history);
+        session.open_buffers.clear();
+        if let Some(path) = self.view.file_path() {
+            session.remember_file_state(path, self.view.file_state());
+            session.open_buffers.push(Session::file_key(path));
+        }
+        let _ = session.save();
+    }
+
+    // =========================================
+    // PeriodicTasks
+    // =========================================
+    fn run_periodic_tasks(&mut self) {
+        let now = Instant::now();
+        if let Some(interval) = self
+            .swap_settings
+            .as_ref()
+            .map(|settings| settings.interval)
+            && self
+                .last_swap_write
+                .is_none_or(|last| now.duration_since(last) >= interval)
+        {
+            self.last_swap_write = Some(now);
+            self.write_swap_file();
+        }
+        if self.swap_settings.is_some()
+            && self
+                .last_crash_snapshot
+                .is_none_or(|last| now.duration_since(last) >= CRASH_SNAPSHOT_INTERVAL)
+        {
+            self.last_crash_snapshot = Some(now);
+            self.refresh_crash_snapshot();
+        }
+        if self
+            .last_disk_check
+            .is_none_or(|last| now.duration_since(last) >= DISK_CHECK_INTERVAL)
+        {
+            self.last_disk_check = Some(now);
+            self.check_external_changes();
+        }
+    }
+
+    fn write_swap_file(&mut self) {
+        let Some(settings) = &self.swap_settings else {
+            return;
+        };
+        let version = self.view.edit_version();
+        if !self.view.get_status().is_modified || self.swap_file_version == Some(version) {
+            return;
+        }
+        match settings.write(self.view.file_path(), &self.view.buffer_text()) {
+            Ok(()) => self.swap_file_version = Some(version),
+            Err(err) => {
+                self.update_message(&format!("ERROR: Could not write swap file: {err}"));
+            }
+        }
+    }
+
+    /// Hands the panic hook the buffer as it is now, or nothing once it is
+    /// saved. The buffer is only copied again after it was edited.
+    fn refresh_crash_snapshot(&mut self) {
+        let version = (self.swap_settings.is_some() && self.view.get_status().is_modified)
+            .then(|| self.view.edit_version());
+        if version.is_some() && version == self.crash_snapshot_version {
+            return;
+        }
+        self.crash_snapshot_version = version;
+        let snapshot = match (&self.swap_settings, version) {
+            (Some(settings), Some(_)) => Some((
+                settings.swap_path_for(self.view.file_path()),
+                self.view.buffer_text(),
+            )),
+            _ => None,
+        };
+        swap_file::set_crash_snapshot(snapshot);
+    }
+
+    fn offer_swap_recovery(&mut self) {
+        let swap_path = self.swap_settings.as_ref().and_then(|settings| {
+            self.view
+                .file_path()
+                .and_then(|path| settings.find_newer_swap(path))
+        });
+        if let Some(swap_path) = swap_path {
+            self.recovery_swap = Some(swap_path);
+            self.set_prompt(PromptType::Recover);
+        }
+    }
+
+    fn check_external_changes(&mut self) {
+        if self.in_prompt() || !self.view.is_changed_on_disk() {
+            return;
+        }
+        if self.view.get_status().is_modified {
+            self.save_after_change_prompt = false;
+            self.set_prompt(PromptType::FileChanged);
+        } else if self.view.reload().is_ok() {
+            self.update_message("File changed on disk and was reloaded.");
+        }
+    }
+
     // =========================================
     // Rendering
     // =========================================
     pub fn refresh_status(&mut self) {
-        let status = self.view.get_status();
+        let mut status = self.view.get_status();
+        status.mode = self.modal.as_ref().map(|modal| modal.mode().name());
+        status.is_recording = self.recording.is_some();
         let title = format!("{} - {NAME}", status.file_name);
         self.status_bar.update_status(status);
 
@@ -284,6 +1547,7 @@ impl Editor {
 
     fn resize(&mut self, size: Size) {
         self.terminal_size = size;
+        Terminal::resize(size);
         self.view.resize(Size {
             height: size.height.saturating_sub(2),
             width: size.width,
@@ -295,6 +1559,11 @@ impl Editor {
         self.message_bar.resize(bar_size);
         self.status_bar.resize(bar_size);
         self.command_bar.resize(bar_size);
+        self.completion_list.resize(bar_size);
+        self.finder.resize(Size {
+            height: size.height.saturating_sub(2),
+            width: size.width,
+        });
     }
 
     fn refresh_screen(&mut self) {
@@ -302,18 +1571,26 @@ impl Editor {
             return;
         }
         let bottom_bar_row = self.terminal_size.height.saturating_sub(1);
-        let _ = Terminal::hide_caret();
+        Terminal::hide_caret();
         if self.in_prompt() {
             self.command_bar.render(bottom_bar_row);
         } else {
             self.message_bar.render(bottom_bar_row);
         }
         if self.terminal_size.height > 1 {
-            self.status_bar
-                .render(self.terminal_size.height.saturating_sub(2));
+            let status_row = self.terminal_size.height.saturating_sub(2);
+            if self.completion_list.is_visible() {
+                self.completion_list.render(status_row);
+            } else {
+                self.status_bar.render(status_row);
+            }
         }
         if self.terminal_size.height > 2 {
-            self.view.render(0);
+            if matches!(self.prompt_type, PromptType::Open | PromptType::Palette) {
+                self.finder.render(0);
+            } else {
+                self.view.render(0);
+            }
         }
 
         let new_caret_pos = if self.in_prompt() {
@@ -325,8 +1602,8 @@ impl Editor {
             self.view.caret_position()
         };
 
-        let _ = Terminal::move_caret_to(new_caret_pos);
-        let _ = Terminal::show_caret();
+        Terminal::move_caret_to(new_caret_pos);
+        Terminal::show_caret();
         let _ = Terminal::execute();
     }
 
@@ -338,8 +1615,27 @@ impl Editor {
     }
 }
 
+/// Appends `entry` to a prompt history, moving it to the end if it was
+/// already present.
+fn push_history(history: &mut Vec<String>, entry: &str) {
+    if entry.is_empty() {
+        return;
+    }
+    history.retain(|existing| existing != entry);
+    history.push(entry.to_string());
+}
+
 impl Drop for Editor {
     fn drop(&mut self) {
+        // On a panic the hook has already written the crash swap file.
+        if !thread::panicking() {
+            if let Some(settings) = &self.swap_settings
+                && self.recovery_swap.is_none()
+            {
+                settings.remove(self.view.file_path());
+            }
+            self.save_session();
+        }
         let _ = Terminal::terminate();
     }
 }
@@ -8,9 +8,32 @@ pub enum AnnotationType {
     PrimitiveType,
     String,
     Comment,
+    /// Names of called or defined functions.
+    Function,
+    Macro,
+    Attribute,
+    Lifetime,
+    /// `SCREAMING_CASE` names.
+    Constant,
+    Operator,
+    /// Escape sequences inside strings.
+    Escape,
+    DocComment,
+    /// TODO, FIXME and XXX inside comments.
+    Todo,
     Bracket0,
     Bracket1,
     Bracket2,
     Bracket3,
     Selection,
+    Mark,
+    Fold,
+    /// Spaces, tabs and indent guides drawn as glyphs.
+    Whitespace,
+    /// Trailing whitespace and indentation mixing tabs and spaces.
+    WhitespaceError,
+    /// The columns left of the text, behind any mark.
+    Gutter,
+    StatusBar,
+    MessageBar,
 }
@@ -33,6 +33,12 @@ impl AnnotatedString {
         });
     }
 
+    pub fn push_annotated(&mut self, string: &str, annotation_type: AnnotationType) {
+        let start = self.string.len();
+        self.string.push_str(string);
+        self.add_annotation(annotation_type, start, self.string.len());
+    }
+
     pub fn truncate_left_until(&mut self, until: usize) {
         self.replace(0, until, "");
     }
@@ -67,7 +73,7 @@ impl AnnotatedString {
                 } else {
                     annotation.start.saturating_add(len_difference)
                 }
-            } else if annotation.start >= start {
+            } else if annotation.start > start {
                 if shortened {
                     max(start, annotation.start.saturating_sub(len_difference))
                 } else {
@@ -83,7 +89,7 @@ impl AnnotatedString {
                 } else {
                     annotation.end.saturating_add(len_difference)
                 }
-            } else if annotation.end >= start {
+            } else if annotation.end > start {
                 if shortened {
                     max(start, annotation.end.saturating_sub(len_difference))
                 } else {
@@ -2,14 +2,16 @@ use crossterm::event::{
     KeyCode::{Backspace, Char, Delete, Enter, Tab},
     KeyEvent, KeyModifiers,
 };
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Edit {
     Insert(char),
     InsertNewline,
     Backspace,
     Delete,
+    DeleteWord,
     Copy,
     Cut,
     Paste,
@@ -25,6 +27,10 @@ impl TryFrom<KeyEvent> for Edit {
             (Char('x'), m) if m == KeyModifiers::CONTROL => Ok(Self::Cut),
             (Char('v'), m) if m == KeyModifiers::CONTROL => Ok(Self::Paste),
             (Char('a'), m) if m == KeyModifiers::CONTROL => Ok(Self::SelectAll),
+            (Char('w'), m) if m == KeyModifiers::CONTROL => Ok(Self::DeleteWord),
+            (Backspace, m) if m == KeyModifiers::CONTROL || m == KeyModifiers::ALT => {
+                Ok(Self::DeleteWord)
+            }
             (Char(character), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
                 Ok(Self::Insert(character))
             }
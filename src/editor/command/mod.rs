@@ -1,4 +1,5 @@
 use crossterm::event::Event;
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 
 mod move_command;
@@ -10,7 +11,7 @@ pub use edit::Edit;
 
 use super::size::Size;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Command {
     Move(Move),
     Edit(Edit),
@@ -1,15 +1,16 @@
 use crossterm::event::{
-    KeyCode::{Down, Left, Right, Up},
+    KeyCode::{Down, End, Home, Left, Right, Up},
     KeyEvent, KeyModifiers,
 };
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Move {
     pub direction: MoveDirection,
     pub is_selection: bool,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum MoveDirection {
     PageUp,
     PageDown,
@@ -47,8 +48,11 @@ impl TryFrom<KeyEvent> for Move {
         let direction = match (code, modifiers) {
             (Up, KeyModifiers::CONTROL) => MoveDirection::PageUp,
             (Down, KeyModifiers::CONTROL) => MoveDirection::PageDown,
-            (Left, KeyModifiers::CONTROL) => MoveDirection::LineStart,
-            (Right, KeyModifiers::CONTROL) => MoveDirection::LineEnd,
+            (Left, KeyModifiers::CONTROL) | (Home, _) => MoveDirection::LineStart,
+            (Right, KeyModifiers::CONTROL) | (End, _) => MoveDirection::LineEnd,
+            (Left | Right, KeyModifiers::ALT) => {
+                return Err(format!("Key Code not supported: ALT+{code:?}"));
+            }
             (Up, _) => MoveDirection::Up,
             (Down, _) => MoveDirection::Down,
             (Left, _) => MoveDirection::Left,
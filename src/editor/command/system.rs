@@ -1,16 +1,25 @@
 use super::super::Size;
 use crossterm::event::{
-    KeyCode::{Char, Esc},
+    KeyCode::{Char, Esc, Left, Right},
     KeyEvent, KeyModifiers,
 };
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum System {
     Save,
     Resize(Size),
     Quit,
     Dismiss,
     Search,
+    Shell,
+    Open,
+    Palette,
+    CommandLine,
+    RecordMacro,
+    PlayMacro,
+    JumpBack,
+    JumpForward,
 }
 
 impl TryFrom<KeyEvent> for System {
@@ -25,8 +34,20 @@ impl TryFrom<KeyEvent> for System {
                 Char('q') => Ok(Self::Quit),
                 Char('s') => Ok(Self::Save),
                 Char('f') => Ok(Self::Search),
+                Char('r') => Ok(Self::Shell),
+                Char('o') => Ok(Self::Open),
+                Char('p') => Ok(Self::Palette),
+                Char('e') => Ok(Self::CommandLine),
+                Char('t') => Ok(Self::RecordMacro),
+                Char('y') => Ok(Self::PlayMacro),
                 _ => Err(format!("Unsupported CONTROL+{code:?} combination")),
             }
+        } else if modifiers == KeyModifiers::ALT && matches!(code, Left | Right) {
+            Ok(if code == Left {
+                Self::JumpBack
+            } else {
+                Self::JumpForward
+            })
         } else if modifiers == KeyModifiers::NONE && matches!(code, Esc) {
             Ok(Self::Dismiss)
         } else {
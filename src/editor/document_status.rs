@@ -5,6 +5,8 @@ pub struct DocumentStatus {
     pub is_modified: bool,
     pub file_name: String,
     pub language_name: Option<String>,
+    pub mode: Option<&'static str>,
+    pub is_recording: bool,
 }
 
 impl PartialEq for DocumentStatus {
@@ -14,6 +16,8 @@ impl PartialEq for DocumentStatus {
             && self.is_modified == other.is_modified
             && self.file_name == other.file_name
             && self.language_name == other.language_name
+            && self.mode == other.mode
+            && self.is_recording == other.is_recording
     }
 }
 
@@ -0,0 +1,415 @@
+use super::{folding::FoldCommand, highlight::config_file::get_config_dir, line::MAX_TAB_WIDTH};
+use std::{fs, ops::RangeInclusive};
+
+const STARTUP_SCRIPT_NAME: &str = "init.den";
+
+/// A line reference in a range, as typed: `12`, `.` (current line) or `$`
+/// (last line).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineAddress {
+    Number(usize),
+    Current,
+    Last,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineRange {
+    /// `%`: every line of the buffer.
+    All,
+    Lines(LineAddress, LineAddress),
+}
+
+impl LineRange {
+    /// Resolves the range to 0-based line indices for a buffer with
+    /// `line_count` lines and the caret on `current_line`.
+    pub fn resolve(
+        self,
+        current_line: usize,
+        line_count: usize,
+    ) -> Result<RangeInclusive<usize>, String> {
+        let last = line_count.saturating_sub(1);
+        let resolve_address = |address| match address {
+            LineAddress::Number(0) => Err("Line numbers start at 1".to_string()),
+            LineAddress::Number(number) if number > line_count => {
+                Err(format!("Line {number} is past the end of the buffer"))
+            }
+            LineAddress::Number(number) => Ok(number - 1),
+            LineAddress::Current => Ok(current_line),
+            LineAddress::Last => Ok(last),
+        };
+        match self {
+            Self::All => Ok(0..=last),
+            Self::Lines(start, end) => {
+                let (start, end) = (resolve_address(start)?, resolve_address(end)?);
+                if start > end {
+                    Err("Backwards range".to_string())
+                } else {
+                    Ok(start..=end)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Setting {
+    TabWidth(usize),
+    Modal(bool),
+    /// Closes folds nested deeper than this level.
+    FoldLevel(usize),
+    Whitespace(bool),
+    IndentGuides(bool),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ExCommand {
+    /// `w` saves, `w name` saves under a new name.
+    Write(Option<String>),
+    /// `wq` / `x`
+    WriteQuit,
+    /// `q`, or `q!` to discard unsaved changes.
+    Quit {
+        force: bool,
+    },
+    /// `e file`, or `e! file` to discard unsaved changes.
+    Edit {
+        file_name: String,
+        force: bool,
+    },
+    Set(Setting),
+    /// `[range]s/pattern/replacement/[g]`. Without a range only the current
+    /// line is changed. Patterns are matched literally.
+    Substitute {
+        range: LineRange,
+        pattern: String,
+        replacement: String,
+        global: bool,
+    },
+    /// `goto N` or a bare `N`, 1-based.
+    Goto(usize),
+    /// `play [name] [count]`. Without a name the last recorded macro is played.
+    PlayMacro {
+        name: Option<String>,
+        count: usize,
+    },
+    /// `mark x` or `k x` sets mark `x` at the caret.
+    Mark(char),
+    /// `'x`
+    JumpToMark(char),
+    /// `delmarks x` / `delm x`
+    DeleteMark(char),
+    /// `fold` and `unfold` act on the fold at the caret, `fold all` and
+    /// `unfold all` on every fold.
+    Fold(FoldCommand),
+    /// `theme name` / `colorscheme name` switches themes, a bare `theme`
+    /// lists them.
+    Theme(Option<String>),
+    /// `language name` / `setf name` sets the buffer's language, a bare
+    /// `language` shows it and lists the others.
+    Language(Option<String>),
+}
+
+impl ExCommand {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim().trim_start_matches(':').trim_start();
+        if input.is_empty() {
+            return Err("Empty command".to_string());
+        }
+
+        let (range, rest) = parse_range(input)?;
+        if let Some(range) = range {
+            return match rest.strip_prefix('s') {
+                Some(substitution) => parse_substitution(range, substitution),
+                None if rest.is_empty() => match range {
+                    LineRange::Lines(LineAddress::Number(line), end)
+                        if line > 0 && end == LineAddress::Number(line) =>
+                    {
+                        Ok(Self::Goto(line))
+                    }
+                    _ => Err(format!("Not a line number: {input}")),
+                },
+                None => Err(format!("Only substitution takes a range: {input}")),
+            };
+        }
+
+        if let Some(name) = input.strip_prefix('\'') {
+            return parse_mark_name(name).map(Self::JumpToMark);
+        }
+
+        if let Some(substitution) = input.strip_prefix('s')
+            && substitution.starts_with(|ch: char| !ch.is_alphanumeric() && !ch.is_whitespace())
+        {
+            let current_line = LineRange::Lines(LineAddress::Current, LineAddress::Current);
+            return parse_substitution(current_line, substitution);
+        }
+
+        let (name, argument) = input
+            .split_once(char::is_whitespace)
+            .map_or((input, ""), |(name, argument)| (name, argument.trim()));
+        let argument = (!argument.is_empty()).then(|| argument.to_string());
+
+        match (name, argument) {
+            ("w" | "write", file_name) => Ok(Self::Write(file_name)),
+            ("wq" | "x", None) => Ok(Self::WriteQuit),
+            ("q" | "quit", None) => Ok(Self::Quit { force: false }),
+            ("q!" | "quit!", None) => Ok(Self::Quit { force: true }),
+            ("e" | "edit", Some(file_name)) => Ok(Self::Edit {
+                file_name,
+                force: false,
+            }),
+            ("e!" | "edit!", Some(file_name)) => Ok(Self::Edit {
+                file_name,
+                force: true,
+            }),
+            ("e" | "edit" | "e!" | "edit!", None) => Err("Missing file name".to_string()),
+            ("set", Some(setting)) => parse_setting(&setting).map(Self::Set),
+            ("set", None) => Err("Missing setting".to_string()),
+            ("goto", Some(line)) => line
+                .parse()
+                .ok()
+                .filter(|line| *line > 0)
+                .map(Self::Goto)
+                .ok_or_else(|| format!("Not a line number: {line}")),
+            ("goto", None) => Err("Missing line number".to_string()),
+            ("mark" | "k", Some(name)) => parse_mark_name(&name).map(Self::Mark),
+            ("delmarks" | "delm", Some(name)) => parse_mark_name(&name).map(Self::DeleteMark),
+            ("mark" | "k" | "delmarks" | "delm", None) => Err("Missing mark name".to_string()),
+            ("fold" | "foldclose", None) => Ok(Self::Fold(FoldCommand::Close)),
+            ("unfold" | "foldopen", None) => Ok(Self::Fold(FoldCommand::Open)),
+            ("fold" | "foldclose", Some(all)) if all == "all" => {
+                Ok(Self::Fold(FoldCommand::CloseAll))
+            }
+            ("unfold" | "foldopen", Some(all)) if all == "all" => {
+                Ok(Self::Fold(FoldCommand::OpenAll))
+            }
+            ("theme" | "colorscheme" | "colo", name) => Ok(Self::Theme(name)),
+            ("language" | "lang" | "setf" | "setfiletype", name) => Ok(Self::Language(name)),
+            ("play", arguments) => {
+                let (name, count) = parse_play_arguments(arguments.as_deref().unwrap_or(""))?;
+                Ok(Self::PlayMacro { name, count })
+            }
+            (name @ ("wq" | "x" | "q" | "quit" | "q!" | "quit!"), Some(_)) => {
+                Err(format!("Trailing characters after {name}"))
+            }
+            (name, _) => Err(format!("Not an editor command: {name}")),
+        }
+    }
+}
+
+/// Parses every command in a script, one per line. Blank lines and lines
+/// starting with `#` or `"` are skipped. Errors carry their 1-based line number.
+pub fn parse_script(contents: &str) -> Vec<Result<ExCommand, String>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#') && !line.starts_with('"')
+        })
+        .map(|(idx, line)| ExCommand::parse(line).map_err(|err| format!("line {}: {err}", idx + 1)))
+        .collect()
+}
+
+/// Parses the `[name] [count]` arguments for playing a macro.
+pub fn parse_play_arguments(input: &str) -> Result<(Option<String>, usize), String> {
+    let mut words: Vec<&str> = input.split_whitespace().collect();
+    let count = match words.last().map(|word| word.parse::<usize>()) {
+        Some(Ok(0)) => return Err("Count must be at least 1".to_string()),
+        Some(Ok(count)) => {
+            words.pop();
+            count
+        }
+        Some(Err(_)) | None => 1,
+    };
+    match words.as_slice() {
+        [] => Ok((None, count)),
+        [name] => Ok((Some((*name).to_string()), count)),
+        _ => Err(format!("Expected [name] [count]: {input}")),
+    }
+}
+
+fn parse_mark_name(input: &str) -> Result<char, String> {
+    let mut chars = input.trim().chars();
+    match (chars.next(), chars.next()) {
+        (Some(name), None) if name.is_ascii_alphabetic() => Ok(name),
+        _ => Err(format!("Mark names are single letters: {input}")),
+    }
+}
+
+/// Reads `init.den` from the config directory, if there is one.
+pub fn load_startup_script() -> Option<String> {
+    let path = get_config_dir().ok()?.join(STARTUP_SCRIPT_NAME);
+    fs::read_to_string(path).ok()
+}
+
+fn parse_address(input: &str) -> Result<(Option<LineAddress>, &str), String> {
+    if let Some(rest) = input.strip_prefix('.') {
+        return Ok((Some(LineAddress::Current), rest));
+    }
+    if let Some(rest) = input.strip_prefix('$') {
+        return Ok((Some(LineAddress::Last), rest));
+    }
+    let digits_end = input
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(input.len());
+    if digits_end == 0 {
+        return Ok((None, input));
+    }
+    let number = input[..digits_end]
+        .parse()
+        .map_err(|_| format!("Line number too large: {}", &input[..digits_end]))?;
+    Ok((Some(LineAddress::Number(number)), &input[digits_end..]))
+}
+
+fn parse_range(input: &str) -> Result<(Option<LineRange>, &str), String> {
+    if let Some(rest) = input.strip_prefix('%') {
+        return Ok((Some(LineRange::All), rest));
+    }
+    let (Some(start), rest) = parse_address(input)? else {
+        return Ok((None, input));
+    };
+    let Some(rest) = rest.strip_prefix(',') else {
+        return Ok((Some(LineRange::Lines(start, start)), rest));
+    };
+    match parse_address(rest)? {
+        (Some(end), rest) => Ok((Some(LineRange::Lines(start, end)), rest)),
+        (None, _) => Err(format!("Missing end of range: {input}")),
+    }
+}
+
+/// Splits `/pattern/replacement/flags` on its delimiter, which is whatever
+/// character follows the `s`. A backslash escapes the delimiter.
+fn parse_substitution(range: LineRange, input: &str) -> Result<ExCommand, String> {
+    let mut chars = input.chars();
+    let delimiter = chars
+        .next()
+        .filter(|ch| !ch.is_alphanumeric() && !ch.is_whitespace() && *ch != '\\')
+        .ok_or_else(|| "Substitution needs a delimiter, e.g. s/foo/bar/".to_string())?;
+
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => current.push(next),
+                Some(next) => {
+                    current.push('\\');
+                    current.push(next);
+                }
+                None => return Err("Trailing backslash in substitution".to_string()),
+            },
+            ch if ch == delimiter && parts.len() < 2 => parts.push(std::mem::take(&mut current)),
+            ch => current.push(ch),
+        }
+    }
+    parts.push(current);
+
+    let mut parts = parts.into_iter();
+    let (Some(pattern), Some(replacement)) = (parts.next(), parts.next()) else {
+        return Err("Substitution needs a replacement, e.g. s/foo/bar/".to_string());
+    };
+    let flags = parts.next().unwrap_or_default();
+    if pattern.is_empty() {
+        return Err("Empty search pattern".to_string());
+    }
+    let global = match flags.trim() {
+        "" => false,
+        "g" => true,
+        other => return Err(format!("Unknown substitution flags: {other}")),
+    };
+    Ok(ExCommand::Substitute {
+        range,
+        pattern,
+        replacement,
+        global,
+    })
+}
+
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value.trim() {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        other => Err(format!("Expected on or off: {other}")),
+    }
+}
+
+fn parse_setting(input: &str) -> Result<Setting, String> {
+    let (name, value) = input
+        .split_once('=')
+        .ok_or_else(|| format!("Expected name=value: {input}"))?;
+    match name.trim() {
+        "tabwidth" | "ts" => value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|width| (1..=MAX_TAB_WIDTH).contains(width))
+            .map(Setting::TabWidth)
+            .ok_or_else(|| format!("Invalid tab width: {value}")),
+        "modal" => parse_switch(value).map(Setting::Modal),
+        "whitespace" | "list" => parse_switch(value).map(Setting::Whitespace),
+        "indentguides" => parse_switch(value).map(Setting::IndentGuides),
+        "foldlevel" | "fdl" => value
+            .trim()
+            .parse()
+            .map(Setting::FoldLevel)
+            .map_err(|_| format!("Invalid fold level: {value}")),
+        other => Err(format!("Unknown setting: {other}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_and_ranges() {
+        assert_eq!(ExCommand::parse("w"), Ok(ExCommand::Write(None)));
+        assert_eq!(
+            ExCommand::parse(":w notes.txt"),
+            Ok(ExCommand::Write(Some("notes.txt".to_string())))
+        );
+        assert_eq!(ExCommand::parse("q!"), Ok(ExCommand::Quit { force: true }));
+        assert_eq!(ExCommand::parse("42"), Ok(ExCommand::Goto(42)));
+        assert_eq!(ExCommand::parse("goto 7"), Ok(ExCommand::Goto(7)));
+        assert_eq!(ExCommand::parse("k a"), Ok(ExCommand::Mark('a')));
+        assert_eq!(ExCommand::parse("'a"), Ok(ExCommand::JumpToMark('a')));
+        assert!(ExCommand::parse("mark ab").is_err());
+        assert_eq!(
+            ExCommand::parse("set tabwidth=4"),
+            Ok(ExCommand::Set(Setting::TabWidth(4)))
+        );
+        assert_eq!(
+            ExCommand::parse("10,20s/foo/b\\/ar/g"),
+            Ok(ExCommand::Substitute {
+                range: LineRange::Lines(LineAddress::Number(10), LineAddress::Number(20)),
+                pattern: "foo".to_string(),
+                replacement: "b/ar".to_string(),
+                global: true,
+            })
+        );
+        assert_eq!(ExCommand::parse("theme"), Ok(ExCommand::Theme(None)));
+        assert_eq!(
+            ExCommand::parse("colo light"),
+            Ok(ExCommand::Theme(Some("light".to_string())))
+        );
+        assert_eq!(
+            ExCommand::parse("setf python"),
+            Ok(ExCommand::Language(Some("python".to_string())))
+        );
+        assert!(ExCommand::parse("frobnicate").is_err());
+        assert!(ExCommand::parse("s/foo").is_err());
+        assert!(matches!(
+            ExCommand::parse("s/a/b/"),
+            Ok(ExCommand::Substitute { global: false, .. })
+        ));
+    }
+
+    #[test]
+    fn resolves_ranges_against_the_buffer() {
+        let range = LineRange::Lines(LineAddress::Current, LineAddress::Last);
+        assert_eq!(range.resolve(2, 10), Ok(2..=9));
+        assert_eq!(LineRange::All.resolve(0, 3), Ok(0..=2));
+        let past_end = LineRange::Lines(LineAddress::Number(1), LineAddress::Number(11));
+        assert!(past_end.resolve(0, 10).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const IGNORE_FILE_NAME: &str = ".gitignore";
+const ALWAYS_SKIPPED: [&str; 1] = [".git"];
+
+/// A single pattern from a `.gitignore` file, relative to the directory the
+/// file was found in.
+struct IgnoreRule {
+    base: PathBuf,
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn parse(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = line
+            .strip_prefix('!')
+            .map_or((false, line), |rest| (true, rest));
+        let line = line.strip_prefix('\\').unwrap_or(line);
+        let (dir_only, line) = line
+            .strip_suffix('/')
+            .map_or((false, line), |rest| (true, rest));
+        // A slash anywhere but the end ties the pattern to the base directory;
+        // otherwise it matches a name at any depth.
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').to_string();
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            base: base.to_path_buf(),
+            pattern,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, relative_path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(path) = relative_path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let path = to_slash_path(path);
+        if self.anchored {
+            glob_match(&self.pattern, &path)
+        } else {
+            let name = path.rsplit('/').next().unwrap_or(&path);
+            glob_match(&self.pattern, name)
+        }
+    }
+}
+
+/// Matches `text` against a gitignore-style glob: `*` and `?` stay within one
+/// path component, `**` spans any number of them.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_chars(&pattern, &text)
+}
+
+fn glob_match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            glob_match_chars(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, ch)| **ch == '/')
+                    .any(|(idx, _)| glob_match_chars(rest, &text[idx + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|idx| glob_match_chars(rest, &text[idx..])),
+        ['*', rest @ ..] => {
+            let component_len = text.iter().position(|ch| *ch == '/').unwrap_or(text.len());
+            (0..=component_len).any(|idx| glob_match_chars(rest, &text[idx..]))
+        }
+        ['?', rest @ ..] => {
+            matches!(text.first(), Some(ch) if *ch != '/') && glob_match_chars(rest, &text[1..])
+        }
+        [ch, rest @ ..] => text.first() == Some(ch) && glob_match_chars(rest, &text[1..]),
+    }
+}
+
+fn to_slash_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_ignored(rules: &[IgnoreRule], relative_path: &Path, is_dir: bool) -> bool {
+    // Later rules override earlier ones, so the last matching rule decides.
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(relative_path, is_dir))
+        .is_some_and(|rule| !rule.negated)
+}
+
+/// Lists the files below `root` as `/`-separated paths relative to it, skipping
+/// anything excluded by `.gitignore` files along the way. Stops after `limit`
+/// files so huge trees don't stall the editor.
+pub fn walk_files(root: &Path, limit: usize) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut rules = Vec::new();
+    walk_dir(root, Path::new(""), &mut rules, &mut files, limit);
+    files.sort();
+    files
+}
+
+fn walk_dir(
+    root: &Path,
+    relative_dir: &Path,
+    rules: &mut Vec<IgnoreRule>,
+    files: &mut Vec<String>,
+    limit: usize,
+) {
+    let dir = root.join(relative_dir);
+    let rules_before = rules.len();
+    if let Ok(contents) = fs::read_to_string(dir.join(IGNORE_FILE_NAME)) {
+        rules.extend(
+            contents
+                .lines()
+                .filter_map(|line| IgnoreRule::parse(relative_dir, line)),
+        );
+    }
+
+    let Ok(read_dir) = fs::read_dir(&dir) else {
+        rules.truncate(rules_before);
+        return;
+    };
+    let mut entries: Vec<_> = read_dir.filter_map(Result::ok).collect();
+    entries.sort_by_key(fs::DirEntry::file_name);
+
+    for entry in entries {
+        if files.len() >= limit {
+            break;
+        }
+        let name = entry.file_name();
+        if ALWAYS_SKIPPED.iter().any(|skipped| name == *skipped) {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let relative_path = relative_dir.join(&name);
+        if is_ignored(rules, &relative_path, file_type.is_dir()) {
+            continue;
+        }
+        if file_type.is_dir() {
+            walk_dir(root, &relative_path, rules, files, limit);
+        } else {
+            files.push(to_slash_path(&relative_path));
+        }
+    }
+    rules.truncate(rules_before);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_gitignore_globs() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("src/**/*.rs", "src/editor/view/mod.rs"));
+        assert!(glob_match("**/target", "target"));
+        assert!(glob_match("file?.txt", "file1.txt"));
+        assert!(!glob_match("file?.txt", "file10.txt"));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let base = Path::new("");
+        let rules: Vec<IgnoreRule> = ["*.log", "!keep.log", "/build/"]
+            .iter()
+            .filter_map(|line| IgnoreRule::parse(base, line))
+            .collect();
+        assert!(is_ignored(&rules, Path::new("logs/debug.log"), false));
+        assert!(!is_ignored(&rules, Path::new("keep.log"), false));
+        assert!(is_ignored(&rules, Path::new("build"), true));
+        assert!(!is_ignored(&rules, Path::new("src/build"), true));
+    }
+}
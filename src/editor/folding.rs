@@ -0,0 +1,142 @@
+/// A foldable block: `start` stays visible, lines `start + 1..=end` are hidden
+/// when the fold is closed. `level` is 1 for outermost regions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FoldRegion {
+    pub start: usize,
+    pub end: usize,
+    pub level: usize,
+}
+
+impl FoldRegion {
+    pub const fn contains(&self, line_idx: usize) -> bool {
+        self.start <= line_idx && line_idx <= self.end
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FoldCommand {
+    /// Closes the innermost open fold around the caret.
+    Close,
+    /// Opens the closed fold at the caret.
+    Open,
+    Toggle,
+    CloseAll,
+    OpenAll,
+    /// Closes every fold nested deeper than the given level and opens the
+    /// rest, so 0 closes everything.
+    Level(usize),
+}
+
+/// Regions opened by a bracket that is still unclosed at the end of its line.
+/// `depths[i]` is the bracket nesting at the start of line `i`, with one extra
+/// entry for the end of the buffer. A region runs to the line holding the
+/// matching closing bracket, or to the last line if there is none.
+pub fn bracket_regions(depths: &[usize]) -> Vec<FoldRegion> {
+    let line_count = depths.len().saturating_sub(1);
+    let mut regions = Vec::new();
+    for start in 0..line_count {
+        if depths[start + 1] <= depths[start] {
+            continue;
+        }
+        let end = (start + 1..line_count)
+            .find(|&line_idx| depths[line_idx + 1] <= depths[start])
+            .unwrap_or(line_count - 1);
+        if end > start {
+            regions.push(FoldRegion {
+                start,
+                end,
+                level: 0,
+            });
+        }
+    }
+    assign_levels(&mut regions);
+    regions
+}
+
+/// Regions made of the lines indented deeper than the line before them.
+/// `indents[i]` is the indentation width of line `i`, `None` for blank lines,
+/// which belong to a region only when indented lines follow them.
+pub fn indent_regions(indents: &[Option<usize>]) -> Vec<FoldRegion> {
+    let mut regions = Vec::new();
+    for (start, indent) in indents.iter().enumerate() {
+        let Some(indent) = *indent else {
+            continue;
+        };
+        let mut end = start;
+        for (line_idx, line_indent) in indents.iter().enumerate().skip(start + 1) {
+            match line_indent {
+                Some(line_indent) if *line_indent > indent => end = line_idx,
+                Some(_) => break,
+                None => {}
+            }
+        }
+        if end > start {
+            regions.push(FoldRegion {
+                start,
+                end,
+                level: 0,
+            });
+        }
+    }
+    assign_levels(&mut regions);
+    regions
+}
+
+/// Sets each region's nesting level. Regions must be sorted by start.
+fn assign_levels(regions: &mut [FoldRegion]) {
+    let mut enclosing_ends: Vec<usize> = Vec::new();
+    for region in regions {
+        while enclosing_ends.last().is_some_and(|&end| end < region.end) {
+            enclosing_ends.pop();
+        }
+        region.level = enclosing_ends.len() + 1;
+        enclosing_ends.push(region.end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_nested_regions() {
+        // fn main() {
+        //     if x {
+        //         y();
+        //     }
+        // }
+        let regions = bracket_regions(&[0, 1, 2, 2, 1, 0]);
+        assert_eq!(
+            regions,
+            vec![
+                FoldRegion {
+                    start: 0,
+                    end: 4,
+                    level: 1,
+                },
+                FoldRegion {
+                    start: 1,
+                    end: 3,
+                    level: 2,
+                },
+            ]
+        );
+
+        let regions = indent_regions(&[Some(0), Some(4), None, Some(8), Some(4), None, Some(0)]);
+        assert_eq!(
+            regions,
+            vec![
+                FoldRegion {
+                    start: 0,
+                    end: 4,
+                    level: 1,
+                },
+                FoldRegion {
+                    start: 1,
+                    end: 3,
+                    level: 2,
+                },
+            ]
+        );
+    }
+}
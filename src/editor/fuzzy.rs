@@ -0,0 +1,90 @@
+const MATCH_SCORE: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 15;
+const BOUNDARY_BONUS: i64 = 30;
+const GAP_PENALTY: i64 = 1;
+
+fn is_boundary(prev: Option<char>, current: char) -> bool {
+    match prev {
+        None => true,
+        Some(prev) => {
+            matches!(prev, '/' | '\\' | '_' | '-' | '.' | ' ')
+                || (prev.is_lowercase() && current.is_uppercase())
+        }
+    }
+}
+
+/// Scores `candidate` against `pattern` as a case-insensitive subsequence
+/// match. Returns `None` if not every pattern character appears in order.
+/// Matches at word boundaries and runs of consecutive characters score higher,
+/// and every skipped character costs a little, so tighter matches rank first.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    let mut pattern_chars = pattern.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut prev_char = None;
+    let mut prev_matched = false;
+
+    for ch in candidate.chars() {
+        let Some(&wanted) = pattern_chars.peek() else {
+            break;
+        };
+        if ch.to_lowercase().eq(std::iter::once(wanted)) {
+            score += MATCH_SCORE;
+            if prev_matched {
+                score += CONSECUTIVE_BONUS;
+            }
+            if is_boundary(prev_char, ch) {
+                score += BOUNDARY_BONUS;
+            }
+            pattern_chars.next();
+            prev_matched = true;
+        } else {
+            score -= GAP_PENALTY;
+            prev_matched = false;
+        }
+        prev_char = Some(ch);
+    }
+
+    pattern_chars.peek().is_none().then_some(score)
+}
+
+/// Returns the indices of `items` matching `pattern`, best match first.
+/// Ties are broken by shorter, then alphabetically earlier, items.
+pub fn rank<S: AsRef<str>>(pattern: &str, items: &[S]) -> Vec<usize> {
+    let mut scored: Vec<(i64, usize)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, item)| fuzzy_score(pattern, item.as_ref()).map(|score| (score, idx)))
+        .collect();
+    scored.sort_by(|(score_a, idx_a), (score_b, idx_b)| {
+        let (a, b) = (items[*idx_a].as_ref(), items[*idx_b].as_ref());
+        score_b
+            .cmp(score_a)
+            .then_with(|| a.len().cmp(&b.len()))
+            .then_with(|| a.cmp(b))
+    });
+    scored.into_iter().map(|(_, idx)| idx).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_all_pattern_characters_in_order() {
+        assert!(fuzzy_score("vmr", "src/view/mod.rs").is_some());
+        assert!(fuzzy_score("rmv", "src/view/mod.rs").is_none());
+        assert!(fuzzy_score("", "anything").is_some());
+    }
+
+    #[test]
+    fn prefers_boundary_and_consecutive_matches() {
+        let items = [
+            "src/editor/terminal/mod.rs",
+            "src/editor.rs",
+            "tests/edit_or.rs",
+        ];
+        let ranked = rank("editor", &items);
+        assert_eq!(items[ranked[0]], "src/editor.rs");
+        assert_eq!(ranked.len(), 3);
+    }
+}
@@ -12,7 +12,10 @@ pub struct LanguageConfig {
     pub line_comment_start: String,
     pub block_comment_start: String,
     pub block_comment_end: String,
+    /// Comment openings that mark documentation, like `///`.
+    pub doc_comment_starts: Vec<String>,
     pub brackets: Vec<BracketConfig>,
+    pub formatter: Option<String>,
 }
 
 pub fn default_rust_config() -> LanguageConfig {
@@ -61,6 +64,12 @@ fn hardcoded_rust_config() -> LanguageConfig {
         line_comment_start: "//".to_string(),
         block_comment_start: "/*".to_string(),
         block_comment_end: "*/".to_string(),
+        doc_comment_starts: vec![
+            "///".to_string(),
+            "//!".to_string(),
+            "/**".to_string(),
+            "/*!".to_string(),
+        ],
         brackets: vec![
             BracketConfig {
                 open: '(',
@@ -78,6 +87,7 @@ fn hardcoded_rust_config() -> LanguageConfig {
                 color_offset: 2,
             },
         ],
+        formatter: None,
     }
 }
 
@@ -115,7 +125,15 @@ pub fn merge_config(
             .block_comment_end
             .clone()
             .unwrap_or_else(|| default.block_comment_end.clone()),
+        doc_comment_starts: file_config
+            .doc_comment_starts
+            .clone()
+            .unwrap_or_else(|| default.doc_comment_starts.clone()),
         brackets: merge_brackets(&default.brackets, file_config.brackets.as_ref()),
+        formatter: file_config
+            .formatter
+            .clone()
+            .or_else(|| default.formatter.clone()),
     }
 }
 
@@ -1,10 +1,54 @@
+use super::textmate::to_sublime_syntax;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use syntect::parsing::{ParseSyntaxError, SyntaxDefinition};
+
+#[derive(Deserialize, Default)]
+pub struct EditorConfigFile {
+    pub swap: Option<SwapConfigFile>,
+    pub editing: Option<EditingConfigFile>,
+    pub macros: Option<MacrosConfigFile>,
+    pub terminal: Option<TerminalConfigFile>,
+    pub highlighting: Option<HighlightingConfigFile>,
+}
+
+#[derive(Deserialize)]
+pub struct HighlightingConfigFile {
+    /// Files larger than this are not highlighted. 0 is no limit.
+    pub max_file_size_kb: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct TerminalConfigFile {
+    /// `auto`, `truecolor`, `256`, `16` or `none`.
+    pub colors: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MacrosConfigFile {
+    pub persist: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct EditingConfigFile {
+    pub modal: Option<bool>,
+    pub show_whitespace: Option<bool>,
+    pub indent_guides: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct SwapConfigFile {
+    pub enabled: Option<bool>,
+    pub directory: Option<String>,
+    pub interval_secs: Option<u64>,
+}
 
 #[derive(Deserialize)]
 pub struct ColorsConfigFile {
+    /// A theme from `themes/<name>.toml` that `colors` is laid over.
+    pub theme: Option<String>,
     pub colors: Option<ColorsConfig>,
 }
 
@@ -16,7 +60,32 @@ pub struct ColorsConfig {
     pub primitive_type: Option<ColorRgb>,
     pub string: Option<ColorRgb>,
     pub comment: Option<ColorRgb>,
+    pub function: Option<ColorRgb>,
+    pub macro_name: Option<ColorRgb>,
+    pub attribute: Option<ColorRgb>,
+    pub lifetime: Option<ColorRgb>,
+    pub constant: Option<ColorRgb>,
+    pub operator: Option<ColorRgb>,
+    pub escape: Option<ColorRgb>,
+    pub doc_comment: Option<ColorRgb>,
+    pub todo: Option<ColorRgb>,
     pub brackets: Option<Vec<ColorRgb>>,
+    pub match_fg: Option<ColorRgb>,
+    pub match_bg: Option<ColorRgb>,
+    pub selected_match_fg: Option<ColorRgb>,
+    pub selected_match_bg: Option<ColorRgb>,
+    pub selection_fg: Option<ColorRgb>,
+    pub selection_bg: Option<ColorRgb>,
+    pub mark: Option<ColorRgb>,
+    pub fold: Option<ColorRgb>,
+    pub whitespace: Option<ColorRgb>,
+    pub whitespace_error_bg: Option<ColorRgb>,
+    pub gutter_fg: Option<ColorRgb>,
+    pub gutter_bg: Option<ColorRgb>,
+    pub status_bar_fg: Option<ColorRgb>,
+    pub status_bar_bg: Option<ColorRgb>,
+    pub message_bar_fg: Option<ColorRgb>,
+    pub message_bar_bg: Option<ColorRgb>,
 }
 
 #[derive(Deserialize)]
@@ -26,8 +95,43 @@ pub struct LanguageConfigFile {
     pub line_comment_start: Option<String>,
     pub block_comment_start: Option<String>,
     pub block_comment_end: Option<String>,
+    pub doc_comment_starts: Option<Vec<String>>,
     pub brackets: Option<Vec<BracketConfigFile>>,
     pub extensions: Option<Vec<String>>,
+    /// File names such as `Makefile`, where `*` matches any run of
+    /// characters, as in `Dockerfile.*`.
+    pub filenames: Option<Vec<String>>,
+    /// Interpreters named by a `#!` line, such as `python3`.
+    pub interpreters: Option<Vec<String>>,
+    /// Regexes tried on the first line of files nothing else matched.
+    pub first_line_patterns: Option<Vec<String>>,
+    pub formatter: Option<String>,
+}
+
+/// How the files of a language are recognized.
+#[derive(Clone, Default)]
+pub struct LanguageDetection {
+    pub extensions: Vec<String>,
+    pub filenames: Vec<String>,
+    pub interpreters: Vec<String>,
+    pub first_line_patterns: Vec<String>,
+}
+
+impl LanguageDetection {
+    fn merge(&mut self, other: Self) {
+        for (known, added) in [
+            (&mut self.extensions, other.extensions),
+            (&mut self.filenames, other.filenames),
+            (&mut self.interpreters, other.interpreters),
+            (&mut self.first_line_patterns, other.first_line_patterns),
+        ] {
+            for value in added {
+                if !known.contains(&value) {
+                    known.push(value);
+                }
+            }
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -37,11 +141,110 @@ pub struct BracketConfigFile {
     pub color_offset: Option<usize>,
 }
 
-#[derive(Deserialize)]
+/// A color written as `"#rrggbb"`, `{ r, g, b }` or `{ hex = "#rrggbb" }`.
+/// The table forms can also set text styles.
+#[derive(Deserialize, Default)]
+#[serde(try_from = "ColorValue")]
 pub struct ColorRgb {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underline: Option<bool>,
+    pub undercurl: Option<bool>,
+    pub dim: Option<bool>,
+    pub reverse: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorValue {
+    Hex(String),
+    Table {
+        r: Option<u8>,
+        g: Option<u8>,
+        b: Option<u8>,
+        hex: Option<String>,
+        bold: Option<bool>,
+        italic: Option<bool>,
+        underline: Option<bool>,
+        undercurl: Option<bool>,
+        dim: Option<bool>,
+        reverse: Option<bool>,
+    },
+}
+
+impl TryFrom<ColorValue> for ColorRgb {
+    type Error = String;
+
+    fn try_from(value: ColorValue) -> Result<Self, Self::Error> {
+        match value {
+            ColorValue::Hex(hex) => parse_hex_color(&hex),
+            ColorValue::Table {
+                r,
+                g,
+                b,
+                hex,
+                bold,
+                italic,
+                underline,
+                undercurl,
+                dim,
+                reverse,
+            } => {
+                let color = match (hex, r, g, b) {
+                    (Some(hex), None, None, None) => parse_hex_color(&hex)?,
+                    (None, Some(r), Some(g), Some(b)) => Self {
+                        r,
+                        g,
+                        b,
+                        ..Self::default()
+                    },
+                    _ => return Err("expected either hex or all of r, g and b".to_string()),
+                };
+                Ok(Self {
+                    bold,
+                    italic,
+                    underline,
+                    undercurl,
+                    dim,
+                    reverse,
+                    ..color
+                })
+            }
+        }
+    }
+}
+
+fn parse_hex_color(hex: &str) -> Result<ColorRgb, String> {
+    let digits = hex
+        .strip_prefix('#')
+        .filter(|digits| digits.len() == 6 && digits.chars().all(|ch| ch.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("expected a #rrggbb color: {hex}"))?;
+    let channel = |idx: usize| u8::from_str_radix(&digits[idx..idx + 2], 16).unwrap_or_default();
+    Ok(ColorRgb {
+        r: channel(0),
+        g: channel(2),
+        b: channel(4),
+        ..ColorRgb::default()
+    })
+}
+
+impl ColorRgb {
+    /// Whether any text style key is set alongside the color.
+    pub fn has_style(&self) -> bool {
+        [
+            self.bold,
+            self.italic,
+            self.underline,
+            self.undercurl,
+            self.dim,
+            self.reverse,
+        ]
+        .iter()
+        .any(Option::is_some)
+    }
 }
 
 #[derive(Debug)]
@@ -51,6 +254,27 @@ pub enum ConfigError {
     IoError(std::io::Error),
     #[allow(dead_code)]
     ParseError(toml::de::Error),
+    #[allow(dead_code)]
+    GrammarError(ParseSyntaxError),
+    #[allow(dead_code)]
+    PlistError(plist::Error),
+}
+
+pub fn load_editor_config(custom_path: Option<&Path>) -> Result<EditorConfigFile, ConfigError> {
+    let config_path = if let Some(path) = custom_path {
+        path.to_path_buf()
+    } else {
+        get_config_dir()?.join("config.toml")
+    };
+
+    if !config_path.exists() {
+        return Err(ConfigError::FileNotFound);
+    }
+
+    let contents = fs::read_to_string(&config_path).map_err(ConfigError::IoError)?;
+    let config: EditorConfigFile = toml::from_str(&contents).map_err(ConfigError::ParseError)?;
+
+    Ok(config)
 }
 
 pub fn load_colors_config(custom_path: Option<&Path>) -> Result<ColorsConfigFile, ConfigError> {
@@ -70,6 +294,57 @@ pub fn load_colors_config(custom_path: Option<&Path>) -> Result<ColorsConfigFile
     Ok(config)
 }
 
+/// A named theme from `themes/<name>.toml` in the config dir. Themes use the
+/// same `[colors]` table as `colors.toml`.
+pub fn load_theme_config(
+    name: &str,
+    custom_path: Option<&Path>,
+) -> Result<ColorsConfigFile, ConfigError> {
+    let theme_path = if let Some(path) = custom_path {
+        path.to_path_buf()
+    } else {
+        get_config_dir()?
+            .join("themes")
+            .join(format!("{name}.toml"))
+    };
+
+    load_colors_config(Some(&theme_path))
+}
+
+/// The names of the theme files in the config dir, sorted.
+pub fn discover_themes() -> Vec<String> {
+    let mut names = Vec::new();
+
+    if let Ok(config_dir) = get_config_dir() {
+        theme_names_in_dir(&config_dir.join("themes"), &mut names);
+    }
+
+    #[cfg(debug_assertions)]
+    {
+        theme_names_in_dir(Path::new("docs/examples/default/themes"), &mut names);
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn theme_names_in_dir(dir: &Path, names: &mut Vec<String>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+
+    names.extend(read_dir.filter_map(|entry| {
+        let path = entry.ok()?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+            return None;
+        }
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(str::to_string)
+    }));
+}
+
 pub fn load_language_config(
     language: &str,
     custom_path: Option<&Path>,
@@ -92,8 +367,66 @@ pub fn load_language_config(
     Ok(config)
 }
 
-pub fn discover_language_extensions() -> Vec<(String, Vec<String>)> {
-    let mut map: HashMap<String, Vec<String>> = HashMap::new();
+/// The file extensions of the grammars `load_grammar` reads: Sublime Text's
+/// own format and the older `.tmLanguage` plists it grew out of.
+pub const GRAMMAR_EXTENSIONS: [&str; 2] = ["sublime-syntax", "tmLanguage"];
+
+/// A Sublime Text style grammar, from `languages/<language>.sublime-syntax`
+/// or `languages/<language>.tmLanguage` in the config dir.
+pub fn load_grammar(
+    language: &str,
+    custom_path: Option<&Path>,
+) -> Result<SyntaxDefinition, ConfigError> {
+    let grammar_path = if let Some(path) = custom_path {
+        path.to_path_buf()
+    } else {
+        let dir = get_config_dir()?.join("languages");
+        GRAMMAR_EXTENSIONS
+            .iter()
+            .map(|ext| dir.join(format!("{language}.{ext}")))
+            .find(|path| path.exists())
+            .ok_or(ConfigError::FileNotFound)?
+    };
+
+    if !grammar_path.exists() {
+        return Err(ConfigError::FileNotFound);
+    }
+
+    let contents = if grammar_path.extension().and_then(|ext| ext.to_str()) == Some("tmLanguage") {
+        let grammar = plist::Value::from_file(&grammar_path).map_err(ConfigError::PlistError)?;
+        to_sublime_syntax(&grammar)
+            .ok_or(ConfigError::GrammarError(ParseSyntaxError::TypeMismatch))?
+    } else {
+        fs::read_to_string(&grammar_path).map_err(ConfigError::IoError)?
+    };
+    SyntaxDefinition::load_from_str(&contents, true, Some(language))
+        .map_err(ConfigError::GrammarError)
+}
+
+/// The syntax-tree highlight query for `language`, from
+/// `queries/<language>/highlights.scm` in the config dir.
+pub fn load_highlight_query(
+    language: &str,
+    custom_path: Option<&Path>,
+) -> Result<String, ConfigError> {
+    let query_path = if let Some(path) = custom_path {
+        path.to_path_buf()
+    } else {
+        get_config_dir()?
+            .join("queries")
+            .join(language)
+            .join("highlights.scm")
+    };
+
+    if !query_path.exists() {
+        return Err(ConfigError::FileNotFound);
+    }
+
+    fs::read_to_string(&query_path).map_err(ConfigError::IoError)
+}
+
+pub fn discover_languages() -> Vec<(String, LanguageDetection)> {
+    let mut map: HashMap<String, LanguageDetection> = HashMap::new();
 
     if let Ok(mut config_dir) = get_config_dir() {
         config_dir.push("languages");
@@ -109,20 +442,25 @@ pub fn discover_language_extensions() -> Vec<(String, Vec<String>)> {
     map.into_iter().collect()
 }
 
-fn load_languages_from_dir(dir: &Path, map: &mut HashMap<String, Vec<String>>, is_default: bool) {
-    let read_dir = match fs::read_dir(dir) {
-        Ok(rd) => rd,
-        Err(_) => return,
+fn load_languages_from_dir(
+    dir: &Path,
+    map: &mut HashMap<String, LanguageDetection>,
+    is_default: bool,
+) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
     };
 
+    let mut pending: HashMap<String, LanguageDetection> = HashMap::new();
     for entry in read_dir {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => continue,
+        let Ok(entry) = entry else {
+            continue;
         };
         let path = entry.path();
 
-        if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let is_grammar = extension.is_some_and(|ext| GRAMMAR_EXTENSIONS.contains(&ext));
+        if extension != Some("toml") && !is_grammar {
             continue;
         }
 
@@ -131,25 +469,43 @@ fn load_languages_from_dir(dir: &Path, map: &mut HashMap<String, Vec<String>>, i
             None => continue,
         };
 
-        if is_default && map.contains_key(&file_stem) {
-            continue;
-        }
-
-        let config = match load_language_config(&file_stem, Some(&path)) {
-            Ok(cfg) => cfg,
-            Err(_) => continue,
+        let detection = if is_grammar {
+            let Ok(grammar) = load_grammar(&file_stem, Some(&path)) else {
+                continue;
+            };
+            // Sublime lists whole file names such as `Makefile` among the
+            // extensions.
+            LanguageDetection {
+                filenames: grammar.file_extensions.clone(),
+                extensions: grammar.file_extensions,
+                interpreters: Vec::new(),
+                first_line_patterns: grammar.first_line_match.into_iter().collect(),
+            }
+        } else {
+            let Ok(config) = load_language_config(&file_stem, Some(&path)) else {
+                continue;
+            };
+            LanguageDetection {
+                extensions: config.extensions.unwrap_or_else(|| vec![file_stem.clone()]),
+                filenames: config.filenames.unwrap_or_default(),
+                interpreters: config.interpreters.unwrap_or_default(),
+                first_line_patterns: config.first_line_patterns.unwrap_or_default(),
+            }
         };
 
-        let exts = config
-            .extensions
-            .clone()
-            .unwrap_or_else(|| vec![file_stem.clone()]);
+        // A language can have both a grammar and a keyword config.
+        pending.entry(file_stem).or_default().merge(detection);
+    }
 
-        map.insert(file_stem, exts);
+    for (language, detection) in pending {
+        if is_default && map.contains_key(&language) {
+            continue;
+        }
+        map.insert(language, detection);
     }
 }
 
-fn get_config_dir() -> Result<PathBuf, ConfigError> {
+pub fn get_config_dir() -> Result<PathBuf, ConfigError> {
     #[cfg(target_os = "windows")]
     {
         std::env::var("APPDATA")
@@ -0,0 +1,97 @@
+/// How many lines at the start and end of a file are searched for a modeline.
+pub const MODELINE_LINES: usize = 5;
+
+/// Whether file name `name` matches `pattern`, where `*` matches any run
+/// of characters.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    // Without a `*` the whole name has to match.
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        let Some(idx) = rest.find(part) else {
+            return false;
+        };
+        rest = &rest[idx + part.len()..];
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The interpreter a `#!` line runs, such as `python3` for both
+/// `#!/usr/bin/python3` and `#!/usr/bin/env python3`.
+pub fn shebang_interpreter(first_line: &str) -> Option<&str> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let program = file_name(words.next()?);
+    if program != "env" {
+        return Some(program);
+    }
+    // Skip `env` options and variable assignments.
+    words
+        .find(|word| !word.starts_with('-') && !word.contains('='))
+        .map(file_name)
+}
+
+/// The language named by a `den: language=name` modeline, usually in a
+/// comment, among `lines`.
+pub fn modeline_language<'a>(lines: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    lines.into_iter().find_map(|line| {
+        let (before, options) = line.split_once("den:")?;
+        if before.ends_with(|ch: char| ch.is_alphanumeric()) {
+            return None;
+        }
+        options
+            .split(|ch: char| ch.is_whitespace() || ch == ':')
+            .find_map(|option| {
+                option
+                    .strip_prefix("language=")
+                    .or_else(|| option.strip_prefix("lang="))
+            })
+            .map(|value| {
+                // Leave out the end of a surrounding comment, as in `*/`.
+                let end = value
+                    .find(|ch: char| !ch.is_alphanumeric() && !"+-_#.".contains(ch))
+                    .unwrap_or(value.len());
+                &value[..end]
+            })
+            .filter(|name| !name.is_empty())
+    })
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_file_names_shebangs_and_modelines() {
+        assert!(glob_matches("Makefile", "Makefile"));
+        assert!(!glob_matches("Makefile", "Makefile.am"));
+        assert!(glob_matches("Dockerfile.*", "Dockerfile.dev"));
+        assert!(glob_matches("*.bashrc", ".bashrc"));
+        assert!(glob_matches("*rc*", "xinitrc.local"));
+        assert!(!glob_matches("a*b", "ab_a"));
+
+        assert_eq!(shebang_interpreter("#!/bin/bash"), Some("bash"));
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/env -S PYTHONUTF8=1 python3 -u"),
+            Some("python3")
+        );
+        assert_eq!(shebang_interpreter("# not a shebang"), None);
+
+        assert_eq!(
+            modeline_language(["x = 1", "/* den: language=c */"]),
+            Some("c")
+        );
+        assert_eq!(modeline_language(["# den: lang=python:"]), Some("python"));
+        assert_eq!(modeline_language(["garden: language=rust"]), None);
+    }
+}
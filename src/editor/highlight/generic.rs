@@ -1,9 +1,14 @@
 use super::Highlighter;
 use crate::editor::annotated_string::AnnotationType;
+use crate::editor::highlight::semantic::{
+    call_ranges, escape_ranges, identifiers, is_constant_name, is_doc_comment, operator_ranges,
+    push_ranges, todo_ranges,
+};
 use crate::editor::highlight::{
     HighlightAnnotation, HighlightState, LanguageConfig, StringType, load_language_config,
     merge_config,
 };
+use std::ops::Range;
 
 pub struct GenericHighlighter {
     config: LanguageConfig,
@@ -19,7 +24,9 @@ impl GenericHighlighter {
                 line_comment_start: "//".to_string(),
                 block_comment_start: "/*".to_string(),
                 block_comment_end: "*/".to_string(),
+                doc_comment_starts: vec![],
                 brackets: vec![],
+                formatter: None,
             };
             merge_config(&default, Some(&lang_config))
         } else {
@@ -34,7 +41,9 @@ impl GenericHighlighter {
                         line_comment_start: "//".to_string(),
                         block_comment_start: "/*".to_string(),
                         block_comment_end: "*/".to_string(),
+                        doc_comment_starts: vec![],
                         brackets: vec![],
+                        formatter: None,
                     };
                     merge_config(&default, Some(&lang_config))
                 } else {
@@ -55,6 +64,7 @@ impl GenericHighlighter {
     }
 }
 
+#[allow(clippy::too_many_lines)]
 fn find_string_ranges(
     string: &str,
     state: &mut HighlightState,
@@ -192,6 +202,30 @@ fn find_string_ranges(
     (ranges, continuation_start)
 }
 
+/// Preprocessor directives like `#include`, first on their line.
+fn find_directive_range(line: &str) -> Option<Range<usize>> {
+    let start = line.len() - line.trim_start().len();
+    let rest = line[start..].strip_prefix('#')?;
+    let name_len = rest
+        .find(|ch: char| !ch.is_alphanumeric() && ch != '_')
+        .unwrap_or(rest.len());
+    (name_len > 0).then(|| start..start + 1 + name_len)
+}
+
+/// `@decorator` and `@Annotation` names, dots included.
+fn find_decorator_ranges(line: &str) -> Vec<Range<usize>> {
+    identifiers(line)
+        .into_iter()
+        .filter(|range| range.start > 0 && line[..range.start].ends_with('@'))
+        .map(|range| {
+            let name_len = line[range.start..]
+                .find(|ch: char| !ch.is_alphanumeric() && ch != '_' && ch != '.')
+                .unwrap_or(line.len() - range.start);
+            range.start - 1..range.start + name_len
+        })
+        .collect()
+}
+
 fn is_word_boundary(ch: char) -> bool {
     !ch.is_alphanumeric() && ch != '_'
 }
@@ -223,17 +257,15 @@ fn find_keyword_at(line: &str, keyword: &str, pos: usize) -> bool {
         None
     };
 
-    if let Some(keyword_first_char) = keyword.chars().next() {
-        if keyword_first_char.is_uppercase() {
-            if let Some(prev_ch) = prev_char {
-                if is_camel_case_boundary(prev_ch, keyword_first_char) {
-                    return false;
-                }
-            }
-        }
+    if let Some(keyword_first_char) = keyword.chars().next()
+        && keyword_first_char.is_uppercase()
+        && let Some(prev_ch) = prev_char
+        && is_camel_case_boundary(prev_ch, keyword_first_char)
+    {
+        return false;
     }
 
-    let before_ok = prev_char.map_or(true, |ch| is_word_boundary(ch));
+    let before_ok = prev_char.is_none_or(is_word_boundary);
 
     let after_pos = pos + keyword.len();
     let after_ok = if after_pos >= line.len() {
@@ -246,7 +278,7 @@ fn find_keyword_at(line: &str, keyword: &str, pos: usize) -> bool {
                 break;
             }
         }
-        next_char.map_or(true, |ch| is_word_boundary(ch))
+        next_char.is_none_or(is_word_boundary)
     };
 
     before_ok && after_ok
@@ -257,6 +289,10 @@ impl Highlighter for GenericHighlighter {
         &self.language_name
     }
 
+    fn formatter(&self) -> Option<&str> {
+        self.config.formatter.as_deref()
+    }
+
     #[allow(clippy::too_many_lines)]
     fn highlight_line(
         &self,
@@ -285,14 +321,25 @@ impl Highlighter for GenericHighlighter {
         }
 
         let continuation_range = continuation_start.map(|start| start..line.len());
+        let all_string_ranges: Vec<Range<usize>> = string_ranges
+            .iter()
+            .cloned()
+            .chain(continuation_range.clone())
+            .collect();
+        push_ranges(
+            &mut annotations,
+            escape_ranges(line, &all_string_ranges),
+            AnnotationType::Escape,
+        );
         let is_in_string = |pos: usize| -> bool {
             string_ranges.iter().any(|range| range.contains(&pos))
                 || continuation_range
                     .as_ref()
-                    .map_or(false, |range| range.contains(&pos))
+                    .is_some_and(|range| range.contains(&pos))
         };
 
         // Block comments
+        let continued_comment = state.in_block_comment.then_some(state.in_doc_comment);
         let mut block_comment_ranges = Vec::new();
         if !self.config.block_comment_start.is_empty() && !self.config.block_comment_end.is_empty()
         {
@@ -334,13 +381,23 @@ impl Highlighter for GenericHighlighter {
             }
         }
 
-        for range in &block_comment_ranges {
+        let mut is_doc = false;
+        for (idx, range) in block_comment_ranges.iter().enumerate() {
+            is_doc = match continued_comment {
+                Some(continued_doc) if idx == 0 => continued_doc,
+                _ => is_doc_comment(&line[range.clone()], &self.config.doc_comment_starts),
+            };
             annotations.push(HighlightAnnotation {
                 start: range.start,
                 end: range.end,
-                annotation_type: AnnotationType::Comment,
+                annotation_type: if is_doc {
+                    AnnotationType::DocComment
+                } else {
+                    AnnotationType::Comment
+                },
             });
         }
+        state.in_doc_comment = state.in_block_comment && is_doc;
 
         let is_in_block_comment = |pos: usize| -> bool {
             block_comment_ranges
@@ -349,16 +406,28 @@ impl Highlighter for GenericHighlighter {
         };
 
         // Line comments
+        let mut comment_ranges = block_comment_ranges.clone();
         if let Some(comment_start) = line.find(self.config.line_comment_start.as_str())
             && !is_in_string(comment_start)
             && !is_in_block_comment(comment_start)
         {
+            let is_doc = is_doc_comment(&line[comment_start..], &self.config.doc_comment_starts);
             annotations.push(HighlightAnnotation {
                 start: comment_start,
                 end: line.len(),
-                annotation_type: AnnotationType::Comment,
+                annotation_type: if is_doc {
+                    AnnotationType::DocComment
+                } else {
+                    AnnotationType::Comment
+                },
             });
+            comment_ranges.push(comment_start..line.len());
         }
+        push_ranges(
+            &mut annotations,
+            todo_ranges(line, &comment_ranges),
+            AnnotationType::Todo,
+        );
 
         let is_in_comment = |pos: usize| -> bool {
             is_in_block_comment(pos) || {
@@ -370,6 +439,18 @@ impl Highlighter for GenericHighlighter {
             }
         };
 
+        let is_code = |pos: usize| !is_in_string(pos) && !is_in_comment(pos);
+        push_ranges(
+            &mut annotations,
+            operator_ranges(line, is_code),
+            AnnotationType::Operator,
+        );
+        push_ranges(
+            &mut annotations,
+            call_ranges(line, is_code, &self.config.keywords),
+            AnnotationType::Function,
+        );
+
         // Keywords
         for keyword in self.config.keywords.iter().map(std::string::String::as_str) {
             let mut search_pos = 0;
@@ -473,11 +554,11 @@ impl Highlighter for GenericHighlighter {
 
             if ch.is_uppercase() {
                 // Only consider this a type name start if the previous character is a word boundary
-                if let Some(prev) = prev_char {
-                    if !is_word_boundary(prev) {
-                        prev_char = Some(ch);
-                        continue;
-                    }
+                if let Some(prev) = prev_char
+                    && !is_word_boundary(prev)
+                {
+                    prev_char = Some(ch);
+                    continue;
                 }
 
                 let start = byte_idx;
@@ -497,7 +578,7 @@ impl Highlighter for GenericHighlighter {
                     next_char = Some(ch_after);
                 }
 
-                let after_ok = next_char.map_or(true, |c| is_word_boundary(c));
+                let after_ok = next_char.is_none_or(is_word_boundary);
 
                 if after_ok {
                     let word = &line[start..end];
@@ -507,7 +588,11 @@ impl Highlighter for GenericHighlighter {
                         annotations.push(HighlightAnnotation {
                             start,
                             end,
-                            annotation_type: AnnotationType::Type,
+                            annotation_type: if is_constant_name(word) {
+                                AnnotationType::Constant
+                            } else {
+                                AnnotationType::Type
+                            },
                         });
                     }
                 }
@@ -516,6 +601,18 @@ impl Highlighter for GenericHighlighter {
             prev_char = Some(ch);
         }
 
+        if let Some(range) = find_directive_range(line).filter(|range| is_code(range.start)) {
+            push_ranges(&mut annotations, [range], AnnotationType::Macro);
+        }
+        let decorator_ranges = find_decorator_ranges(line)
+            .into_iter()
+            .filter(|range| is_code(range.start));
+        push_ranges(
+            &mut annotations,
+            decorator_ranges,
+            AnnotationType::Attribute,
+        );
+
         // Brackets
         let mut paren_level = state.paren_level;
         let mut brace_level = state.brace_level;
@@ -620,3 +717,50 @@ impl Highlighter for GenericHighlighter {
         (annotations, state)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_functions_directives_constants_operators_and_todos() {
+        let highlighter = GenericHighlighter {
+            config: LanguageConfig {
+                keywords: vec!["if".to_string(), "return".to_string()],
+                primitive_types: vec!["int".to_string()],
+                line_comment_start: "//".to_string(),
+                block_comment_start: "/*".to_string(),
+                block_comment_end: "*/".to_string(),
+                doc_comment_starts: vec!["///".to_string()],
+                brackets: vec![],
+                formatter: None,
+            },
+            language_name: "c".to_string(),
+        };
+        let typed = |line: &str, text: &str| {
+            let start = line.find(text).unwrap();
+            highlighter
+                .highlight_line(line, 0, HighlightState::default())
+                .0
+                .into_iter()
+                .filter(|annotation| {
+                    annotation.start == start && annotation.end == start + text.len()
+                })
+                .map(|annotation| annotation.annotation_type)
+                .next_back()
+        };
+
+        let line = "if (count >= MAX_LEN) return clamp(count);";
+        assert_eq!(typed(line, "clamp"), Some(AnnotationType::Function));
+        assert_eq!(typed(line, "MAX_LEN"), Some(AnnotationType::Constant));
+        assert_eq!(typed(line, ">="), Some(AnnotationType::Operator));
+        assert_eq!(typed(line, "if"), Some(AnnotationType::Keyword));
+
+        let line = "#include <stdio.h>";
+        assert_eq!(typed(line, "#include"), Some(AnnotationType::Macro));
+
+        let line = "x = 1; // FIXME: overflow";
+        assert_eq!(typed(line, "FIXME"), Some(AnnotationType::Todo));
+        assert_eq!(typed("/* TODOS */", "TODOS"), None);
+    }
+}
@@ -0,0 +1,309 @@
+use super::Highlighter;
+use super::config_file::{GRAMMAR_EXTENSIONS, LanguageDetection, load_grammar};
+use crate::editor::annotated_string::AnnotationType;
+use crate::editor::highlight::semantic::push_todo_markers;
+use crate::editor::highlight::{HighlightAnnotation, HighlightState, load_language_config};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use syntect::parsing::{
+    ParseState, Scope, ScopeStack, SyntaxDefinition, SyntaxReference, SyntaxSet, SyntaxSetBuilder,
+};
+
+/// Highlights with a Sublime Text style regex grammar, run line by line.
+/// The grammar's context stack at the end of a line is kept in a table and
+/// carried to the next line as an index in `HighlightState::grammar_state`.
+pub struct GrammarHighlighter {
+    language_name: String,
+    syntax_name: String,
+    syntax_set: Arc<SyntaxSet>,
+    formatter: Option<String>,
+    states: Mutex<GrammarStates>,
+}
+
+#[derive(Default)]
+struct GrammarStates {
+    /// Parser states at line ends. Index 0 is the start of the file.
+    line_ends: Vec<(ParseState, ScopeStack)>,
+    /// Indices into `line_ends` by scope stack. `ParseState` cannot be
+    /// hashed, so states sharing a stack are told apart by comparing them.
+    by_scopes: HashMap<Vec<Scope>, Vec<usize>>,
+    scope_types: HashMap<Scope, Option<AnnotationType>>,
+}
+
+/// Loads the grammars of `languages` into one set, so that they can include
+/// each other, and returns a highlighter for each by language.
+pub fn load_grammars(
+    languages: &[(String, LanguageDetection)],
+) -> HashMap<String, GrammarHighlighter> {
+    let mut builder = SyntaxSetBuilder::new();
+    let mut syntax_names = Vec::new();
+    for (language, _) in languages {
+        if let Some(grammar) = find_grammar(language) {
+            syntax_names.push((language.clone(), grammar.name.clone()));
+            builder.add(grammar);
+        }
+    }
+    if syntax_names.is_empty() {
+        return HashMap::new();
+    }
+
+    let syntax_set = Arc::new(builder.build());
+    syntax_names
+        .into_iter()
+        .filter(|(_, syntax_name)| syntax_set.find_syntax_by_name(syntax_name).is_some())
+        .map(|(language, syntax_name)| {
+            let formatter = load_language_config(&language, None)
+                .ok()
+                .and_then(|config| config.formatter);
+            let highlighter = GrammarHighlighter {
+                language_name: language.clone(),
+                syntax_name,
+                syntax_set: Arc::clone(&syntax_set),
+                formatter,
+                states: Mutex::new(GrammarStates::default()),
+            };
+            (language, highlighter)
+        })
+        .collect()
+}
+
+fn find_grammar(language: &str) -> Option<SyntaxDefinition> {
+    if let Ok(grammar) = load_grammar(language, None) {
+        return Some(grammar);
+    }
+
+    #[cfg(debug_assertions)]
+    {
+        use std::path::Path;
+        for ext in GRAMMAR_EXTENSIONS {
+            let path = format!("docs/examples/default/languages/{language}.{ext}");
+            if let Ok(grammar) = load_grammar(language, Some(Path::new(&path))) {
+                return Some(grammar);
+            }
+        }
+    }
+
+    None
+}
+
+impl GrammarHighlighter {
+    fn syntax(&self) -> &SyntaxReference {
+        self.syntax_set
+            .find_syntax_by_name(&self.syntax_name)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
+    }
+}
+
+impl GrammarStates {
+    fn line_start(
+        &self,
+        grammar_state: usize,
+        syntax: &SyntaxReference,
+    ) -> (ParseState, ScopeStack) {
+        self.line_ends
+            .get(grammar_state)
+            .filter(|_| grammar_state > 0)
+            .cloned()
+            .unwrap_or_else(|| (ParseState::new(syntax), ScopeStack::new()))
+    }
+
+    /// The index of a line end state, added to the table if it is new. A file
+    /// only ever reaches a handful of distinct states.
+    fn intern(&mut self, line_end: (ParseState, ScopeStack), syntax: &SyntaxReference) -> usize {
+        if self.line_ends.is_empty() {
+            let start = (ParseState::new(syntax), ScopeStack::new());
+            self.by_scopes.insert(start.1.as_slice().to_vec(), vec![0]);
+            self.line_ends.push(start);
+        }
+        let candidates = self
+            .by_scopes
+            .entry(line_end.1.as_slice().to_vec())
+            .or_default();
+        if let Some(idx) = candidates
+            .iter()
+            .copied()
+            .find(|idx| self.line_ends[*idx] == line_end)
+        {
+            return idx;
+        }
+        candidates.push(self.line_ends.len());
+        self.line_ends.push(line_end);
+        self.line_ends.len() - 1
+    }
+
+    /// The type of the innermost scope on `stack` that has one.
+    fn annotation_type(&mut self, stack: &ScopeStack) -> Option<AnnotationType> {
+        stack.as_slice().iter().rev().find_map(|scope| {
+            *self
+                .scope_types
+                .entry(*scope)
+                .or_insert_with(|| scope_type(&scope.build_string()))
+        })
+    }
+}
+
+impl Highlighter for GrammarHighlighter {
+    fn highlight_line(
+        &self,
+        line: &str,
+        _line_idx: usize,
+        mut state: HighlightState,
+    ) -> (Vec<HighlightAnnotation>, HighlightState) {
+        let syntax = self.syntax();
+        let mut states = self.states.lock().unwrap_or_else(PoisonError::into_inner);
+        let (mut parse_state, mut stack) = states.line_start(state.grammar_state, syntax);
+
+        // Grammars are written against lines that end in a newline.
+        let Ok(ops) = parse_state.parse_line(&format!("{line}\n"), &self.syntax_set) else {
+            return (Vec::new(), state);
+        };
+        let mut annotations: Vec<HighlightAnnotation> = Vec::new();
+        let mut position = 0;
+        for (byte_idx, op) in ops.iter().map(|(idx, op)| ((*idx).min(line.len()), op)) {
+            if byte_idx > position
+                && let Some(annotation_type) = states.annotation_type(&stack)
+            {
+                push_span(&mut annotations, annotation_type, position, byte_idx);
+            }
+            position = position.max(byte_idx);
+            if stack.apply(op).is_err() {
+                break;
+            }
+        }
+        if position < line.len()
+            && let Some(annotation_type) = states.annotation_type(&stack)
+        {
+            push_span(&mut annotations, annotation_type, position, line.len());
+        }
+
+        push_todo_markers(line, &mut annotations);
+
+        state.grammar_state = states.intern((parse_state, stack), syntax);
+        (annotations, state)
+    }
+
+    fn language_name(&self) -> &str {
+        &self.language_name
+    }
+
+    fn formatter(&self) -> Option<&str> {
+        self.formatter.as_deref()
+    }
+}
+
+/// Adds a span, extending the previous one when they touch and match.
+fn push_span(
+    annotations: &mut Vec<HighlightAnnotation>,
+    annotation_type: AnnotationType,
+    start: usize,
+    end: usize,
+) {
+    if let Some(last) = annotations.last_mut()
+        && last.annotation_type == annotation_type
+        && last.end == start
+    {
+        last.end = end;
+        return;
+    }
+    annotations.push(HighlightAnnotation {
+        annotation_type,
+        start,
+        end,
+    });
+}
+
+/// The annotation type for a grammar scope name. Dotted names fall back to
+/// their parent, so `string.quoted.double` is drawn like `string`.
+fn scope_type(name: &str) -> Option<AnnotationType> {
+    match name {
+        "comment" => Some(AnnotationType::Comment),
+        "comment.block.documentation" | "comment.line.documentation" => {
+            Some(AnnotationType::DocComment)
+        }
+        "string" | "markup.raw" => Some(AnnotationType::String),
+        "constant.character.escape" => Some(AnnotationType::Escape),
+        "constant.numeric" | "constant.language" => Some(AnnotationType::Number),
+        "constant.other" | "variable.other.constant" => Some(AnnotationType::Constant),
+        "keyword.operator" => Some(AnnotationType::Operator),
+        "punctuation" => None,
+        "entity.name.function" | "support.function" => Some(AnnotationType::Function),
+        "entity.name.macro" | "support.function.macro" => Some(AnnotationType::Macro),
+        "entity.other.attribute-name" | "meta.annotation" | "meta.attribute" => {
+            Some(AnnotationType::Attribute)
+        }
+        "storage.modifier.lifetime" => Some(AnnotationType::Lifetime),
+        "keyword" | "storage" | "markup.heading" => Some(AnnotationType::Keyword),
+        "support.type.primitive" | "storage.type.primitive" => Some(AnnotationType::PrimitiveType),
+        "entity.name.type" | "entity.name.class" | "support.type" | "support.class" => {
+            Some(AnnotationType::Type)
+        }
+        _ => name
+            .rsplit_once('.')
+            .and_then(|(parent, _)| scope_type(parent)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_multi_line_strings_between_lines() {
+        let grammar = SyntaxDefinition::load_from_str(
+            include_str!("../../../docs/examples/default/languages/toml.sublime-syntax"),
+            true,
+            None,
+        )
+        .expect("default TOML grammar");
+        let mut builder = SyntaxSetBuilder::new();
+        builder.add(grammar);
+        let highlighter = GrammarHighlighter {
+            language_name: "toml".to_string(),
+            syntax_name: "TOML".to_string(),
+            syntax_set: Arc::new(builder.build()),
+            formatter: None,
+            states: Mutex::new(GrammarStates::default()),
+        };
+
+        let mut state = HighlightState::default();
+        let mut types = Vec::new();
+        for line in [
+            "[package]",
+            "text = \"\"\"",
+            "port = 80",
+            "\"\"\"",
+            "port = 80 # x",
+        ] {
+            let (annotations, new_state) = highlighter.highlight_line(line, 0, state);
+            types.push(
+                annotations
+                    .iter()
+                    .map(|annotation| {
+                        let text = line[annotation.start..annotation.end].to_string();
+                        (text, annotation.annotation_type)
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            state = new_state;
+        }
+        assert_eq!(
+            types,
+            vec![
+                vec![("package".to_string(), AnnotationType::Type)],
+                vec![
+                    ("text".to_string(), AnnotationType::Keyword),
+                    ("=".to_string(), AnnotationType::Operator),
+                    ("\"\"\"".to_string(), AnnotationType::String),
+                ],
+                vec![("port = 80".to_string(), AnnotationType::String)],
+                vec![("\"\"\"".to_string(), AnnotationType::String)],
+                vec![
+                    ("port".to_string(), AnnotationType::Keyword),
+                    ("=".to_string(), AnnotationType::Operator),
+                    ("80".to_string(), AnnotationType::Number),
+                    ("# x".to_string(), AnnotationType::Comment),
+                ],
+            ]
+        );
+    }
+}
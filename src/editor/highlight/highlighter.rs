@@ -1,19 +1,50 @@
 use crate::editor::annotated_string::AnnotationType;
+use crate::editor::line::Line;
+use std::ops::Range;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct HighlightAnnotation {
     pub annotation_type: AnnotationType,
     pub start: usize,
     pub end: usize,
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub struct HighlightState {
     pub in_block_comment: bool,
+    /// The open block comment is a doc comment.
+    pub in_doc_comment: bool,
     pub paren_level: usize,
     pub brace_level: usize,
     pub bracket_level: usize,
     pub in_string: Option<StringType>,
+    /// Index of the grammar parser state at the end of the previous line,
+    /// for grammar-based highlighters. 0 is the start of the file.
+    pub grammar_state: usize,
+}
+
+/// Lines `start..old_end` of the buffer at version `since` became lines
+/// `start..new_end`, through one or more edits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineEdit {
+    pub since: u64,
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
+impl LineEdit {
+    /// This edit followed by lines `start..old_end` of its result becoming
+    /// `start..new_end`.
+    #[must_use]
+    pub fn then(self, start: usize, old_end: usize, new_end: usize) -> Self {
+        Self {
+            since: self.since,
+            start: self.start.min(start),
+            old_end: self.old_end + old_end.saturating_sub(self.new_end),
+            new_end: new_end + self.new_end.saturating_sub(old_end),
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -26,6 +57,25 @@ pub enum StringType {
 }
 
 pub trait Highlighter: Send + Sync {
+    /// Called with the whole buffer before its lines are highlighted, for
+    /// highlighters that parse the document instead of scanning line by line.
+    /// `version` changes whenever the buffer may have changed, and `edit`,
+    /// when known, holds the lines changed since the version last seen.
+    /// Returns the lines whose annotations changed, edited or not.
+    fn update_document(
+        &self,
+        _lines: &[Line],
+        _edit: Option<&LineEdit>,
+        _version: u64,
+    ) -> Option<Range<usize>> {
+        None
+    }
+    /// Changes when the annotations of `line_idx` change although its text
+    /// did not, as document-based highlighters find after an edit elsewhere.
+    /// Line-based highlighters depend only on the line and the state before it.
+    fn line_revision(&self, _line_idx: usize) -> u64 {
+        0
+    }
     fn highlight_line(
         &self,
         line: &str,
@@ -33,4 +83,9 @@ pub trait Highlighter: Send + Sync {
         state: HighlightState,
     ) -> (Vec<HighlightAnnotation>, HighlightState);
     fn language_name(&self) -> &str;
+    /// Shell command that reads the buffer on stdin and writes the formatted
+    /// buffer to stdout, run before saving.
+    fn formatter(&self) -> Option<&str> {
+        None
+    }
 }
@@ -1,5 +1,5 @@
 pub mod highlighter;
-pub use highlighter::{HighlightAnnotation, HighlightState, Highlighter, StringType};
+pub use highlighter::{HighlightAnnotation, HighlightState, Highlighter, LineEdit, StringType};
 
 pub mod config;
 pub use config::{LanguageConfig, default_rust_config, merge_config};
@@ -8,7 +8,12 @@ pub mod config_file;
 pub use config_file::load_language_config;
 
 pub mod rust;
+pub mod detect;
 pub mod generic;
+pub mod grammar;
+pub mod semantic;
+pub mod syntax_tree;
+pub mod textmate;
 
 pub mod registry;
 pub use registry::HighlighterRegistry;
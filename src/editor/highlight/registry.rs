@@ -1,52 +1,197 @@
 use super::Highlighter;
-use super::config_file::discover_language_extensions;
+use super::config_file::{LanguageDetection, discover_languages};
+use super::detect::{MODELINE_LINES, glob_matches, modeline_language, shebang_interpreter};
 use super::generic::GenericHighlighter;
+use super::grammar::load_grammars;
 use super::rust::RustHighlighter;
+use super::syntax_tree::TreeSitterHighlighter;
+use crate::editor::line::Line;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use syntect::parsing::Regex;
 
 pub struct HighlighterRegistry {
-    highlighters: Vec<Box<dyn Highlighter>>,
+    highlighters: Vec<Arc<dyn Highlighter>>,
+    /// The config name of each highlighter, such as `python`.
+    names: Vec<String>,
     extension_map: HashMap<String, usize>,
+    filename_patterns: Vec<(String, usize)>,
+    interpreter_map: HashMap<String, usize>,
+    first_line_patterns: Vec<(Regex, usize)>,
 }
 
 impl HighlighterRegistry {
     pub fn new() -> Self {
         let mut registry = Self {
             highlighters: Vec::new(),
+            names: Vec::new(),
             extension_map: HashMap::new(),
+            filename_patterns: Vec::new(),
+            interpreter_map: HashMap::new(),
+            first_line_patterns: Vec::new(),
         };
 
-        registry.register(Box::new(RustHighlighter::new()), vec!["rs".to_string()]);
+        let languages = discover_languages();
+        let mut rust_detection = LanguageDetection {
+            extensions: vec!["rs".to_string()],
+            ..LanguageDetection::default()
+        };
+        if let Some((_, detection)) = languages
+            .iter()
+            .find(|(language, _)| language.eq_ignore_ascii_case("rust"))
+        {
+            rust_detection.filenames.clone_from(&detection.filenames);
+            rust_detection
+                .interpreters
+                .clone_from(&detection.interpreters);
+            rust_detection
+                .first_line_patterns
+                .clone_from(&detection.first_line_patterns);
+        }
+        let rust: Box<dyn Highlighter> = match TreeSitterHighlighter::rust() {
+            Some(highlighter) => Box::new(highlighter),
+            None => Box::new(RustHighlighter::new()),
+        };
+        registry.register(rust, "rust", rust_detection);
 
-        for (language, extensions) in discover_language_extensions() {
+        let mut grammars = load_grammars(&languages);
+        for (language, detection) in languages {
             if language.eq_ignore_ascii_case("rust") {
                 continue;
             }
 
+            if let Some(highlighter) = grammars.remove(&language) {
+                registry.register(Box::new(highlighter), &language, detection);
+                continue;
+            }
+
             if let Some(highlighter) = GenericHighlighter::new(&language) {
-                registry.register(Box::new(highlighter), extensions);
+                registry.register(Box::new(highlighter), &language, detection);
             }
         }
 
         registry
     }
 
-    pub fn register(&mut self, highlighter: Box<dyn Highlighter>, extensions: Vec<String>) {
+    pub fn register(
+        &mut self,
+        highlighter: Box<dyn Highlighter>,
+        name: &str,
+        detection: LanguageDetection,
+    ) {
         let index = self.highlighters.len();
-        self.highlighters.push(highlighter);
+        self.highlighters.push(Arc::from(highlighter));
+        self.names.push(name.to_string());
 
-        for ext in extensions {
+        for ext in detection.extensions {
             self.extension_map.insert(ext, index);
         }
+        for pattern in detection.filenames {
+            self.filename_patterns.push((pattern, index));
+        }
+        for interpreter in detection.interpreters {
+            self.interpreter_map.insert(interpreter, index);
+        }
+        for pattern in detection.first_line_patterns {
+            // A pattern that does not compile would panic on first use.
+            if Regex::try_compile(&pattern).is_none() {
+                self.first_line_patterns.push((Regex::new(pattern), index));
+            }
+        }
     }
 
-    pub fn get_highlighter(&self, file_extension: Option<&str>) -> Option<&dyn Highlighter> {
-        let ext = file_extension?;
-        let index = self.extension_map.get(ext)?;
-        self.highlighters
-            .get(*index)
+    /// The language of a file, from the first of: a `den: language=...`
+    /// modeline near its start or end, its file name, its extension, its
+    /// `#!` line and its first line.
+    pub fn detect_language(&self, path: Option<&Path>, lines: &[Line]) -> Option<&str> {
+        let modeline_lines = lines
+            .iter()
+            .take(MODELINE_LINES)
+            .chain(
+                lines.iter().skip(
+                    lines
+                        .len()
+                        .saturating_sub(MODELINE_LINES)
+                        .max(MODELINE_LINES),
+                ),
+            )
+            .map(|line| &**line);
+        if let Some(language) =
+            modeline_language(modeline_lines).and_then(|language| self.find_language(language))
+        {
+            return Some(language);
+        }
+
+        let file_name = path
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str());
+        let first_line = lines.first().map(|line| &**line);
+        let index = file_name
+            .and_then(|name| {
+                self.filename_patterns
+                    .iter()
+                    .find(|(pattern, _)| glob_matches(pattern, name))
+                    .map(|(_, index)| *index)
+            })
+            .or_else(|| {
+                let ext = path?.extension()?.to_str()?;
+                self.extension_map.get(ext).copied()
+            })
+            .or_else(|| {
+                let interpreter = shebang_interpreter(first_line?)?;
+                // `python3.12` falls back to `python3`, then to `python`.
+                let unversioned =
+                    interpreter.trim_end_matches(|ch: char| ch.is_ascii_digit() || ch == '.');
+                [
+                    interpreter,
+                    interpreter.split('.').next().unwrap_or(interpreter),
+                    unversioned,
+                ]
+                .iter()
+                .find_map(|name| self.interpreter_map.get(*name).copied())
+            })
+            .or_else(|| {
+                let first_line = first_line?;
+                self.first_line_patterns
+                    .iter()
+                    .find(|(pattern, _)| pattern.is_match(first_line))
+                    .map(|(_, index)| *index)
+            })?;
+        self.names.get(index).map(String::as_str)
+    }
+
+    /// The registered name for `name`, which may also be a highlighter's
+    /// display name, in any case.
+    pub fn find_language(&self, name: &str) -> Option<&str> {
+        self.names
+            .iter()
+            .zip(&self.highlighters)
+            .find(|(known, highlighter)| {
+                known.eq_ignore_ascii_case(name)
+                    || highlighter.language_name().eq_ignore_ascii_case(name)
+            })
+            .map(|(known, _)| known.as_str())
+    }
+
+    /// The names of every registered language, sorted.
+    pub fn language_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.names.iter().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    pub fn get_highlighter(&self, language: Option<&str>) -> Option<&dyn Highlighter> {
+        self.get_shared_highlighter(language)
             .map(std::convert::AsRef::as_ref)
     }
+
+    /// The highlighter for `language`, for use on another thread.
+    pub fn get_shared_highlighter(&self, language: Option<&str>) -> Option<&Arc<dyn Highlighter>> {
+        let language = language?;
+        let index = self.names.iter().position(|name| name == language)?;
+        self.highlighters.get(index)
+    }
 }
 
 impl Default for HighlighterRegistry {
@@ -1,9 +1,14 @@
 use super::Highlighter;
 use crate::editor::annotated_string::AnnotationType;
+use crate::editor::highlight::semantic::{
+    call_ranges, escape_ranges, identifiers, is_constant_name, is_doc_comment, operator_ranges,
+    push_ranges, todo_ranges,
+};
 use crate::editor::highlight::{
     HighlightAnnotation, HighlightState, LanguageConfig, default_rust_config, load_language_config,
     merge_config,
 };
+use std::ops::Range;
 
 pub struct RustHighlighter {
     config: LanguageConfig,
@@ -36,7 +41,7 @@ fn find_string_ranges(string: &str) -> Vec<std::ops::Range<usize>> {
             if ch == '"' {
                 in_double_quote = true;
                 string_start = byte_idx;
-            } else if ch == '\'' {
+            } else if ch == '\'' && is_char_literal(&string[byte_idx..]) {
                 in_single_quote = true;
                 string_start = byte_idx;
             }
@@ -57,6 +62,84 @@ fn find_string_ranges(string: &str) -> Vec<std::ops::Range<usize>> {
     ranges
 }
 
+/// Whether the quote starting `rest` opens a char literal like `'a'` or
+/// `'\n'` rather than a lifetime or label like `'a`.
+fn is_char_literal(rest: &str) -> bool {
+    let mut chars = rest.chars().skip(1);
+    match chars.next() {
+        Some('\\') => true,
+        Some(_) => chars.next() == Some('\''),
+        None => false,
+    }
+}
+
+/// `'a` lifetimes and `'outer` loop labels.
+fn find_lifetime_ranges(line: &str) -> Vec<Range<usize>> {
+    identifiers(line)
+        .into_iter()
+        .filter(|range| range.start > 0 && line[..range.start].ends_with('\''))
+        .map(|range| range.start - 1..range.end)
+        .collect()
+}
+
+/// `name!` macro invocations and `macro_rules!`, but not `a != b`.
+fn find_macro_ranges(line: &str) -> Vec<Range<usize>> {
+    identifiers(line)
+        .into_iter()
+        .filter(|range| line[range.end..].starts_with('!') && !line[range.end..].starts_with("!="))
+        .map(|range| range.start..range.end + 1)
+        .collect()
+}
+
+/// `#[...]` and `#![...]` attributes, up to the matching `]`.
+fn find_attribute_ranges(line: &str, is_code: impl Fn(usize) -> bool) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut search_start = 0;
+    while let Some(rel_pos) = line[search_start..].find('#') {
+        let start = search_start + rel_pos;
+        search_start = start + 1;
+        let rest = &line[start + 1..];
+        let open = if rest.starts_with('[') {
+            1
+        } else if rest.starts_with("![") {
+            2
+        } else {
+            continue;
+        };
+        if !is_code(start) {
+            continue;
+        }
+        let mut depth = 0;
+        let mut end = line.len();
+        for (byte_idx, ch) in line[start + open..].char_indices() {
+            if ch == '[' {
+                depth += 1;
+            } else if ch == ']' {
+                depth -= 1;
+                if depth == 0 {
+                    end = start + open + byte_idx + 1;
+                    break;
+                }
+            }
+        }
+        ranges.push(start..end);
+        search_start = end;
+    }
+    ranges
+}
+
+/// Names right after `fn`.
+fn find_fn_name_ranges(line: &str) -> Vec<Range<usize>> {
+    let words = identifiers(line);
+    words
+        .windows(2)
+        .filter(|pair| {
+            &line[pair[0].clone()] == "fn" && line[pair[0].end..pair[1].start].trim().is_empty()
+        })
+        .map(|pair| pair[1].clone())
+        .collect()
+}
+
 fn find_number_ranges(string: &str) -> Vec<std::ops::Range<usize>> {
     let mut ranges = Vec::new();
     let mut i = 0;
@@ -224,10 +307,17 @@ impl Highlighter for RustHighlighter {
             });
         }
 
+        push_ranges(
+            &mut annotations,
+            escape_ranges(line, &string_ranges),
+            AnnotationType::Escape,
+        );
+
         let is_in_string = |byte_idx: usize| -> bool {
             string_ranges.iter().any(|range| range.contains(&byte_idx))
         };
 
+        let continued_comment = state.in_block_comment.then_some(state.in_doc_comment);
         let mut comment_ranges = Vec::new();
         if state.in_block_comment {
             if let Some(close_pos) = line.find(self.config.block_comment_end.as_str()) {
@@ -271,6 +361,25 @@ impl Highlighter for RustHighlighter {
         let is_in_comment = |byte_idx: usize| -> bool {
             comment_ranges.iter().any(|range| range.contains(&byte_idx))
         };
+        let is_code = |byte_idx: usize| !is_in_string(byte_idx) && !is_in_comment(byte_idx);
+
+        push_ranges(
+            &mut annotations,
+            operator_ranges(line, is_code),
+            AnnotationType::Operator,
+        );
+        let function_ranges = call_ranges(line, is_code, &self.config.keywords)
+            .into_iter()
+            .chain(
+                find_fn_name_ranges(line)
+                    .into_iter()
+                    .filter(|range| is_code(range.start)),
+            );
+        push_ranges(&mut annotations, function_ranges, AnnotationType::Function);
+        let lifetime_ranges = find_lifetime_ranges(line)
+            .into_iter()
+            .filter(|range| is_code(range.start));
+        push_ranges(&mut annotations, lifetime_ranges, AnnotationType::Lifetime);
 
         for primitive_type in &self.config.primitive_types {
             let mut search_start = 0;
@@ -332,7 +441,11 @@ impl Highlighter for RustHighlighter {
                 let is_keyword = self.config.keywords.iter().any(|kw| kw.as_str() == word);
                 if !is_keyword {
                     annotations.push(HighlightAnnotation {
-                        annotation_type: AnnotationType::Type,
+                        annotation_type: if is_constant_name(word) {
+                            AnnotationType::Constant
+                        } else {
+                            AnnotationType::Type
+                        },
                         start: range.start,
                         end: range.end,
                     });
@@ -340,13 +453,38 @@ impl Highlighter for RustHighlighter {
             }
         }
 
-        for range in &comment_ranges {
+        let macro_ranges = find_macro_ranges(line)
+            .into_iter()
+            .filter(|range| is_code(range.start));
+        push_ranges(&mut annotations, macro_ranges, AnnotationType::Macro);
+        push_ranges(
+            &mut annotations,
+            find_attribute_ranges(line, is_code),
+            AnnotationType::Attribute,
+        );
+
+        let mut is_doc = false;
+        for (idx, range) in comment_ranges.iter().enumerate() {
+            is_doc = match continued_comment {
+                Some(continued_doc) if idx == 0 => continued_doc,
+                _ => is_doc_comment(&line[range.clone()], &self.config.doc_comment_starts),
+            };
             annotations.push(HighlightAnnotation {
-                annotation_type: AnnotationType::Comment,
+                annotation_type: if is_doc {
+                    AnnotationType::DocComment
+                } else {
+                    AnnotationType::Comment
+                },
                 start: range.start,
                 end: range.end,
             });
         }
+        state.in_doc_comment = state.in_block_comment && is_doc;
+        push_ranges(
+            &mut annotations,
+            todo_ranges(line, &comment_ranges),
+            AnnotationType::Todo,
+        );
 
         // 括弧のハイライト
         // 注意: HighlightState の paren_level, brace_level, bracket_level は
@@ -404,4 +542,53 @@ impl Highlighter for RustHighlighter {
     fn language_name(&self) -> &'static str {
         "Rust"
     }
+
+    fn formatter(&self) -> Option<&str> {
+        self.config.formatter.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_lifetimes_macros_attributes_and_doc_comments() {
+        let highlighter = RustHighlighter::new();
+        let typed = |line: &str, text: &str| {
+            let start = line.find(text).unwrap();
+            highlighter
+                .highlight_line(line, 0, HighlightState::default())
+                .0
+                .into_iter()
+                .filter(|annotation| {
+                    annotation.start == start && annotation.end == start + text.len()
+                })
+                .map(|annotation| annotation.annotation_type)
+                .next_back()
+        };
+
+        let line = "fn parse<'a>(s: &'a str) -> char { let c = '\\n'; println!(\"{c}\\t\") }";
+        assert_eq!(typed(line, "parse"), Some(AnnotationType::Function));
+        assert_eq!(typed(line, "'a"), Some(AnnotationType::Lifetime));
+        assert_eq!(typed(line, "'\\n'"), Some(AnnotationType::String));
+        assert_eq!(typed(line, "println!"), Some(AnnotationType::Macro));
+        assert_eq!(typed(line, "\\t"), Some(AnnotationType::Escape));
+        assert_eq!(typed(line, "->"), Some(AnnotationType::Operator));
+
+        let line = "#[derive(Debug)] const MAX_LEN: usize = 4;";
+        assert_eq!(
+            typed(line, "#[derive(Debug)]"),
+            Some(AnnotationType::Attribute)
+        );
+        assert_eq!(typed(line, "MAX_LEN"), Some(AnnotationType::Constant));
+
+        let line = "/// TODO: document";
+        assert_eq!(typed(line, line), Some(AnnotationType::DocComment));
+        assert_eq!(typed(line, "TODO"), Some(AnnotationType::Todo));
+        assert_eq!(
+            typed("//// banner", "//// banner"),
+            Some(AnnotationType::Comment)
+        );
+    }
 }
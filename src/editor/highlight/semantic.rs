@@ -0,0 +1,168 @@
+use super::HighlightAnnotation;
+use crate::editor::annotated_string::AnnotationType;
+use std::ops::Range;
+
+const TODO_MARKERS: [&str; 3] = ["TODO", "FIXME", "XXX"];
+const OPERATOR_CHARS: &str = "+-*/%=!<>&|^~?";
+
+pub fn push_ranges(
+    annotations: &mut Vec<HighlightAnnotation>,
+    ranges: impl IntoIterator<Item = Range<usize>>,
+    annotation_type: AnnotationType,
+) {
+    annotations.extend(ranges.into_iter().map(|range| HighlightAnnotation {
+        annotation_type,
+        start: range.start,
+        end: range.end,
+    }));
+}
+
+pub fn is_identifier_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Byte ranges of the identifiers in `line`: words starting with a letter or
+/// an underscore.
+pub fn identifiers(line: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    let mut prev = None;
+    for (byte_idx, ch) in line.char_indices() {
+        match start {
+            Some(word_start) if !is_identifier_char(ch) => {
+                ranges.push(word_start..byte_idx);
+                start = None;
+            }
+            None if (ch.is_alphabetic() || ch == '_') && !prev.is_some_and(is_identifier_char) => {
+                start = Some(byte_idx);
+            }
+            _ => {}
+        }
+        prev = Some(ch);
+    }
+    if let Some(word_start) = start {
+        ranges.push(word_start..line.len());
+    }
+    ranges
+}
+
+/// `SCREAMING_CASE` names, which are constants by convention.
+pub fn is_constant_name(word: &str) -> bool {
+    word.len() > 1
+        && word.starts_with(|ch: char| ch.is_uppercase())
+        && word
+            .chars()
+            .all(|ch| ch.is_uppercase() || ch.is_ascii_digit() || ch == '_')
+}
+
+/// Identifiers directly followed by `(` that are not keywords.
+pub fn call_ranges(
+    line: &str,
+    is_code: impl Fn(usize) -> bool,
+    keywords: &[String],
+) -> Vec<Range<usize>> {
+    identifiers(line)
+        .into_iter()
+        .filter(|range| {
+            is_code(range.start)
+                && line[range.end..].starts_with('(')
+                && !keywords
+                    .iter()
+                    .any(|keyword| *keyword == line[range.clone()])
+        })
+        .collect()
+}
+
+/// Runs of operator characters outside strings and comments.
+pub fn operator_ranges(line: &str, is_code: impl Fn(usize) -> bool) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (byte_idx, ch) in line.char_indices() {
+        if !OPERATOR_CHARS.contains(ch) || !is_code(byte_idx) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if last.end == byte_idx => last.end = byte_idx + 1,
+            _ => ranges.push(byte_idx..byte_idx + 1),
+        }
+    }
+    ranges
+}
+
+/// Escape sequences inside `strings`: a backslash and the character after it,
+/// or the whole `\x41` / `\u{1F600}` form.
+pub fn escape_ranges(line: &str, strings: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    for string in strings {
+        let text = &line[string.clone()];
+        let mut chars = text.char_indices().peekable();
+        while let Some((offset, ch)) = chars.next() {
+            if ch != '\\' {
+                continue;
+            }
+            let Some((_, escaped)) = chars.next() else {
+                break;
+            };
+            let mut end = offset + 1 + escaped.len_utf8();
+            let rest = &text[end..];
+            if escaped == 'u' && rest.starts_with('{') {
+                end += rest.find('}').map_or(rest.len(), |close| close + 1);
+            } else if escaped == 'x' {
+                end += rest
+                    .chars()
+                    .take(2)
+                    .take_while(char::is_ascii_hexdigit)
+                    .count();
+            }
+            while chars.peek().is_some_and(|(next, _)| *next < end) {
+                chars.next();
+            }
+            ranges.push(string.start + offset..string.start + end);
+        }
+    }
+    ranges
+}
+
+/// TODO, FIXME and XXX written as words inside `comments`.
+pub fn todo_ranges(line: &str, comments: &[Range<usize>]) -> Vec<Range<usize>> {
+    identifiers(line)
+        .into_iter()
+        .filter(|range| {
+            TODO_MARKERS.contains(&&line[range.clone()])
+                && comments
+                    .iter()
+                    .any(|comment| comment.contains(&range.start))
+        })
+        .collect()
+}
+
+/// Marks TODO, FIXME and XXX inside the comments already in `annotations`,
+/// for highlighters that find comments some other way than line by line.
+pub fn push_todo_markers(line: &str, annotations: &mut Vec<HighlightAnnotation>) {
+    let comments: Vec<Range<usize>> = annotations
+        .iter()
+        .filter(|annotation| {
+            matches!(
+                annotation.annotation_type,
+                AnnotationType::Comment | AnnotationType::DocComment
+            )
+        })
+        .map(|annotation| annotation.start..annotation.end)
+        .collect();
+    push_ranges(
+        annotations,
+        todo_ranges(line, &comments),
+        AnnotationType::Todo,
+    );
+}
+
+/// Whether `comment` opens with one of `doc_starts`. A longer run of the same
+/// character, like `////`, is an ordinary comment.
+pub fn is_doc_comment(comment: &str, doc_starts: &[String]) -> bool {
+    doc_starts.iter().any(|start| {
+        comment.starts_with(start.as_str())
+            && start
+                .chars()
+                .last()
+                .is_none_or(|last| !comment[start.len()..].starts_with(last))
+    })
+}
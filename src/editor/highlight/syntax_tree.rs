@@ -0,0 +1,606 @@
+use super::Highlighter;
+use super::config_file::load_highlight_query;
+use crate::editor::annotated_string::AnnotationType;
+use crate::editor::highlight::semantic::push_todo_markers;
+use crate::editor::highlight::{
+    HighlightAnnotation, HighlightState, LanguageConfig, LineEdit, default_rust_config,
+    load_language_config, merge_config,
+};
+use crate::editor::line::Line;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::{Mutex, PoisonError};
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Language, Parser, Point, Query, QueryCursor, Tree};
+
+const BRACKET_TYPES: [AnnotationType; 4] = [
+    AnnotationType::Bracket0,
+    AnnotationType::Bracket1,
+    AnnotationType::Bracket2,
+    AnnotationType::Bracket3,
+];
+
+/// Added to the bundled Rust query, which only captures the name after the
+/// tick of a lifetime, so that `'a` is drawn as one lifetime.
+const RUST_QUERY_ADDITIONS: &str = "(lifetime) @lifetime";
+
+/// Highlights from a syntax tree built by a tree-sitter grammar. The captures
+/// of the language's highlight query are mapped to annotation types by name.
+pub struct TreeSitterHighlighter {
+    language_name: String,
+    config: LanguageConfig,
+    query: Query,
+    capture_types: Vec<Option<AnnotationType>>,
+    document: Mutex<Document>,
+}
+
+/// The buffer as last parsed, with the annotations of each of its lines.
+struct Document {
+    parser: Parser,
+    tree: Option<Tree>,
+    text: String,
+    line_starts: Vec<usize>,
+    version: Option<u64>,
+    annotations: Vec<Vec<HighlightAnnotation>>,
+    /// The version at which the annotations of each line last changed.
+    line_revisions: Vec<u64>,
+}
+
+impl TreeSitterHighlighter {
+    pub fn rust() -> Option<Self> {
+        let default_config = default_rust_config();
+        let config = if let Ok(lang_config) = load_language_config("rust", None) {
+            merge_config(&default_config, Some(&lang_config))
+        } else {
+            default_config
+        };
+        Self::new(
+            "Rust",
+            "rust",
+            &tree_sitter_rust::LANGUAGE.into(),
+            &format!(
+                "{}\n{RUST_QUERY_ADDITIONS}\n",
+                tree_sitter_rust::HIGHLIGHTS_QUERY
+            ),
+            config,
+        )
+    }
+
+    /// `queries/<language>/highlights.scm` in the config dir replaces the
+    /// grammar's own query. If it does not compile, the built-in one is used.
+    fn new(
+        language_name: &str,
+        config_name: &str,
+        language: &Language,
+        builtin_query: &str,
+        config: LanguageConfig,
+    ) -> Option<Self> {
+        let query = load_highlight_query(config_name, None)
+            .ok()
+            .and_then(|source| Query::new(language, &source).ok())
+            .or_else(|| Query::new(language, builtin_query).ok())?;
+        let mut parser = Parser::new();
+        parser.set_language(language).ok()?;
+        let capture_types = query
+            .capture_names()
+            .iter()
+            .map(|name| capture_type(name))
+            .collect();
+
+        Some(Self {
+            language_name: language_name.to_string(),
+            config,
+            query,
+            capture_types,
+            document: Mutex::new(Document {
+                parser,
+                tree: None,
+                text: String::new(),
+                line_starts: Vec::new(),
+                version: None,
+                annotations: Vec::new(),
+                line_revisions: Vec::new(),
+            }),
+        })
+    }
+
+    /// Bracket annotations outside strings and comments, colored by nesting
+    /// like the scanners do, so that `state` tracks bracket levels for folding.
+    fn annotate_brackets(
+        &self,
+        line: &str,
+        annotations: &mut Vec<HighlightAnnotation>,
+        state: &mut HighlightState,
+    ) {
+        let literals: Vec<Range<usize>> = annotations
+            .iter()
+            .filter(|annotation| {
+                matches!(
+                    annotation.annotation_type,
+                    AnnotationType::String
+                        | AnnotationType::Escape
+                        | AnnotationType::Comment
+                        | AnnotationType::DocComment
+                )
+            })
+            .map(|annotation| annotation.start..annotation.end)
+            .collect();
+        let mut levels = [state.paren_level, state.brace_level, state.bracket_level];
+
+        for (byte_idx, ch) in line.char_indices() {
+            if literals.iter().any(|range| range.contains(&byte_idx)) {
+                continue;
+            }
+            for (bracket_idx, bracket) in self.config.brackets.iter().enumerate().take(3) {
+                let level = if ch == bracket.open {
+                    levels[bracket_idx] += 1;
+                    levels[bracket_idx] - 1
+                } else if ch == bracket.close {
+                    levels[bracket_idx] = levels[bracket_idx].saturating_sub(1);
+                    levels[bracket_idx]
+                } else {
+                    continue;
+                };
+                annotations.push(HighlightAnnotation {
+                    annotation_type: BRACKET_TYPES[(level + bracket.color_offset) % 4],
+                    start: byte_idx,
+                    end: byte_idx + ch.len_utf8(),
+                });
+                break;
+            }
+        }
+
+        state.paren_level = levels[0];
+        state.brace_level = levels[1];
+        state.bracket_level = levels[2];
+    }
+}
+
+impl Highlighter for TreeSitterHighlighter {
+    fn update_document(
+        &self,
+        lines: &[Line],
+        edit: Option<&LineEdit>,
+        version: u64,
+    ) -> Option<Range<usize>> {
+        let mut guard = self.document.lock().unwrap_or_else(PoisonError::into_inner);
+        let document = &mut *guard;
+        let last_version = document.version.replace(version);
+        if last_version == Some(version) {
+            return None;
+        }
+
+        let input_edit = match (edit, &document.tree) {
+            (Some(edit), Some(_)) if last_version == Some(edit.since) => {
+                document.splice(lines, edit)
+            }
+            _ => None,
+        };
+        let Some(input_edit) = input_edit else {
+            let (text, line_starts) = join_lines(lines);
+            if document.tree.is_some() && text == document.text {
+                return None;
+            }
+            document.tree = document.parser.parse(&text, None);
+            document.text = text;
+            document.line_starts = line_starts;
+            let line_count = document.line_starts.len();
+            document.annotations = vec![Vec::new(); line_count];
+            document.line_revisions = vec![version; line_count];
+            document.highlight_rows(&self.query, &self.capture_types, 0..line_count, version);
+            return Some(0..line_count);
+        };
+        if let Some(tree) = document.tree.as_mut() {
+            tree.edit(&input_edit);
+        }
+        let old_tree = document.tree.take();
+        document.tree = document.parser.parse(&document.text, old_tree.as_ref());
+        let line_count = document.line_starts.len();
+        let (Some(old_tree), Some(tree)) = (&old_tree, &document.tree) else {
+            document.annotations = vec![Vec::new(); line_count];
+            document.line_revisions = vec![version; line_count];
+            return Some(0..line_count);
+        };
+
+        // Lines outside the edit keep their annotations, which are relative
+        // to the line, and only the edit and the rows whose syntax it changed
+        // are queried again.
+        let edited_rows = input_edit.start_position.row..input_edit.new_end_position.row + 1;
+        let old_rows = input_edit.start_position.row..input_edit.old_end_position.row + 1;
+        let mut dirty_rows: Vec<Range<usize>> = old_tree
+            .changed_ranges(tree)
+            .map(|range| range.start_point.row..range.end_point.row + 1)
+            .chain([edited_rows.clone()])
+            .collect();
+        document.replace_rows(old_rows, edited_rows.clone(), version);
+        dirty_rows.sort_unstable_by_key(|rows| rows.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for rows in dirty_rows {
+            match merged.last_mut() {
+                Some(last) if rows.start <= last.end => last.end = last.end.max(rows.end),
+                _ => merged.push(rows),
+            }
+        }
+        let mut changed = edited_rows;
+        for rows in merged {
+            let rows = rows.start.min(line_count)..rows.end.min(line_count);
+            if let Some(rows) =
+                document.highlight_rows(&self.query, &self.capture_types, rows, version)
+            {
+                changed = changed.start.min(rows.start)..changed.end.max(rows.end);
+            }
+        }
+        Some(changed)
+    }
+
+    fn line_revision(&self, line_idx: usize) -> u64 {
+        let document = self.document.lock().unwrap_or_else(PoisonError::into_inner);
+        document.line_revisions.get(line_idx).copied().unwrap_or(0)
+    }
+
+    fn highlight_line(
+        &self,
+        line: &str,
+        line_idx: usize,
+        mut state: HighlightState,
+    ) -> (Vec<HighlightAnnotation>, HighlightState) {
+        let mut annotations = {
+            let document = self.document.lock().unwrap_or_else(PoisonError::into_inner);
+            if document.line(line_idx) == Some(line) {
+                document.annotations[line_idx].clone()
+            } else {
+                Vec::new()
+            }
+        };
+        push_todo_markers(line, &mut annotations);
+        self.annotate_brackets(line, &mut annotations, &mut state);
+        (annotations, state)
+    }
+
+    fn language_name(&self) -> &str {
+        &self.language_name
+    }
+
+    fn formatter(&self) -> Option<&str> {
+        self.config.formatter.as_deref()
+    }
+}
+
+/// The lines joined by newlines, with the byte offset each starts at.
+fn join_lines(lines: &[Line]) -> (String, Vec<usize>) {
+    let mut text = String::new();
+    let mut line_starts = Vec::with_capacity(lines.len());
+    for (line_idx, line) in lines.iter().enumerate() {
+        if line_idx > 0 {
+            text.push('\n');
+        }
+        line_starts.push(text.len());
+        text.push_str(line);
+    }
+    (text, line_starts)
+}
+
+/// Line `line_idx` of `text`, whose lines start at `line_starts`, without
+/// its newline.
+fn line_in<'a>(text: &'a str, line_starts: &[usize], line_idx: usize) -> Option<&'a str> {
+    let start = *line_starts.get(line_idx)?;
+    text.get(start..line_end_in(text, line_starts, line_idx))
+}
+
+/// The byte offset where line `line_idx` of `text` ends, before its newline.
+fn line_end_in(text: &str, line_starts: &[usize], line_idx: usize) -> usize {
+    line_starts
+        .get(line_idx + 1)
+        .map_or(text.len(), |next| next - 1)
+}
+
+impl Document {
+    fn line(&self, line_idx: usize) -> Option<&str> {
+        line_in(&self.text, &self.line_starts, line_idx)
+    }
+
+    /// Replaces the rows `edit` changed with the new lines there, without
+    /// touching the rest of the text. Returns `None` when the edit does not
+    /// fit the text, which is then rebuilt from scratch.
+    fn splice(&mut self, lines: &[Line], edit: &LineEdit) -> Option<InputEdit> {
+        let line_count = self.line_starts.len();
+        let (mut start, mut old_end, mut new_end) = (
+            edit.start,
+            edit.old_end.min(line_count),
+            edit.new_end.min(lines.len()),
+        );
+        if start > old_end || start > new_end || line_count - old_end != lines.len() - new_end {
+            return None;
+        }
+        // A row on either side that neither removed nor added lines, so that
+        // both the old and the new rows are whole lines.
+        if start == old_end || start == new_end {
+            if old_end < line_count {
+                old_end += 1;
+                new_end += 1;
+            } else if start > 0 {
+                start -= 1;
+            } else {
+                return None;
+            }
+        }
+
+        let start_byte = self.line_starts[start];
+        let old_end_byte = line_end_in(&self.text, &self.line_starts, old_end - 1);
+        let mut new_text = String::new();
+        let mut new_starts = Vec::with_capacity(new_end - start);
+        for (row, line) in lines.iter().enumerate().take(new_end).skip(start) {
+            if row > start {
+                new_text.push('\n');
+            }
+            new_starts.push(start_byte + new_text.len());
+            new_text.push_str(line);
+        }
+        let new_end_byte = start_byte + new_text.len();
+        let input_edit = InputEdit {
+            start_byte,
+            old_end_byte,
+            new_end_byte,
+            start_position: Point::new(start, 0),
+            old_end_position: Point::new(old_end - 1, old_end_byte - self.line_starts[old_end - 1]),
+            new_end_position: Point::new(
+                new_end - 1,
+                new_end_byte - new_starts[new_starts.len() - 1],
+            ),
+        };
+
+        self.text.replace_range(start_byte..old_end_byte, &new_text);
+        for line_start in &mut self.line_starts[old_end..] {
+            *line_start = *line_start + new_end_byte - old_end_byte;
+        }
+        self.line_starts.splice(start..old_end, new_starts);
+        Some(input_edit)
+    }
+
+    /// Swaps the annotations and revisions of `old_rows` for empty lines at
+    /// `new_rows`, which are about to be highlighted, and moves the lines
+    /// after them to their new rows.
+    fn replace_rows(&mut self, old_rows: Range<usize>, new_rows: Range<usize>, version: u64) {
+        let len = self.annotations.len();
+        let old_rows = old_rows.start.min(len)..old_rows.end.min(len);
+        self.annotations
+            .splice(old_rows.clone(), vec![Vec::new(); new_rows.len()]);
+        self.line_revisions
+            .splice(old_rows, vec![version; new_rows.len()]);
+    }
+
+    /// Replaces the annotations of `rows` with the query's captures there.
+    /// As in tree-sitter's own highlighter, the first pattern to capture a
+    /// node decides its type, so the specific patterns at the top of a query
+    /// win. Lines whose annotations change get `version` as their revision,
+    /// and are returned as the range from the first to the last of them.
+    fn highlight_rows(
+        &mut self,
+        query: &Query,
+        capture_types: &[Option<AnnotationType>],
+        rows: Range<usize>,
+        version: u64,
+    ) -> Option<Range<usize>> {
+        let tree = self.tree.as_ref()?;
+        if rows.is_empty() {
+            return None;
+        }
+        let previous: Vec<_> = self.annotations[rows.clone()]
+            .iter_mut()
+            .map(std::mem::take)
+            .collect();
+        let line_end = |row: usize| line_end_in(&self.text, &self.line_starts, row);
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(self.line_starts[rows.start]..line_end(rows.end - 1));
+        let mut captures = cursor.captures(query, tree.root_node(), self.text.as_bytes());
+        let mut seen_nodes = HashSet::new();
+
+        while let Some((query_match, capture_idx)) = captures.next() {
+            let capture = query_match.captures[*capture_idx];
+            let node = capture.node;
+            if !seen_nodes.insert(node.byte_range()) {
+                continue;
+            }
+            let Some(annotation_type) = capture_types[capture.index as usize] else {
+                continue;
+            };
+            let first_row = node.start_position().row.max(rows.start);
+            let last_row = node.end_position().row.min(rows.end - 1);
+            for row in first_row..=last_row {
+                let line_start = self.line_starts[row];
+                let start = node.start_byte().max(line_start) - line_start;
+                let end = node
+                    .end_byte()
+                    .min(line_end(row))
+                    .saturating_sub(line_start);
+                if start < end {
+                    self.annotations[row].push(HighlightAnnotation {
+                        annotation_type,
+                        start,
+                        end,
+                    });
+                }
+            }
+        }
+
+        let mut changed: Option<Range<usize>> = None;
+        for (row, previous) in rows.zip(previous) {
+            if self.annotations[row] != previous {
+                self.line_revisions[row] = version;
+                changed = Some(changed.map_or(row, |changed| changed.start)..row + 1);
+            }
+        }
+        changed
+    }
+}
+
+/// The annotation type for a capture name. Dotted names fall back to their
+/// parent, so `keyword.control` is drawn like `keyword`.
+fn capture_type(name: &str) -> Option<AnnotationType> {
+    match name {
+        "comment" => Some(AnnotationType::Comment),
+        "comment.documentation" => Some(AnnotationType::DocComment),
+        "string" | "character" => Some(AnnotationType::String),
+        "escape" | "string.escape" => Some(AnnotationType::Escape),
+        "number" | "float" | "boolean" | "constant.builtin" => Some(AnnotationType::Number),
+        "constant" => Some(AnnotationType::Constant),
+        "keyword" => Some(AnnotationType::Keyword),
+        "operator" => Some(AnnotationType::Operator),
+        "function" => Some(AnnotationType::Function),
+        "function.macro" => Some(AnnotationType::Macro),
+        "attribute" => Some(AnnotationType::Attribute),
+        "label" | "lifetime" => Some(AnnotationType::Lifetime),
+        "type.builtin" => Some(AnnotationType::PrimitiveType),
+        "type" | "constructor" => Some(AnnotationType::Type),
+        _ => name
+            .rsplit_once('.')
+            .and_then(|(parent, _)| capture_type(parent)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_lifetimes_macros_and_raw_strings() {
+        let highlighter = TreeSitterHighlighter::rust().expect("bundled grammar");
+        let lines: Vec<Line> = [
+            "fn f<'a>(x: &'a str) -> Vec<u8> {",
+            "    println!(r#\"{x}\"#)",
+            "}",
+        ]
+        .iter()
+        .map(|line| Line::from(line))
+        .collect();
+        highlighter.update_document(&lines, None, 0);
+
+        let typed = |line_idx: usize, text: &str| {
+            let line = lines[line_idx].to_string();
+            let start = line.find(text).unwrap();
+            highlighter
+                .highlight_line(&line, line_idx, HighlightState::default())
+                .0
+                .into_iter()
+                .filter(|annotation| {
+                    annotation.start == start && annotation.end == start + text.len()
+                })
+                .map(|annotation| annotation.annotation_type)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(typed(0, "fn"), vec![AnnotationType::Keyword]);
+        assert_eq!(typed(0, "'a"), vec![AnnotationType::Lifetime]);
+        assert_eq!(typed(0, "Vec"), vec![AnnotationType::Type]);
+        assert_eq!(typed(0, "u8"), vec![AnnotationType::PrimitiveType]);
+        assert_eq!(typed(1, "println"), vec![AnnotationType::Macro]);
+        assert_eq!(typed(1, "r#\"{x}\"#"), vec![AnnotationType::String]);
+
+        let edited: Vec<Line> = ["fn f<'a>(x: &'a str) -> Vec<u8> {", "    // done", "}"]
+            .iter()
+            .map(|line| Line::from(line))
+            .collect();
+        let edit = LineEdit {
+            since: 0,
+            start: 1,
+            old_end: 2,
+            new_end: 2,
+        };
+        highlighter.update_document(&edited, Some(&edit), 1);
+        assert_eq!(
+            highlighter
+                .highlight_line("    // done", 1, HighlightState::default())
+                .0
+                .iter()
+                .map(|annotation| annotation.annotation_type)
+                .collect::<Vec<_>>(),
+            vec![AnnotationType::Comment]
+        );
+        assert_eq!(highlighter.line_revision(0), 0);
+        assert_eq!(highlighter.line_revision(1), 1);
+    }
+
+    #[test]
+    fn parses_edited_lines_again_like_from_scratch() {
+        let highlighter = TreeSitterHighlighter::rust().expect("bundled grammar");
+        let lines: Vec<Line> = ["fn f<'a>(x: &'a str) -> Vec<u8> {", "    // done", "}"]
+            .iter()
+            .map(|line| Line::from(line))
+            .collect();
+        highlighter.update_document(&lines, None, 0);
+
+        // Opening a block comment recolors the lines after it as well, the
+        // same as highlighting the new text from scratch would.
+        let edited: Vec<Line> = [
+            "fn f<'a>(x: &'a str) -> Vec<u8> {",
+            "    /* done",
+            "    let y = x; // */",
+            "}",
+        ]
+        .iter()
+        .map(|line| Line::from(line))
+        .collect();
+        let edit = LineEdit {
+            since: 0,
+            start: 1,
+            old_end: 2,
+            new_end: 3,
+        };
+        highlighter.update_document(&edited, Some(&edit), 1);
+        let spans = |highlighter: &TreeSitterHighlighter, lines: &[Line], line_idx: usize| {
+            highlighter
+                .highlight_line(&lines[line_idx], line_idx, HighlightState::default())
+                .0
+                .into_iter()
+                .map(|annotation| (annotation.annotation_type, annotation.start, annotation.end))
+                .collect::<Vec<_>>()
+        };
+        let assert_same_as_fresh = |lines: &[Line]| {
+            let fresh = TreeSitterHighlighter::rust().expect("bundled grammar");
+            fresh.update_document(lines, None, 0);
+            for line_idx in 0..lines.len() {
+                assert_eq!(
+                    spans(&highlighter, lines, line_idx),
+                    spans(&fresh, lines, line_idx)
+                );
+            }
+        };
+        assert_same_as_fresh(&edited);
+        assert_eq!(
+            spans(&highlighter, &edited, 2)[0].0,
+            AnnotationType::Comment
+        );
+        assert_eq!(highlighter.line_revision(0), 0);
+        assert_eq!(highlighter.line_revision(2), 1);
+
+        // Removing the last lines and appending new ones.
+        let mut edited = edited;
+        edited.truncate(2);
+        highlighter.update_document(
+            &edited,
+            Some(&LineEdit {
+                since: 1,
+                start: 2,
+                old_end: 4,
+                new_end: 2,
+            }),
+            2,
+        );
+        assert_same_as_fresh(&edited);
+        edited.extend(["    */", "}"].iter().map(|line| Line::from(line)));
+        highlighter.update_document(
+            &edited,
+            Some(&LineEdit {
+                since: 2,
+                start: 2,
+                old_end: 2,
+                new_end: 4,
+            }),
+            3,
+        );
+        assert_same_as_fresh(&edited);
+        assert_eq!(
+            spans(&highlighter, &edited, 1)[0].0,
+            AnnotationType::Comment
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use plist::{Dictionary, Value};
+use std::fmt::Write;
+
+/// Converts a grammar read from a `.tmLanguage` plist to the text of the
+/// `.sublime-syntax` that behaves the same, which is the format syntect
+/// loads. A `begin`/`end` rule pushes a context that pops at `end`, and each
+/// repository entry becomes a named context.
+pub fn to_sublime_syntax(grammar: &Value) -> Option<String> {
+    let grammar = grammar.as_dictionary()?;
+    let mut converter = Converter::default();
+    let main = converter.patterns(grammar.get("patterns"));
+    converter
+        .contexts
+        .insert("main".to_string(), Value::Array(main));
+    if let Some(repository) = grammar.get("repository").and_then(Value::as_dictionary) {
+        for (key, rule) in repository {
+            let context = converter.context(rule);
+            converter
+                .contexts
+                .insert(repository_context(key), Value::Array(context));
+        }
+    }
+
+    let mut syntax = Dictionary::new();
+    for (from, to) in [
+        ("name", "name"),
+        ("scopeName", "scope"),
+        ("fileTypes", "file_extensions"),
+        ("firstLineMatch", "first_line_match"),
+    ] {
+        if let Some(value) = grammar.get(from) {
+            syntax.insert(to.to_string(), value.clone());
+        }
+    }
+    syntax.insert(
+        "contexts".to_string(),
+        Value::Dictionary(converter.contexts),
+    );
+    let mut text = String::new();
+    write_yaml(&Value::Dictionary(syntax), &mut text);
+    Some(text)
+}
+
+#[derive(Default)]
+struct Converter {
+    contexts: Dictionary,
+    /// Rules that only group `patterns` become contexts of their own, as a
+    /// context cannot be written inline where an include is expected.
+    groups: usize,
+}
+
+impl Converter {
+    fn patterns(&mut self, patterns: Option<&Value>) -> Vec<Value> {
+        patterns
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|rule| self.rule(rule))
+            .collect()
+    }
+
+    /// The context of a repository entry. One that only groups other rules
+    /// is their list rather than an include of it.
+    fn context(&mut self, rule: &Value) -> Vec<Value> {
+        match rule.as_dictionary() {
+            Some(dict)
+                if ["include", "match", "begin"]
+                    .iter()
+                    .all(|key| !dict.contains_key(key)) =>
+            {
+                self.patterns(dict.get("patterns"))
+            }
+            _ => self.rule(rule).into_iter().collect(),
+        }
+    }
+
+    fn rule(&mut self, rule: &Value) -> Option<Value> {
+        let rule = rule.as_dictionary()?;
+        if rule.get("disabled").and_then(Value::as_signed_integer) == Some(1) {
+            return None;
+        }
+        let string = |key: &str| rule.get(key).and_then(Value::as_string);
+        let mut item = Dictionary::new();
+        if let Some(include) = string("include") {
+            item.insert("include".to_string(), include_reference(include).into());
+        } else if let Some(pattern) = string("match") {
+            item.insert("match".to_string(), pattern.into());
+            if let Some(scope) = string("name") {
+                item.insert("scope".to_string(), scope.into());
+            }
+            insert_captures(&mut item, rule.get("captures"));
+        } else if let (Some(begin), Some(end)) = (string("begin"), string("end")) {
+            let mut context = Vec::new();
+            for (from, to) in [
+                ("name", "meta_scope"),
+                ("contentName", "meta_content_scope"),
+            ] {
+                if let Some(scope) = string(from) {
+                    let mut meta = Dictionary::new();
+                    meta.insert(to.to_string(), scope.into());
+                    context.push(Value::Dictionary(meta));
+                }
+            }
+            // TextMate tries `end` before the rules inside, as does a
+            // context trying its patterns in order.
+            let mut pop = Dictionary::new();
+            pop.insert("match".to_string(), end.into());
+            insert_captures(
+                &mut pop,
+                rule.get("endCaptures").or_else(|| rule.get("captures")),
+            );
+            pop.insert("pop".to_string(), Value::Boolean(true));
+            context.push(Value::Dictionary(pop));
+            context.extend(self.patterns(rule.get("patterns")));
+
+            item.insert("match".to_string(), begin.into());
+            insert_captures(
+                &mut item,
+                rule.get("beginCaptures").or_else(|| rule.get("captures")),
+            );
+            item.insert("push".to_string(), Value::Array(context));
+        } else if rule.contains_key("patterns") {
+            let name = format!("group.{}", self.groups);
+            self.groups += 1;
+            let context = self.patterns(rule.get("patterns"));
+            self.contexts.insert(name.clone(), Value::Array(context));
+            item.insert("include".to_string(), name.into());
+        } else {
+            return None;
+        }
+        Some(Value::Dictionary(item))
+    }
+}
+
+/// `{"1": {"name": "scope"}}` captures as Sublime's `{1: "scope"}`.
+fn insert_captures(item: &mut Dictionary, captures: Option<&Value>) {
+    let Some(captures) = captures.and_then(Value::as_dictionary) else {
+        return;
+    };
+    let captures: Dictionary = captures
+        .iter()
+        .filter_map(|(group, capture)| {
+            let scope = capture.as_dictionary()?.get("name")?.as_string()?;
+            Some((group.clone(), Value::from(scope)))
+        })
+        .collect();
+    if !captures.is_empty() {
+        item.insert("captures".to_string(), Value::Dictionary(captures));
+    }
+}
+
+fn include_reference(include: &str) -> String {
+    match include {
+        "$self" | "$base" => "main".to_string(),
+        _ => match include.split_once('#') {
+            Some(("", key)) => repository_context(key),
+            Some((scope, key)) => format!("scope:{scope}#{}", repository_context(key)),
+            None => format!("scope:{include}"),
+        },
+    }
+}
+
+fn repository_context(key: &str) -> String {
+    format!("repository.{key}")
+}
+
+/// Writes `value` in YAML's flow style, which reads like JSON, except that
+/// capture groups are written as the numbers Sublime expects.
+fn write_yaml(value: &Value, text: &mut String) {
+    match value {
+        Value::Dictionary(dict) => {
+            text.push('{');
+            for (idx, (key, value)) in dict.iter().enumerate() {
+                if idx > 0 {
+                    text.push_str(", ");
+                }
+                if !key.is_empty() && key.bytes().all(|byte| byte.is_ascii_digit()) {
+                    text.push_str(key);
+                } else {
+                    write_string(key, text);
+                }
+                text.push_str(": ");
+                write_yaml(value, text);
+            }
+            text.push('}');
+        }
+        Value::Array(items) => {
+            text.push('[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    text.push_str(", ");
+                }
+                write_yaml(item, text);
+            }
+            text.push(']');
+        }
+        Value::Boolean(value) => text.push_str(if *value { "true" } else { "false" }),
+        Value::String(string) => write_string(string, text),
+        _ => text.push_str("null"),
+    }
+}
+
+fn write_string(string: &str, text: &mut String) {
+    text.push('"');
+    for ch in string.chars() {
+        match ch {
+            '"' | '\\' => {
+                text.push('\\');
+                text.push(ch);
+            }
+            ch if ch.is_control() => {
+                let _ = write!(text, "\\u{:04x}", u32::from(ch));
+            }
+            ch => text.push(ch),
+        }
+    }
+    text.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::parsing::{ParseState, ScopeStackOp, SyntaxDefinition, SyntaxSetBuilder};
+
+    #[test]
+    fn converts_begin_end_rules_captures_and_repository_includes() {
+        let grammar = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>name</key><string>Test</string>
+    <key>scopeName</key><string>source.test</string>
+    <key>fileTypes</key><array><string>test</string></array>
+    <key>patterns</key>
+    <array>
+        <dict><key>include</key><string>#strings</string></dict>
+        <dict>
+            <key>match</key><string>\b(let)\s+(\w+)</string>
+            <key>captures</key>
+            <dict>
+                <key>1</key><dict><key>name</key><string>keyword.test</string></dict>
+                <key>2</key><dict><key>name</key><string>variable.test</string></dict>
+            </dict>
+        </dict>
+    </array>
+    <key>repository</key>
+    <dict>
+        <key>strings</key>
+        <dict>
+            <key>begin</key><string>"</string>
+            <key>end</key><string>"</string>
+            <key>name</key><string>string.quoted.test</string>
+            <key>patterns</key>
+            <array>
+                <dict>
+                    <key>match</key><string>\\.</string>
+                    <key>name</key><string>constant.character.escape.test</string>
+                </dict>
+            </array>
+        </dict>
+    </dict>
+</dict>
+</plist>"#;
+        let grammar = Value::from_reader_xml(grammar.as_bytes()).expect("valid plist");
+        let text = to_sublime_syntax(&grammar).expect("a dictionary");
+        let syntax = SyntaxDefinition::load_from_str(&text, true, None).expect("valid syntax");
+        assert_eq!(syntax.name, "Test");
+        assert_eq!(syntax.file_extensions, vec!["test".to_string()]);
+
+        let mut builder = SyntaxSetBuilder::new();
+        builder.add(syntax);
+        let syntax_set = builder.build();
+        let mut state = ParseState::new(&syntax_set.syntaxes()[0]);
+        let pushes: Vec<(usize, String)> = state
+            .parse_line("let s = \"a\\n\"\n", &syntax_set)
+            .expect("parses")
+            .into_iter()
+            .filter_map(|(idx, op)| match op {
+                ScopeStackOp::Push(scope) => Some((idx, scope.build_string())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            pushes,
+            vec![
+                (0, "source.test".to_string()),
+                (0, "keyword.test".to_string()),
+                (4, "variable.test".to_string()),
+                (8, "string.quoted.test".to_string()),
+                (10, "constant.character.escape.test".to_string()),
+            ]
+        );
+    }
+}
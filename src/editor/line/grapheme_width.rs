@@ -1,7 +1,24 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+pub const MAX_TAB_WIDTH: usize = 16;
+
+static TAB_WIDTH: AtomicUsize = AtomicUsize::new(DEFAULT_TAB_WIDTH);
+
+/// Sets how many columns a tab occupies, clamped to `1..=MAX_TAB_WIDTH`.
+pub fn set_tab_width(width: usize) {
+    TAB_WIDTH.store(width.clamp(1, MAX_TAB_WIDTH), Ordering::Relaxed);
+}
+
+pub fn tab_width() -> usize {
+    TAB_WIDTH.load(Ordering::Relaxed)
+}
+
 #[derive(Copy, Clone)]
 pub enum GraphemeWidth {
     Half,
     Full,
+    Tab,
 }
 
 impl From<GraphemeWidth> for usize {
@@ -9,6 +26,7 @@ impl From<GraphemeWidth> for usize {
         match val {
             GraphemeWidth::Half => 1,
             GraphemeWidth::Full => 2,
+            GraphemeWidth::Tab => tab_width(),
         }
     }
 }
@@ -6,8 +6,12 @@ use std::{
 
 mod grapheme_width;
 mod text_fragment;
+mod whitespace;
 use grapheme_width::GraphemeWidth;
+pub use grapheme_width::{MAX_TAB_WIDTH, set_tab_width, tab_width};
 use text_fragment::TextFragment;
+use whitespace::whitespace_glyphs;
+pub use whitespace::{indent_guides, set_indent_guides, set_show_whitespace, show_whitespace};
 
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
@@ -16,6 +20,13 @@ use super::highlight::{HighlightAnnotation, Highlighter};
 use super::{AnnotatedString, AnnotationType};
 use crate::editor::highlight::HighlightState;
 
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum CharClass {
+    Space,
+    Word,
+    Punctuation,
+}
+
 #[derive(Default, Clone)]
 pub struct Line {
     fragments: Vec<TextFragment>,
@@ -48,7 +59,9 @@ impl Line {
                             (None, rendered_width)
                         },
                         |replacement| {
-                            let rendered_width = if grapheme.width_cjk() > 1 {
+                            let rendered_width = if grapheme == "\t" {
+                                GraphemeWidth::Tab
+                            } else if grapheme.width_cjk() > 1 {
                                 GraphemeWidth::Full
                             } else {
                                 GraphemeWidth::Half
@@ -111,6 +124,7 @@ impl Line {
         self.fragments.len()
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn get_annotated_visible_substr(
         &self,
         range: Range<usize>,
@@ -183,34 +197,96 @@ impl Line {
             result.truncate_right_from(byte_end);
         }
 
+        self.replace_visible_fragments(
+            &mut result,
+            byte_start..byte_end,
+            show_whitespace(),
+            indent_guides(),
+        );
+
+        (result, new_state)
+    }
+
+    /// Swaps graphemes in `visible` (byte range of the line) for what is drawn
+    /// in their place. Works from the end of the line, so the byte offsets of
+    /// fragments still to be replaced stay valid.
+    fn replace_visible_fragments(
+        &self,
+        result: &mut AnnotatedString,
+        visible: Range<usize>,
+        show_whitespace: bool,
+        indent_guides: bool,
+    ) {
+        let indentation_end = self.string.len() - self.string.trim_start().len();
+        let indentation = &self.string[..indentation_end];
+        let mixed_indentation = indentation.contains(' ') && indentation.contains('\t');
+        let trailing_start = self.string.trim_end().len();
+        let indent_width = tab_width();
+
+        let mut columns = Vec::with_capacity(self.fragments.len());
+        let mut column = 0;
         for fragment in &self.fragments {
-            if fragment.start >= byte_end
-                || fragment.start.saturating_add(fragment.grapheme.len()) <= byte_start
+            columns.push(column);
+            column += usize::from(fragment.rendered_width);
+        }
+
+        for (fragment, column) in self.fragments.iter().zip(columns).rev() {
+            if fragment.start >= visible.end
+                || fragment.start.saturating_add(fragment.grapheme.len()) <= visible.start
             {
                 continue;
             }
-
-            if let Some(replacement) = fragment.replacement {
-                let start = fragment.start.saturating_sub(byte_start);
-                let end = start.saturating_add(fragment.grapheme.len());
-                let result_len = result.to_string().len();
-                if start < result_len && end <= result_len {
-                    result.replace(start, end, &replacement.to_string());
-                }
+            let width = usize::from(fragment.rendered_width);
+            let in_indentation = fragment.start < indentation_end;
+            let glyphs = whitespace_glyphs(
+                &fragment.grapheme,
+                column,
+                width,
+                in_indentation,
+                indent_width,
+                show_whitespace,
+                indent_guides,
+            );
+            let replacement = glyphs.clone().or_else(|| {
+                fragment.replacement.map(|replacement| {
+                    let repeat = if matches!(fragment.rendered_width, GraphemeWidth::Tab) {
+                        width
+                    } else {
+                        1
+                    };
+                    replacement.to_string().repeat(repeat)
+                })
+            });
+            let Some(replacement) = replacement else {
+                continue;
+            };
+            let start = fragment.start.saturating_sub(visible.start);
+            let end = start.saturating_add(fragment.grapheme.len());
+            let result_len = result.to_string().len();
+            if start >= result_len || end > result_len {
+                continue;
+            }
+            result.replace(start, end, &replacement);
+
+            let is_misplaced = show_whitespace
+                && (fragment.start >= trailing_start || (in_indentation && mixed_indentation));
+            if is_misplaced {
+                result.add_annotation(
+                    AnnotationType::WhitespaceError,
+                    start,
+                    start + replacement.len(),
+                );
+            } else if glyphs.is_some() {
+                result.add_annotation(AnnotationType::Whitespace, start, start + replacement.len());
             }
         }
-
-        (result, new_state)
     }
 
     pub fn width_until(&self, grapheme_idx: usize) -> usize {
         self.fragments
             .iter()
             .take(grapheme_idx)
-            .map(|fragment| match fragment.rendered_width {
-                GraphemeWidth::Half => 1,
-                GraphemeWidth::Full => 2,
-            })
+            .map(|fragment| usize::from(fragment.rendered_width))
             .sum()
     }
 
@@ -249,14 +325,6 @@ impl Line {
         self.width_until(self.grapheme_count())
     }
 
-    pub fn append_char(&mut self, character: char) {
-        self.insert_char(character, self.grapheme_count());
-    }
-
-    pub fn delete_last(&mut self) {
-        self.delete(self.grapheme_count().saturating_sub(1));
-    }
-
     pub fn delete(&mut self, at: usize) {
         if let Some(fragment) = self.fragments.get(at) {
             let start = fragment.start;
@@ -266,6 +334,47 @@ impl Line {
         }
     }
 
+    /// Classifies the grapheme at `grapheme_idx` for word motions, or `None`
+    /// past the end of the line.
+    pub fn char_class_at(&self, grapheme_idx: usize) -> Option<CharClass> {
+        self.fragments.get(grapheme_idx).map(|fragment| {
+            if fragment.grapheme.trim().is_empty() {
+                CharClass::Space
+            } else if fragment
+                .grapheme
+                .chars()
+                .all(|ch| ch.is_alphanumeric() || ch == '_')
+            {
+                CharClass::Word
+            } else {
+                CharClass::Punctuation
+            }
+        })
+    }
+
+    /// Returns the grapheme index where the word before `grapheme_idx` starts,
+    /// skipping any whitespace directly before it first.
+    pub fn previous_word_start(&self, grapheme_idx: usize) -> usize {
+        let mut idx = min(grapheme_idx, self.grapheme_count());
+        while idx > 0 && self.char_class_at(idx - 1) == Some(CharClass::Space) {
+            idx -= 1;
+        }
+        if idx == 0 {
+            return 0;
+        }
+        let word_class = self.char_class_at(idx - 1);
+        while idx > 0 && self.char_class_at(idx - 1) == word_class {
+            idx -= 1;
+        }
+        idx
+    }
+
+    pub fn delete_grapheme_range(&mut self, range: Range<usize>) {
+        let start = self.grapheme_to_byte_idx(range.start);
+        let end = self.grapheme_to_byte_idx(range.end);
+        self.delete_byte_range(start..end);
+    }
+
     pub fn delete_byte_range(&mut self, range: Range<usize>) {
         if range.is_empty() {
             return;
@@ -351,6 +460,16 @@ impl Line {
             .map(|(_, grapheme_idx)| *grapheme_idx)
     }
 
+    pub fn match_grapheme_indices(&self, query: &str) -> Vec<usize> {
+        if query.is_empty() {
+            return Vec::new();
+        }
+        self.find_all(query, 0..self.string.len())
+            .into_iter()
+            .map(|(_, grapheme_idx)| grapheme_idx)
+            .collect()
+    }
+
     fn find_all(&self, query: &str, range: Range<usize>) -> Vec<(usize, usize)> {
         let end = min(range.end, self.string.len());
         let start = range.start;
@@ -429,4 +548,38 @@ mod tests {
         }
         assert!(found_selection, "Selection annotation should cover 'c'");
     }
+
+    #[test]
+    fn whitespace_rendering_flags_trailing_and_mixed_indentation() {
+        let line = Line::from(" \tx y  ");
+        let mut ann = AnnotatedString::from(&line.string);
+        ann.add_annotation(AnnotationType::Selection, 0, 1);
+        line.replace_visible_fragments(&mut ann, 0..line.string.len(), true, false);
+        let parts: Vec<_> = (&ann)
+            .into_iter()
+            .map(|part| (part.string.to_string(), part.annotation_type))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                ("·".to_string(), Some(AnnotationType::Selection)),
+                ("→   ".to_string(), Some(AnnotationType::WhitespaceError)),
+                ("x".to_string(), None),
+                ("·".to_string(), Some(AnnotationType::Whitespace)),
+                ("y".to_string(), None),
+                ("·".to_string(), Some(AnnotationType::WhitespaceError)),
+                ("·".to_string(), Some(AnnotationType::WhitespaceError)),
+            ]
+        );
+    }
+
+    #[test]
+    fn previous_word_start_skips_whitespace_then_word() {
+        let line = Line::from("let foo_bar = baz.qux  ");
+        assert_eq!(line.previous_word_start(line.grapheme_count()), 18);
+        assert_eq!(line.previous_word_start(18), 17);
+        assert_eq!(line.previous_word_start(17), 14);
+        assert_eq!(line.previous_word_start(11), 4);
+        assert_eq!(line.previous_word_start(0), 0);
+    }
 }
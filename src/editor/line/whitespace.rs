@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const SPACE_GLYPH: char = '·';
+const TAB_GLYPH: char = '→';
+const INDENT_GUIDE_GLYPH: char = '│';
+
+static SHOW_WHITESPACE: AtomicBool = AtomicBool::new(false);
+static INDENT_GUIDES: AtomicBool = AtomicBool::new(false);
+
+/// Draws spaces and tabs as visible glyphs and highlights trailing
+/// whitespace and mixed indentation.
+pub fn set_show_whitespace(enabled: bool) {
+    SHOW_WHITESPACE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn show_whitespace() -> bool {
+    SHOW_WHITESPACE.load(Ordering::Relaxed)
+}
+
+/// Draws a guide in the indentation at every tab width.
+pub fn set_indent_guides(enabled: bool) {
+    INDENT_GUIDES.store(enabled, Ordering::Relaxed);
+}
+
+pub fn indent_guides() -> bool {
+    INDENT_GUIDES.load(Ordering::Relaxed)
+}
+
+/// What a space or tab starting at `column` and `width` columns wide is drawn
+/// as, or `None` to draw it as usual. Guides only apply inside the
+/// indentation and sit on multiples of `indent_width`. Takes the two
+/// settings rather than reading them, so one frame draws with one set.
+pub fn whitespace_glyphs(
+    grapheme: &str,
+    column: usize,
+    width: usize,
+    in_indentation: bool,
+    indent_width: usize,
+    show_whitespace: bool,
+    indent_guides: bool,
+) -> Option<String> {
+    if grapheme != " " && grapheme != "\t" {
+        return None;
+    }
+    let guides = in_indentation && indent_guides;
+    if !show_whitespace && !guides {
+        return None;
+    }
+    let glyphs: String = (column..column + width)
+        .map(|col| {
+            if guides && col % indent_width.max(1) == 0 {
+                INDENT_GUIDE_GLYPH
+            } else if show_whitespace && col == column {
+                if grapheme == "\t" {
+                    TAB_GLYPH
+                } else {
+                    SPACE_GLYPH
+                }
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    (!glyphs.trim().is_empty()).then_some(glyphs)
+}
@@ -0,0 +1,81 @@
+use super::{
+    command::{Command, System},
+    highlight::config_file::{get_config_dir, load_editor_config},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Error, ErrorKind},
+    path::PathBuf,
+};
+
+const MACROS_FILE_NAME: &str = "macros.toml";
+
+/// Named keyboard macros. When `[macros] persist = true` is set in
+/// config.toml they are kept in `macros.toml` in the config directory.
+#[derive(Serialize, Deserialize, Default)]
+pub struct MacroStore {
+    #[serde(default)]
+    macros: BTreeMap<String, Vec<Command>>,
+    #[serde(skip)]
+    persist: bool,
+}
+
+impl MacroStore {
+    fn macros_path() -> Option<PathBuf> {
+        get_config_dir().ok().map(|dir| dir.join(MACROS_FILE_NAME))
+    }
+
+    pub fn load() -> Self {
+        let persist = load_editor_config(None)
+            .ok()
+            .and_then(|config| config.macros)
+            .and_then(|macros| macros.persist)
+            .unwrap_or(false);
+        if !persist {
+            return Self::default();
+        }
+        let mut store: Self = Self::macros_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        store.persist = true;
+        store
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[Command]> {
+        self.macros.get(name).map(Vec::as_slice)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.macros.keys().cloned().collect()
+    }
+
+    /// Stores a macro under `name`, replacing any macro of the same name, and
+    /// writes the store to disk if persistence is enabled.
+    pub fn insert(&mut self, name: &str, commands: Vec<Command>) -> Result<(), Error> {
+        self.macros.insert(name.to_string(), commands);
+        if self.persist { self.save() } else { Ok(()) }
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let path = Self::macros_path()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no config directory"))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents =
+            toml::to_string(self).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        fs::write(path, contents)
+    }
+}
+
+/// Commands that control recording and playback themselves, or only make
+/// sense at the moment they happen, are never recorded.
+pub const fn is_recordable(command: Command) -> bool {
+    !matches!(
+        command,
+        Command::System(System::RecordMacro | System::PlayMacro | System::Resize(_))
+    )
+}
@@ -0,0 +1,416 @@
+use super::{
+    command::{Command, Edit, MoveDirection, System},
+    folding::FoldCommand,
+};
+
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+pub enum Mode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::Insert => "INSERT",
+            Self::Visual => "VISUAL",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `w`
+    WordForward,
+    /// `b`
+    WordBackward,
+    /// `e`
+    WordEnd,
+    /// `0`
+    LineStart,
+    /// `$`
+    LineEnd,
+    /// `gg`
+    FirstLine,
+    /// `G`
+    LastLine,
+    /// `Ngg` or `NG`, 1-based.
+    Line(usize),
+}
+
+impl Motion {
+    /// Linewise motions make operators act on whole lines.
+    pub const fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Self::Up | Self::Down | Self::FirstLine | Self::LastLine | Self::Line(_)
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+/// Where insert mode starts: `i`, `a`, `I`, `A`, `o` and `O`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InsertAt {
+    Caret,
+    AfterCaret,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+/// What the letter typed after `m`, `'` or `` ` `` names a mark for.
+#[derive(Clone, Copy)]
+enum MarkKey {
+    Set,
+    Jump,
+}
+
+#[derive(Clone, Copy)]
+pub enum ModalAction {
+    /// Handled exactly as in modeless editing.
+    Command(Command),
+    Move {
+        motion: Motion,
+        count: usize,
+    },
+    Operate {
+        operator: Operator,
+        motion: Motion,
+        count: usize,
+    },
+    /// `dd`, `cc` and `yy`.
+    OperateLines {
+        operator: Operator,
+        count: usize,
+    },
+    OperateSelection(Operator),
+    Paste {
+        before: bool,
+    },
+    Insert(InsertAt),
+    EnterVisual,
+    /// Back to normal mode from insert or visual mode.
+    EnterNormal,
+    /// `mx`
+    SetMark(char),
+    /// `'x` or `` `x ``
+    JumpToMark(char),
+    /// `zc`, `zo`, `za`, `zM` and `zR`.
+    Fold(FoldCommand),
+}
+
+/// Turns the commands produced by the regular key mapping into vi-style
+/// actions. Counts, pending operators and the `g` prefix are collected here
+/// until a complete action has been typed.
+#[derive(Default)]
+pub struct ModalState {
+    mode: Mode,
+    count: Option<usize>,
+    operator: Option<(Operator, usize)>,
+    pending_g: bool,
+    pending_mark: Option<MarkKey>,
+    pending_z: bool,
+}
+
+impl ModalState {
+    pub const fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    fn reset_pending(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.pending_g = false;
+        self.pending_mark = None;
+        self.pending_z = false;
+    }
+
+    pub fn handle(&mut self, command: Command) -> Option<ModalAction> {
+        if self.mode == Mode::Insert {
+            return Some(if matches!(command, Command::System(System::Dismiss)) {
+                self.mode = Mode::Normal;
+                ModalAction::EnterNormal
+            } else {
+                ModalAction::Command(command)
+            });
+        }
+
+        match command {
+            Command::System(System::Dismiss) => {
+                let had_pending = self.count.is_some()
+                    || self.operator.is_some()
+                    || self.pending_g
+                    || self.pending_mark.is_some()
+                    || self.pending_z;
+                self.reset_pending();
+                if self.mode == Mode::Visual {
+                    self.mode = Mode::Normal;
+                    Some(ModalAction::EnterNormal)
+                } else if had_pending {
+                    None
+                } else {
+                    Some(ModalAction::Command(command))
+                }
+            }
+            Command::Move(move_command) => {
+                let motion = match move_command.direction {
+                    MoveDirection::Up => Motion::Up,
+                    MoveDirection::Down => Motion::Down,
+                    MoveDirection::Left => Motion::Left,
+                    MoveDirection::Right => Motion::Right,
+                    MoveDirection::LineStart => Motion::LineStart,
+                    MoveDirection::LineEnd => Motion::LineEnd,
+                    MoveDirection::PageUp | MoveDirection::PageDown => {
+                        self.reset_pending();
+                        return Some(ModalAction::Command(command));
+                    }
+                };
+                Some(self.motion(motion))
+            }
+            Command::Edit(Edit::Insert(ch)) => self.key(ch),
+            Command::Edit(Edit::InsertNewline) => Some(self.motion(Motion::Down)),
+            Command::Edit(Edit::Backspace) => Some(self.motion(Motion::Left)),
+            Command::Edit(Edit::Delete) => self.key('x'),
+            Command::Edit(_) | Command::System(_) => {
+                self.reset_pending();
+                Some(ModalAction::Command(command))
+            }
+        }
+    }
+
+    /// Completes a key typed after the `m`, `'`, `z` or `g` prefix.
+    fn prefixed_key(&mut self, ch: char) -> Option<ModalAction> {
+        if let Some(mark_key) = self.pending_mark {
+            self.reset_pending();
+            return Some(match mark_key {
+                MarkKey::Set => ModalAction::SetMark(ch),
+                MarkKey::Jump => ModalAction::JumpToMark(ch),
+            });
+        }
+        if self.pending_z {
+            self.reset_pending();
+            let command = match ch {
+                'c' => FoldCommand::Close,
+                'o' => FoldCommand::Open,
+                'a' => FoldCommand::Toggle,
+                'M' => FoldCommand::CloseAll,
+                'R' => FoldCommand::OpenAll,
+                _ => return None,
+            };
+            return Some(ModalAction::Fold(command));
+        }
+        self.pending_g = false;
+        if ch == 'g' {
+            let motion = self.count.take().map_or(Motion::FirstLine, Motion::Line);
+            Some(self.motion(motion))
+        } else {
+            self.reset_pending();
+            None
+        }
+    }
+
+    fn key(&mut self, ch: char) -> Option<ModalAction> {
+        if self.pending_mark.is_some() || self.pending_z || self.pending_g {
+            return self.prefixed_key(ch);
+        }
+
+        match ch {
+            '0' if self.count.is_none() => Some(self.motion(Motion::LineStart)),
+            '0'..='9' => {
+                let digit = ch.to_digit(10).map_or(0, |digit| digit as usize);
+                self.count = Some(
+                    self.count
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add(digit),
+                );
+                None
+            }
+            'g' => {
+                self.pending_g = true;
+                None
+            }
+            'z' => {
+                self.pending_z = true;
+                None
+            }
+            'm' => {
+                self.pending_mark = Some(MarkKey::Set);
+                None
+            }
+            '\'' | '`' => {
+                self.pending_mark = Some(MarkKey::Jump);
+                None
+            }
+            'h' => Some(self.motion(Motion::Left)),
+            'l' | ' ' => Some(self.motion(Motion::Right)),
+            'k' => Some(self.motion(Motion::Up)),
+            'j' => Some(self.motion(Motion::Down)),
+            'w' => Some(self.motion(Motion::WordForward)),
+            'b' => Some(self.motion(Motion::WordBackward)),
+            'e' => Some(self.motion(Motion::WordEnd)),
+            '$' => Some(self.motion(Motion::LineEnd)),
+            'G' => {
+                let motion = self.count.take().map_or(Motion::LastLine, Motion::Line);
+                Some(self.motion(motion))
+            }
+            'd' => self.operator(Operator::Delete),
+            'c' => self.operator(Operator::Change),
+            'y' => self.operator(Operator::Yank),
+            'x' if self.mode == Mode::Visual => self.operator(Operator::Delete),
+            'x' => {
+                let count = self.take_count();
+                self.operator = None;
+                Some(ModalAction::Operate {
+                    operator: Operator::Delete,
+                    motion: Motion::Right,
+                    count,
+                })
+            }
+            'p' | 'P' => {
+                self.reset_pending();
+                Some(ModalAction::Paste { before: ch == 'P' })
+            }
+            'v' if self.mode == Mode::Visual => {
+                self.reset_pending();
+                self.mode = Mode::Normal;
+                Some(ModalAction::EnterNormal)
+            }
+            'v' => {
+                self.reset_pending();
+                self.mode = Mode::Visual;
+                Some(ModalAction::EnterVisual)
+            }
+            'i' | 'a' | 'I' | 'A' | 'o' | 'O' if self.mode == Mode::Normal => {
+                self.reset_pending();
+                self.mode = Mode::Insert;
+                Some(ModalAction::Insert(match ch {
+                    'i' => InsertAt::Caret,
+                    'a' => InsertAt::AfterCaret,
+                    'I' => InsertAt::LineStart,
+                    'A' => InsertAt::LineEnd,
+                    'o' => InsertAt::LineBelow,
+                    _ => InsertAt::LineAbove,
+                }))
+            }
+            _ => {
+                self.reset_pending();
+                None
+            }
+        }
+    }
+
+    /// Counts typed before the operator and before the motion multiply, as in
+    /// `2d3w`.
+    fn take_count(&mut self) -> usize {
+        let count = self.count.take().unwrap_or(1);
+        self.operator.map_or(count, |(_, operator_count)| {
+            count.saturating_mul(operator_count)
+        })
+    }
+
+    fn motion(&mut self, motion: Motion) -> ModalAction {
+        let count = self.take_count();
+        match self.operator.take() {
+            Some((operator, _)) => {
+                if operator == Operator::Change {
+                    self.mode = Mode::Insert;
+                }
+                ModalAction::Operate {
+                    operator,
+                    motion,
+                    count,
+                }
+            }
+            None => ModalAction::Move { motion, count },
+        }
+    }
+
+    fn operator(&mut self, operator: Operator) -> Option<ModalAction> {
+        if self.mode == Mode::Visual {
+            self.reset_pending();
+            self.mode = if operator == Operator::Change {
+                Mode::Insert
+            } else {
+                Mode::Normal
+            };
+            return Some(ModalAction::OperateSelection(operator));
+        }
+        if self
+            .operator
+            .is_some_and(|(pending, _)| pending == operator)
+        {
+            let count = self.take_count();
+            self.operator = None;
+            if operator == Operator::Change {
+                self.mode = Mode::Insert;
+            }
+            return Some(ModalAction::OperateLines { operator, count });
+        }
+        let count = self.count.take().unwrap_or(1);
+        self.operator = Some((operator, count));
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(state: &mut ModalState, keys: &str) -> Option<ModalAction> {
+        keys.chars()
+            .map(|ch| state.handle(Command::Edit(Edit::Insert(ch))))
+            .last()
+            .flatten()
+    }
+
+    #[test]
+    fn combines_counts_operators_and_motions() {
+        let mut state = ModalState::default();
+        assert!(matches!(
+            type_keys(&mut state, "2d3w"),
+            Some(ModalAction::Operate {
+                operator: Operator::Delete,
+                motion: Motion::WordForward,
+                count: 6,
+            })
+        ));
+        assert!(matches!(
+            type_keys(&mut state, "3yy"),
+            Some(ModalAction::OperateLines {
+                operator: Operator::Yank,
+                count: 3,
+            })
+        ));
+        assert!(matches!(
+            type_keys(&mut state, "12gg"),
+            Some(ModalAction::Move {
+                motion: Motion::Line(12),
+                ..
+            })
+        ));
+        assert!(matches!(
+            type_keys(&mut state, "cw"),
+            Some(ModalAction::Operate {
+                operator: Operator::Change,
+                ..
+            })
+        ));
+        assert!(state.mode() == Mode::Insert);
+    }
+}
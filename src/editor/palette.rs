@@ -0,0 +1,177 @@
+use super::{
+    command::{Command, Edit, Move, MoveDirection, System},
+    folding::FoldCommand,
+};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+/// Something the command palette can run: either a regular key-bound
+/// command, or an action that is only reachable from the palette.
+#[derive(Clone, Copy)]
+pub enum Action {
+    Command(Command),
+    SaveAs,
+    Reload,
+    Format,
+    DiffWithDisk,
+    ToggleModal,
+    ToggleWhitespace,
+    ToggleIndentGuides,
+    Fold(FoldCommand),
+}
+
+pub struct PaletteEntry {
+    pub name: &'static str,
+    pub action: Action,
+    /// The key currently producing this command, if any.
+    pub key: Option<String>,
+}
+
+const fn move_entry(
+    name: &'static str,
+    direction: MoveDirection,
+    is_selection: bool,
+) -> (&'static str, Action) {
+    (
+        name,
+        Action::Command(Command::Move(Move {
+            direction,
+            is_selection,
+        })),
+    )
+}
+
+const fn edit_entry(name: &'static str, edit: Edit) -> (&'static str, Action) {
+    (name, Action::Command(Command::Edit(edit)))
+}
+
+const fn system_entry(name: &'static str, system: System) -> (&'static str, Action) {
+    (name, Action::Command(Command::System(system)))
+}
+
+/// Every palette entry. `Resize`, `Dismiss` and `Palette` itself are left out
+/// since running them from the palette would do nothing useful.
+const ACTIONS: &[(&str, Action)] = &[
+    system_entry("Save", System::Save),
+    ("Save As...", Action::SaveAs),
+    system_entry("Open File...", System::Open),
+    system_entry("Find...", System::Search),
+    system_entry("Run Shell Command...", System::Shell),
+    system_entry("Command Line...", System::CommandLine),
+    system_entry("Start/Stop Macro Recording", System::RecordMacro),
+    system_entry("Play Macro...", System::PlayMacro),
+    system_entry("Jump Back", System::JumpBack),
+    system_entry("Jump Forward", System::JumpForward),
+    system_entry("Quit", System::Quit),
+    ("Reload File from Disk", Action::Reload),
+    ("Format Buffer", Action::Format),
+    ("Diff Buffer Against Disk", Action::DiffWithDisk),
+    ("Toggle Modal (vi) Editing", Action::ToggleModal),
+    ("Toggle Whitespace Rendering", Action::ToggleWhitespace),
+    ("Toggle Indent Guides", Action::ToggleIndentGuides),
+    ("Toggle Fold at Caret", Action::Fold(FoldCommand::Toggle)),
+    ("Fold at Caret", Action::Fold(FoldCommand::Close)),
+    ("Unfold at Caret", Action::Fold(FoldCommand::Open)),
+    ("Fold All", Action::Fold(FoldCommand::CloseAll)),
+    ("Unfold All", Action::Fold(FoldCommand::OpenAll)),
+    edit_entry("Copy", Edit::Copy),
+    edit_entry("Cut", Edit::Cut),
+    edit_entry("Paste", Edit::Paste),
+    edit_entry("Select All", Edit::SelectAll),
+    edit_entry("Insert Newline", Edit::InsertNewline),
+    edit_entry("Insert Tab", Edit::Insert('\t')),
+    edit_entry("Delete Previous Character", Edit::Backspace),
+    edit_entry("Delete Next Character", Edit::Delete),
+    edit_entry("Delete Previous Word", Edit::DeleteWord),
+    move_entry("Move Up", MoveDirection::Up, false),
+    move_entry("Move Down", MoveDirection::Down, false),
+    move_entry("Move Left", MoveDirection::Left, false),
+    move_entry("Move Right", MoveDirection::Right, false),
+    move_entry("Page Up", MoveDirection::PageUp, false),
+    move_entry("Page Down", MoveDirection::PageDown, false),
+    move_entry("Go to Line Start", MoveDirection::LineStart, false),
+    move_entry("Go to Line End", MoveDirection::LineEnd, false),
+    move_entry("Select Up", MoveDirection::Up, true),
+    move_entry("Select Down", MoveDirection::Down, true),
+    move_entry("Select Left", MoveDirection::Left, true),
+    move_entry("Select Right", MoveDirection::Right, true),
+    move_entry("Select Page Up", MoveDirection::PageUp, true),
+    move_entry("Select Page Down", MoveDirection::PageDown, true),
+    move_entry("Select to Line Start", MoveDirection::LineStart, true),
+    move_entry("Select to Line End", MoveDirection::LineEnd, true),
+];
+
+pub fn entries() -> Vec<PaletteEntry> {
+    ACTIONS
+        .iter()
+        .map(|&(name, action)| PaletteEntry {
+            name,
+            action,
+            key: match action {
+                Action::Command(command) => bound_key(command),
+                Action::SaveAs
+                | Action::Reload
+                | Action::Format
+                | Action::DiffWithDisk
+                | Action::ToggleModal
+                | Action::ToggleWhitespace
+                | Action::ToggleIndentGuides
+                | Action::Fold(_) => None,
+            },
+        })
+        .collect()
+}
+
+/// Finds the key bound to `command` by running candidate key events through
+/// the regular key mapping, so the palette never disagrees with the bindings.
+fn bound_key(command: Command) -> Option<String> {
+    const MODIFIERS: [KeyModifiers; 5] = [
+        KeyModifiers::NONE,
+        KeyModifiers::CONTROL,
+        KeyModifiers::SHIFT,
+        KeyModifiers::ALT,
+        KeyModifiers::CONTROL.union(KeyModifiers::SHIFT),
+    ];
+    let named_keys = [
+        KeyCode::Enter,
+        KeyCode::Tab,
+        KeyCode::Backspace,
+        KeyCode::Delete,
+        KeyCode::Esc,
+        KeyCode::Up,
+        KeyCode::Down,
+        KeyCode::Left,
+        KeyCode::Right,
+        KeyCode::Home,
+        KeyCode::End,
+    ];
+    let letters = ('a'..='z').map(KeyCode::Char);
+
+    MODIFIERS
+        .iter()
+        .flat_map(|&modifiers| {
+            named_keys
+                .into_iter()
+                .chain(letters.clone())
+                .map(move |code| KeyEvent::new(code, modifiers))
+        })
+        .find(|&key_event| Command::try_from(Event::Key(key_event)).ok() == Some(command))
+        .map(describe_key)
+}
+
+fn describe_key(key_event: KeyEvent) -> String {
+    let mut description = String::new();
+    for (modifier, name) in [
+        (KeyModifiers::CONTROL, "Ctrl-"),
+        (KeyModifiers::ALT, "Alt-"),
+        (KeyModifiers::SHIFT, "Shift-"),
+    ] {
+        if key_event.modifiers.contains(modifier) {
+            description.push_str(name);
+        }
+    }
+    let key = match key_event.code {
+        KeyCode::Char(ch) => ch.to_ascii_uppercase().to_string(),
+        code => format!("{code:?}"),
+    };
+    description + &key
+}
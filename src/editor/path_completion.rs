@@ -0,0 +1,101 @@
+use std::{env, fs, path::PathBuf};
+
+const SEPARATORS: [char; 2] = ['/', std::path::MAIN_SEPARATOR];
+
+/// Tab-completion state for a path typed into a prompt. The first Tab
+/// extends the input to the longest common prefix of all candidates;
+/// further Tabs cycle through the candidates one by one.
+pub struct PathCompletion {
+    candidates: Vec<String>,
+    selected: Option<usize>,
+}
+
+impl PathCompletion {
+    pub fn new(input: &str) -> Self {
+        Self {
+            candidates: complete_path(input),
+            selected: None,
+        }
+    }
+
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    pub const fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    pub fn common_prefix(&self) -> String {
+        let Some(first) = self.candidates.first() else {
+            return String::new();
+        };
+        let mut prefix_len = first.len();
+        for candidate in &self.candidates[1..] {
+            prefix_len = first
+                .char_indices()
+                .zip(candidate.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((idx, ch), _)| idx + ch.len_utf8())
+                .min(prefix_len);
+        }
+        first[..prefix_len].to_string()
+    }
+
+    pub fn next(&mut self) -> Option<&str> {
+        if self.candidates.is_empty() {
+            return None;
+        }
+        let next = self
+            .selected
+            .map_or(0, |idx| (idx + 1) % self.candidates.len());
+        self.selected = Some(next);
+        self.candidates.get(next).map(String::as_str)
+    }
+}
+
+/// Expands a leading `~` to the user's home directory.
+pub fn expand_tilde(input: &str) -> PathBuf {
+    let home = env::var("HOME").or_else(|_| env::var("USERPROFILE"));
+    match (input.strip_prefix('~'), home) {
+        (Some(""), Ok(home)) => PathBuf::from(home),
+        (Some(rest), Ok(home)) if rest.starts_with(SEPARATORS) => {
+            PathBuf::from(home).join(rest.trim_start_matches(SEPARATORS))
+        }
+        _ => PathBuf::from(input),
+    }
+}
+
+/// Lists the entries matching the last path component of `input`. Candidates
+/// keep the directory part exactly as typed (including `~`), and directories
+/// get a trailing separator so completion can continue into them.
+fn complete_path(input: &str) -> Vec<String> {
+    let (dir_part, prefix) = input
+        .rfind(SEPARATORS)
+        .map_or(("", input), |idx| input.split_at(idx + 1));
+    let dir = if dir_part.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_tilde(dir_part)
+    };
+
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = read_dir
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            let suffix = if is_dir { "/" } else { "" };
+            Some(format!("{dir_part}{name}{suffix}"))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
@@ -0,0 +1,108 @@
+use super::highlight::config_file::get_config_dir;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+const SESSION_FILE_NAME: &str = "session.toml";
+const MAX_HISTORY: usize = 100;
+const MAX_REMEMBERED_FILES: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct SelectionState {
+    pub start_line: usize,
+    pub start_grapheme: usize,
+    pub end_line: usize,
+    pub end_grapheme: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct FileState {
+    pub line_idx: usize,
+    pub grapheme_idx: usize,
+    pub scroll_row: usize,
+    pub scroll_col: usize,
+    pub selection: Option<SelectionState>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct FileEntry {
+    path: String,
+    #[serde(flatten)]
+    state: FileState,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Session {
+    #[serde(default)]
+    pub search_history: Vec<String>,
+    #[serde(default)]
+    pub save_history: Vec<String>,
+    #[serde(default)]
+    pub open_buffers: Vec<String>,
+    /// Most recently used first.
+    #[serde(default)]
+    files: Vec<FileEntry>,
+}
+
+impl Session {
+    fn session_path() -> Option<PathBuf> {
+        get_config_dir().ok().map(|dir| dir.join(SESSION_FILE_NAME))
+    }
+
+    /// Loads the session file, falling back to an empty session if it is
+    /// missing or unreadable.
+    pub fn load() -> Self {
+        Self::session_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::session_path()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no config directory"))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents =
+            toml::to_string(self).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        fs::write(path, contents)
+    }
+
+    /// Files are keyed by absolute path so the same file opened from different
+    /// working directories shares its state.
+    pub fn file_key(path: &Path) -> String {
+        path.canonicalize()
+            .unwrap_or_else(|_| path.to_path_buf())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn file_state(&self, path: &Path) -> Option<FileState> {
+        let key = Self::file_key(path);
+        self.files
+            .iter()
+            .find(|entry| entry.path == key)
+            .map(|entry| entry.state)
+    }
+
+    pub fn remember_file_state(&mut self, path: &Path, state: FileState) {
+        let key = Self::file_key(path);
+        self.files.retain(|entry| entry.path != key);
+        self.files.insert(0, FileEntry { path: key, state });
+        self.files.truncate(MAX_REMEMBERED_FILES);
+    }
+
+    pub fn set_histories(&mut self, search_history: &[String], save_history: &[String]) {
+        self.search_history = Self::most_recent(search_history);
+        self.save_history = Self::most_recent(save_history);
+    }
+
+    fn most_recent(history: &[String]) -> Vec<String> {
+        let skip = history.len().saturating_sub(MAX_HISTORY);
+        history[skip..].to_vec()
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::{Error, Read, Write, stdin};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+const MAX_SUMMARY_CHARS: usize = 200;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ShellMode {
+    /// `|cmd` (or no prefix): replace the selection, or the whole buffer, with stdout.
+    Pipe,
+    /// `<cmd`: insert stdout at the caret.
+    Insert,
+    /// `?cmd`: only show stdout in the message bar.
+    Show,
+    /// `!cmd`: run on the real terminal, outside of raw mode.
+    Interactive,
+}
+
+pub struct ShellCommand {
+    pub mode: ShellMode,
+    pub command: String,
+}
+
+pub struct ShellOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl ShellCommand {
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim_start();
+        let (mode, command) = match input.chars().next()? {
+            '|' => (ShellMode::Pipe, &input[1..]),
+            '<' => (ShellMode::Insert, &input[1..]),
+            '?' => (ShellMode::Show, &input[1..]),
+            '!' => (ShellMode::Interactive, &input[1..]),
+            _ => (ShellMode::Pipe, input),
+        };
+        let command = command.trim();
+        if command.is_empty() {
+            return None;
+        }
+        Some(Self {
+            mode,
+            command: command.to_string(),
+        })
+    }
+
+    fn build_process(&self) -> Command {
+        #[cfg(target_os = "windows")]
+        {
+            let mut process = Command::new("cmd");
+            process.arg("/C").arg(&self.command);
+            process
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+            let mut process = Command::new(shell);
+            process.arg("-c").arg(&self.command);
+            process
+        }
+    }
+
+    /// Runs the command with `input` on stdin and collects stdout/stderr.
+    /// stdin is written from a separate thread so large inputs can't deadlock
+    /// against a child that is blocked writing its own output.
+    pub fn run(&self, input: Option<&str>) -> Result<ShellOutput, Error> {
+        let mut child = self
+            .build_process()
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let writer = match (child.stdin.take(), input) {
+            (Some(mut child_stdin), Some(input)) => {
+                let input = input.to_string();
+                Some(thread::spawn(move || {
+                    // A child that exits without reading stdin is not an error.
+                    let _ = child_stdin.write_all(input.as_bytes());
+                }))
+            }
+            _ => None,
+        };
+
+        let output = child.wait_with_output()?;
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+
+        Ok(ShellOutput {
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    /// Runs the command attached to the terminal and waits for Enter afterwards
+    /// so the user can read its output. The caller is responsible for leaving
+    /// and re-entering raw mode around this call.
+    pub fn run_interactive(&self) -> Result<ExitStatus, Error> {
+        let status = self.build_process().status()?;
+        print!(
+            "\r\n[{}] Press Enter to return to the editor...",
+            describe_status(status)
+        );
+        std::io::stdout().flush()?;
+        let mut byte = [0u8; 1];
+        while stdin().read(&mut byte)? == 1 && byte[0] != b'\n' {}
+        Ok(status)
+    }
+}
+
+/// Builds an interactive command that pages a diff between two files.
+pub fn diff_command(original: &Path, modified: &Path) -> ShellCommand {
+    #[cfg(target_os = "windows")]
+    let command = format!(
+        "fc \"{}\" \"{}\" | more",
+        original.display(),
+        modified.display()
+    );
+
+    #[cfg(not(target_os = "windows"))]
+    let command = format!(
+        "diff -u {} {} | ${{PAGER:-less}}",
+        shell_quote(&original.to_string_lossy()),
+        shell_quote(&modified.to_string_lossy())
+    );
+
+    ShellCommand {
+        mode: ShellMode::Interactive,
+        command,
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+pub fn describe_status(status: ExitStatus) -> String {
+    status.code().map_or_else(
+        || "terminated by signal".to_string(),
+        |code| format!("exit status {code}"),
+    )
+}
+
+/// Collapses multi-line command output into something that fits the message bar.
+pub fn summarize_output(output: &str) -> String {
+    let summary = output
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" | ");
+    if summary.chars().count() > MAX_SUMMARY_CHARS {
+        let truncated: String = summary.chars().take(MAX_SUMMARY_CHARS).collect();
+        format!("{truncated}...")
+    } else {
+        summary
+    }
+}
+
+/// Strips the single trailing newline most commands emit, since the buffer
+/// model stores lines without their terminators.
+pub fn strip_trailing_newline(output: &str) -> &str {
+    output
+        .strip_suffix("\r\n")
+        .or_else(|| output.strip_suffix('\n'))
+        .unwrap_or(output)
+}
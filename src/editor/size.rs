@@ -1,4 +1,6 @@
-#[derive(Default, Copy, Clone, Eq, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Size {
     pub height: usize,
     pub width: usize,
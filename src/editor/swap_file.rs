@@ -0,0 +1,106 @@
+use super::highlight::config_file::{get_config_dir, load_editor_config};
+use std::{
+    fs,
+    io::Error,
+    path::{Path, PathBuf},
+    process,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+const SWAP_EXTENSION: &str = "swp";
+
+/// The latest unsaved buffer and the swap file it belongs in. The panic hook
+/// cannot reach the editor, so it writes this instead.
+static CRASH_SNAPSHOT: Mutex<Option<(PathBuf, String)>> = Mutex::new(None);
+
+pub fn set_crash_snapshot(snapshot: Option<(PathBuf, String)>) {
+    *CRASH_SNAPSHOT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = snapshot;
+}
+
+/// Writes the crash snapshot, if there is one, to its swap file. Gives up
+/// rather than wait when the panic struck while the snapshot was being set.
+pub fn write_crash_snapshot() {
+    let Ok(snapshot) = CRASH_SNAPSHOT.try_lock() else {
+        return;
+    };
+    if let Some((swap_path, contents)) = snapshot.as_ref() {
+        if let Some(directory) = swap_path.parent() {
+            let _ = fs::create_dir_all(directory);
+        }
+        let _ = fs::write(swap_path, contents);
+    }
+}
+
+#[derive(Clone)]
+pub struct SwapSettings {
+    pub enabled: bool,
+    pub directory: PathBuf,
+    pub interval: Duration,
+}
+
+impl SwapSettings {
+    /// Reads the `[swap]` section of `config.toml`. A relative `directory`
+    /// is resolved against the den config dir.
+    pub fn load() -> Option<Self> {
+        let config_dir = get_config_dir().ok()?;
+        let swap_config = load_editor_config(None).ok().and_then(|config| config.swap);
+
+        let (enabled, directory, interval) = swap_config.map_or((None, None, None), |swap| {
+            (swap.enabled, swap.directory, swap.interval_secs)
+        });
+
+        Some(Self {
+            enabled: enabled.unwrap_or(true),
+            directory: directory
+                .map_or_else(|| config_dir.join("swap"), |dir| config_dir.join(dir)),
+            interval: interval.map_or(DEFAULT_INTERVAL, Duration::from_secs),
+        })
+    }
+
+    /// Swap files are named after the absolute path of the file they protect,
+    /// with path separators escaped, so files with the same name in different
+    /// directories don't collide. Unnamed buffers get a per-process name.
+    pub fn swap_path_for(&self, file_path: Option<&Path>) -> PathBuf {
+        let name = file_path.map_or_else(
+            || format!("unnamed-{}", process::id()),
+            |path| {
+                let absolute = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                absolute
+                    .to_string_lossy()
+                    .chars()
+                    .map(|ch| match ch {
+                        '/' | '\\' | ':' => '%',
+                        _ => ch,
+                    })
+                    .collect()
+            },
+        );
+        self.directory.join(format!("{name}.{SWAP_EXTENSION}"))
+    }
+
+    pub fn write(&self, file_path: Option<&Path>, contents: &str) -> Result<(), Error> {
+        fs::create_dir_all(&self.directory)?;
+        fs::write(self.swap_path_for(file_path), contents)
+    }
+
+    pub fn remove(&self, file_path: Option<&Path>) {
+        let _ = fs::remove_file(self.swap_path_for(file_path));
+    }
+
+    /// Returns the swap file for `file_path` if it was written after the file
+    /// was last modified, i.e. if it may hold unsaved work.
+    pub fn find_newer_swap(&self, file_path: &Path) -> Option<PathBuf> {
+        let swap_path = self.swap_path_for(Some(file_path));
+        let swap_modified = fs::metadata(&swap_path).ok()?.modified().ok()?;
+        let file_modified = fs::metadata(file_path)
+            .ok()
+            .and_then(|metadata| metadata.modified().ok());
+        file_modified
+            .is_none_or(|file_modified| swap_modified > file_modified)
+            .then_some(swap_path)
+    }
+}
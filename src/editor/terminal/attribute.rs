@@ -1,9 +1,17 @@
 use crossterm::style::Color;
-use std::sync::Mutex;
+use std::sync::{Mutex, PoisonError};
 
+use super::color_support::{color_support, quantize};
 use crate::editor::annotated_string::AnnotationType;
-use crate::editor::highlight::config_file::{ColorRgb, ColorsConfig, load_colors_config};
+use crate::editor::highlight::config_file::{
+    ColorRgb, ColorsConfig, ColorsConfigFile, ConfigError, discover_themes, load_colors_config,
+    load_theme_config,
+};
+
+/// The built-in scheme, unless a `themes/default.toml` replaces it.
+pub const DEFAULT_THEME: &str = "default";
 
+#[derive(Clone)]
 pub struct ColorScheme {
     pub match_fg: Color,
     pub match_bg: Color,
@@ -15,9 +23,32 @@ pub struct ColorScheme {
     pub primitive_type: Color,
     pub string: Color,
     pub comment: Color,
+    pub function: Color,
+    pub macro_name: Color,
+    pub attribute: Color,
+    pub lifetime: Color,
+    pub constant: Color,
+    pub operator: Color,
+    pub escape: Color,
+    pub doc_comment: Color,
+    pub todo: Color,
     pub brackets: [Color; 4],
     pub selection_fg: Color,
     pub selection_bg: Color,
+    pub mark: Color,
+    pub fold: Color,
+    pub whitespace: Color,
+    pub whitespace_error_bg: Color,
+    /// UI colors left unset keep the terminal's own colors. The status bar
+    /// is drawn reversed until it has a color.
+    pub gutter_fg: Option<Color>,
+    pub gutter_bg: Option<Color>,
+    pub status_bar_fg: Option<Color>,
+    pub status_bar_bg: Option<Color>,
+    pub message_bar_fg: Option<Color>,
+    pub message_bar_bg: Option<Color>,
+    /// Styles set in `colors.toml`. Other annotation types use `default_style`.
+    pub styles: Vec<(AnnotationType, TextStyle)>,
 }
 
 pub const DEFAULT_COLOR_SCHEME: ColorScheme = ColorScheme {
@@ -71,6 +102,51 @@ pub const DEFAULT_COLOR_SCHEME: ColorScheme = ColorScheme {
         g: 153,
         b: 85,
     },
+    function: Color::Rgb {
+        r: 220,
+        g: 220,
+        b: 170,
+    },
+    macro_name: Color::Rgb {
+        r: 197,
+        g: 134,
+        b: 192,
+    },
+    attribute: Color::Rgb {
+        r: 156,
+        g: 220,
+        b: 254,
+    },
+    lifetime: Color::Rgb {
+        r: 224,
+        g: 108,
+        b: 117,
+    },
+    constant: Color::Rgb {
+        r: 79,
+        g: 193,
+        b: 255,
+    },
+    operator: Color::Rgb {
+        r: 212,
+        g: 212,
+        b: 212,
+    },
+    escape: Color::Rgb {
+        r: 215,
+        g: 186,
+        b: 125,
+    },
+    doc_comment: Color::Rgb {
+        r: 96,
+        g: 139,
+        b: 78,
+    },
+    todo: Color::Rgb {
+        r: 255,
+        g: 215,
+        b: 0,
+    },
     brackets: [
         Color::Rgb {
             r: 140,
@@ -99,18 +175,93 @@ pub const DEFAULT_COLOR_SCHEME: ColorScheme = ColorScheme {
         g: 200,
         b: 200,
     },
+    mark: Color::Rgb {
+        r: 229,
+        g: 192,
+        b: 123,
+    },
+    fold: Color::Rgb {
+        r: 128,
+        g: 128,
+        b: 128,
+    },
+    whitespace: Color::Rgb {
+        r: 80,
+        g: 80,
+        b: 80,
+    },
+    whitespace_error_bg: Color::Rgb {
+        r: 160,
+        g: 40,
+        b: 40,
+    },
+    gutter_fg: None,
+    gutter_bg: None,
+    status_bar_fg: None,
+    status_bar_bg: None,
+    message_bar_fg: None,
+    message_bar_bg: None,
+    styles: Vec::new(),
 };
 
 static COLOR_SCHEME: std::sync::LazyLock<Mutex<ColorScheme>> = std::sync::LazyLock::new(|| {
-    let default = default_color_scheme();
-    let merged_scheme = if let Ok(config_file) = load_colors_config(None) {
-        merge_color_scheme(&default, config_file.colors.as_ref())
-    } else {
-        default
+    let Ok(config_file) = load_colors_config(None) else {
+        return Mutex::new(default_color_scheme());
     };
-    Mutex::new(merged_scheme)
+    let base = config_file
+        .theme
+        .as_deref()
+        .and_then(|name| theme_color_scheme(name).ok())
+        .unwrap_or_else(default_color_scheme);
+    Mutex::new(merge_color_scheme(&base, config_file.colors.as_ref()))
 });
 
+/// The theme names that `load_theme` accepts.
+pub fn theme_names() -> Vec<String> {
+    let mut names = discover_themes();
+    if !names.iter().any(|name| name == DEFAULT_THEME) {
+        names.insert(0, DEFAULT_THEME.to_string());
+    }
+    names
+}
+
+/// Replaces the color scheme with the theme `name`. The screen has to be
+/// redrawn for it to show.
+pub fn load_theme(name: &str) -> Result<(), ConfigError> {
+    let scheme = theme_color_scheme(name)?;
+    *COLOR_SCHEME.lock().unwrap_or_else(PoisonError::into_inner) = scheme;
+    Ok(())
+}
+
+fn theme_color_scheme(name: &str) -> Result<ColorScheme, ConfigError> {
+    let theme = match load_theme_config(name, None) {
+        Err(ConfigError::FileNotFound) => debug_theme_config(name),
+        theme => theme,
+    };
+    match theme {
+        Ok(theme) => Ok(merge_color_scheme(
+            &DEFAULT_COLOR_SCHEME,
+            theme.colors.as_ref(),
+        )),
+        Err(ConfigError::FileNotFound) if name == DEFAULT_THEME => Ok(default_color_scheme()),
+        Err(err) => Err(err),
+    }
+}
+
+fn debug_theme_config(name: &str) -> Result<ColorsConfigFile, ConfigError> {
+    #[cfg(debug_assertions)]
+    {
+        let path = format!("docs/examples/default/themes/{name}.toml");
+        load_theme_config(name, Some(std::path::Path::new(&path)))
+    }
+
+    #[cfg(not(debug_assertions))]
+    {
+        let _ = name;
+        Err(ConfigError::FileNotFound)
+    }
+}
+
 fn default_color_scheme() -> ColorScheme {
     #[cfg(debug_assertions)]
     {
@@ -127,67 +278,105 @@ fn default_color_scheme() -> ColorScheme {
     DEFAULT_COLOR_SCHEME
 }
 
+/// Text attributes drawn on top of an annotation's colors.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub undercurl: bool,
+    pub dim: bool,
+    pub reverse: bool,
+}
+
 pub struct Attribute {
     pub foreground: Option<Color>,
     pub background: Option<Color>,
+    pub style: TextStyle,
+}
+
+/// The style of annotation types that `colors.toml` does not style.
+fn default_style(annotation_type: AnnotationType) -> TextStyle {
+    match annotation_type {
+        AnnotationType::Todo => TextStyle {
+            bold: true,
+            ..TextStyle::default()
+        },
+        AnnotationType::DocComment => TextStyle {
+            italic: true,
+            ..TextStyle::default()
+        },
+        _ => TextStyle::default(),
+    }
+}
+
+impl ColorScheme {
+    fn style(&self, annotation_type: AnnotationType) -> TextStyle {
+        self.styles
+            .iter()
+            .find(|(styled_type, _)| *styled_type == annotation_type)
+            .map_or_else(|| default_style(annotation_type), |(_, style)| *style)
+    }
 }
 
 impl From<AnnotationType> for Attribute {
     fn from(annotation_type: AnnotationType) -> Self {
         let scheme = COLOR_SCHEME.lock().unwrap();
-        match annotation_type {
-            AnnotationType::Match => Self {
-                foreground: Some(scheme.match_fg),
-                background: Some(scheme.match_bg),
-            },
-            AnnotationType::SelectedMatch => Self {
-                foreground: Some(scheme.selected_match_fg),
-                background: Some(scheme.selected_match_bg),
-            },
-            AnnotationType::Keyword => Self {
-                foreground: Some(scheme.keyword),
-                background: None,
-            },
-            AnnotationType::Number => Self {
-                foreground: Some(scheme.number),
-                background: None,
-            },
-            AnnotationType::Type => Self {
-                foreground: Some(scheme.type_name),
-                background: None,
-            },
-            AnnotationType::PrimitiveType => Self {
-                foreground: Some(scheme.primitive_type),
-                background: None,
-            },
-            AnnotationType::String => Self {
-                foreground: Some(scheme.string),
-                background: None,
-            },
-            AnnotationType::Comment => Self {
-                foreground: Some(scheme.comment),
-                background: None,
-            },
-            AnnotationType::Bracket0 => Self {
-                foreground: Some(scheme.brackets[0]),
-                background: None,
-            },
-            AnnotationType::Bracket1 => Self {
-                foreground: Some(scheme.brackets[1]),
-                background: None,
-            },
-            AnnotationType::Bracket2 => Self {
-                foreground: Some(scheme.brackets[2]),
-                background: None,
-            },
-            AnnotationType::Bracket3 => Self {
-                foreground: Some(scheme.brackets[3]),
-                background: None,
-            },
-            AnnotationType::Selection => Self {
-                foreground: Some(scheme.selection_fg),
-                background: Some(scheme.selection_bg),
-            },
+        let (foreground, background) = match annotation_type {
+            AnnotationType::Match => (Some(scheme.match_fg), Some(scheme.match_bg)),
+            AnnotationType::SelectedMatch => (
+                Some(scheme.selected_match_fg),
+                Some(scheme.selected_match_bg),
+            ),
+            AnnotationType::Keyword => (Some(scheme.keyword), None),
+            AnnotationType::Number => (Some(scheme.number), None),
+            AnnotationType::Type => (Some(scheme.type_name), None),
+            AnnotationType::PrimitiveType => (Some(scheme.primitive_type), None),
+            AnnotationType::String => (Some(scheme.string), None),
+            AnnotationType::Comment => (Some(scheme.comment), None),
+            AnnotationType::Function => (Some(scheme.function), None),
+            AnnotationType::Macro => (Some(scheme.macro_name), None),
+            AnnotationType::Attribute => (Some(scheme.attribute), None),
+            AnnotationType::Lifetime => (Some(scheme.lifetime), None),
+            AnnotationType::Constant => (Some(scheme.constant), None),
+            AnnotationType::Operator => (Some(scheme.operator), None),
+            AnnotationType::Escape => (Some(scheme.escape), None),
+            AnnotationType::DocComment => (Some(scheme.doc_comment), None),
+            AnnotationType::Todo => (Some(scheme.todo), None),
+            AnnotationType::Bracket0 => (Some(scheme.brackets[0]), None),
+            AnnotationType::Bracket1 => (Some(scheme.brackets[1]), None),
+            AnnotationType::Bracket2 => (Some(scheme.brackets[2]), None),
+            AnnotationType::Bracket3 => (Some(scheme.brackets[3]), None),
+            AnnotationType::Selection => (Some(scheme.selection_fg), Some(scheme.selection_bg)),
+            AnnotationType::Mark => (Some(scheme.mark), scheme.gutter_bg),
+            AnnotationType::Fold => (Some(scheme.fold), None),
+            AnnotationType::Whitespace => (Some(scheme.whitespace), None),
+            AnnotationType::WhitespaceError => (None, Some(scheme.whitespace_error_bg)),
+            AnnotationType::Gutter => (scheme.gutter_fg, scheme.gutter_bg),
+            AnnotationType::StatusBar => (scheme.status_bar_fg, scheme.status_bar_bg),
+            AnnotationType::MessageBar => (scheme.message_bar_fg, scheme.message_bar_bg),
+        };
+        let mut style = scheme.style(annotation_type);
+        let support = color_support();
+        let dropped_background = background.is_some_and(|color| quantize(color, support).is_none());
+        let foreground = foreground.and_then(|color| quantize(color, support));
+        let background = background.and_then(|color| quantize(color, support));
+        // Without colors, the status bar and highlighted backgrounds such as
+        // the selection still have to stand out.
+        let needs_reverse = match annotation_type {
+            AnnotationType::StatusBar => foreground.is_none() && background.is_none(),
+            AnnotationType::Match
+            | AnnotationType::SelectedMatch
+            | AnnotationType::Selection
+            | AnnotationType::WhitespaceError => dropped_background,
+            _ => false,
+        };
+        style.reverse |= needs_reverse;
+        Self {
+            foreground,
+            background,
+            style,
         }
     }
 }
@@ -200,71 +389,192 @@ fn color_rgb_to_color(rgb: &ColorRgb) -> Color {
     }
 }
 
+/// `default` with every color and style that `file_config` sets replaced.
 pub fn merge_color_scheme(
     default: &ColorScheme,
     file_config: Option<&ColorsConfig>,
 ) -> ColorScheme {
     let Some(file_config) = file_config else {
-        return ColorScheme {
-            match_fg: default.match_fg,
-            match_bg: default.match_bg,
-            selected_match_fg: default.selected_match_fg,
-            selected_match_bg: default.selected_match_bg,
-            keyword: default.keyword,
-            number: default.number,
-            type_name: default.type_name,
-            primitive_type: default.primitive_type,
-            string: default.string,
-            comment: default.comment,
-            brackets: default.brackets,
-            selection_fg: default.selection_fg,
-            selection_bg: default.selection_bg,
-        };
+        return default.clone();
     };
+    let color = |rgb: Option<&ColorRgb>, fallback: Color| rgb.map_or(fallback, color_rgb_to_color);
+    let ui_color =
+        |rgb: Option<&ColorRgb>, fallback: Option<Color>| rgb.map(color_rgb_to_color).or(fallback);
 
-    let brackets = if let Some(file_brackets) = &file_config.brackets {
-        let mut result = default.brackets;
-        for (i, bracket_color) in file_brackets.iter().enumerate() {
-            if i < result.len() {
-                result[i] = color_rgb_to_color(bracket_color);
-            }
-        }
-        result
-    } else {
-        default.brackets
-    };
+    let mut brackets = default.brackets;
+    for (bracket, bracket_color) in brackets
+        .iter_mut()
+        .zip(file_config.brackets.iter().flatten())
+    {
+        *bracket = color_rgb_to_color(bracket_color);
+    }
+
+    let mut styles = default.styles.clone();
+    for (annotation_type, style) in file_styles(default, file_config) {
+        styles.retain(|(styled_type, _)| *styled_type != annotation_type);
+        styles.push((annotation_type, style));
+    }
 
     ColorScheme {
-        match_fg: default.match_fg,
-        match_bg: default.match_bg,
-        selected_match_fg: default.selected_match_fg,
-        selected_match_bg: default.selected_match_bg,
-        keyword: file_config
-            .keyword
-            .as_ref()
-            .map_or(default.keyword, color_rgb_to_color),
-        number: file_config
-            .number
-            .as_ref()
-            .map_or(default.number, color_rgb_to_color),
-        type_name: file_config
-            .type_name
-            .as_ref()
-            .map_or(default.type_name, color_rgb_to_color),
-        primitive_type: file_config
-            .primitive_type
-            .as_ref()
-            .map_or(default.primitive_type, color_rgb_to_color),
-        string: file_config
-            .string
-            .as_ref()
-            .map_or(default.string, color_rgb_to_color),
-        comment: file_config
-            .comment
-            .as_ref()
-            .map_or(default.comment, color_rgb_to_color),
+        match_fg: color(file_config.match_fg.as_ref(), default.match_fg),
+        match_bg: color(file_config.match_bg.as_ref(), default.match_bg),
+        selected_match_fg: color(
+            file_config.selected_match_fg.as_ref(),
+            default.selected_match_fg,
+        ),
+        selected_match_bg: color(
+            file_config.selected_match_bg.as_ref(),
+            default.selected_match_bg,
+        ),
+        keyword: color(file_config.keyword.as_ref(), default.keyword),
+        number: color(file_config.number.as_ref(), default.number),
+        type_name: color(file_config.type_name.as_ref(), default.type_name),
+        primitive_type: color(file_config.primitive_type.as_ref(), default.primitive_type),
+        string: color(file_config.string.as_ref(), default.string),
+        comment: color(file_config.comment.as_ref(), default.comment),
+        function: color(file_config.function.as_ref(), default.function),
+        macro_name: color(file_config.macro_name.as_ref(), default.macro_name),
+        attribute: color(file_config.attribute.as_ref(), default.attribute),
+        lifetime: color(file_config.lifetime.as_ref(), default.lifetime),
+        constant: color(file_config.constant.as_ref(), default.constant),
+        operator: color(file_config.operator.as_ref(), default.operator),
+        escape: color(file_config.escape.as_ref(), default.escape),
+        doc_comment: color(file_config.doc_comment.as_ref(), default.doc_comment),
+        todo: color(file_config.todo.as_ref(), default.todo),
         brackets,
-        selection_fg: default.selection_fg,
-        selection_bg: default.selection_bg,
+        selection_fg: color(file_config.selection_fg.as_ref(), default.selection_fg),
+        selection_bg: color(file_config.selection_bg.as_ref(), default.selection_bg),
+        mark: color(file_config.mark.as_ref(), default.mark),
+        fold: color(file_config.fold.as_ref(), default.fold),
+        whitespace: color(file_config.whitespace.as_ref(), default.whitespace),
+        whitespace_error_bg: color(
+            file_config.whitespace_error_bg.as_ref(),
+            default.whitespace_error_bg,
+        ),
+        gutter_fg: ui_color(file_config.gutter_fg.as_ref(), default.gutter_fg),
+        gutter_bg: ui_color(file_config.gutter_bg.as_ref(), default.gutter_bg),
+        status_bar_fg: ui_color(file_config.status_bar_fg.as_ref(), default.status_bar_fg),
+        status_bar_bg: ui_color(file_config.status_bar_bg.as_ref(), default.status_bar_bg),
+        message_bar_fg: ui_color(file_config.message_bar_fg.as_ref(), default.message_bar_fg),
+        message_bar_bg: ui_color(file_config.message_bar_bg.as_ref(), default.message_bar_bg),
+        styles,
+    }
+}
+
+/// The styles of the annotation types whose color entry sets any style key.
+fn file_styles(
+    default: &ColorScheme,
+    file_config: &ColorsConfig,
+) -> Vec<(AnnotationType, TextStyle)> {
+    let brackets = file_config.brackets.as_deref().unwrap_or_default();
+    [
+        (AnnotationType::Keyword, file_config.keyword.as_ref()),
+        (AnnotationType::Number, file_config.number.as_ref()),
+        (AnnotationType::Type, file_config.type_name.as_ref()),
+        (
+            AnnotationType::PrimitiveType,
+            file_config.primitive_type.as_ref(),
+        ),
+        (AnnotationType::String, file_config.string.as_ref()),
+        (AnnotationType::Comment, file_config.comment.as_ref()),
+        (AnnotationType::Function, file_config.function.as_ref()),
+        (AnnotationType::Macro, file_config.macro_name.as_ref()),
+        (AnnotationType::Attribute, file_config.attribute.as_ref()),
+        (AnnotationType::Lifetime, file_config.lifetime.as_ref()),
+        (AnnotationType::Constant, file_config.constant.as_ref()),
+        (AnnotationType::Operator, file_config.operator.as_ref()),
+        (AnnotationType::Escape, file_config.escape.as_ref()),
+        (AnnotationType::DocComment, file_config.doc_comment.as_ref()),
+        (AnnotationType::Todo, file_config.todo.as_ref()),
+        (AnnotationType::Bracket0, brackets.first()),
+        (AnnotationType::Bracket1, brackets.get(1)),
+        (AnnotationType::Bracket2, brackets.get(2)),
+        (AnnotationType::Bracket3, brackets.get(3)),
+        (AnnotationType::Match, file_config.match_fg.as_ref()),
+        (
+            AnnotationType::SelectedMatch,
+            file_config.selected_match_fg.as_ref(),
+        ),
+        (AnnotationType::Selection, file_config.selection_fg.as_ref()),
+        (AnnotationType::Mark, file_config.mark.as_ref()),
+        (AnnotationType::Fold, file_config.fold.as_ref()),
+        (AnnotationType::Whitespace, file_config.whitespace.as_ref()),
+        (AnnotationType::Gutter, file_config.gutter_fg.as_ref()),
+        (
+            AnnotationType::StatusBar,
+            file_config.status_bar_fg.as_ref(),
+        ),
+        (
+            AnnotationType::MessageBar,
+            file_config.message_bar_fg.as_ref(),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(annotation_type, rgb)| {
+        let rgb = rgb.filter(|rgb| rgb.has_style())?;
+        let base = default.style(annotation_type);
+        let style = TextStyle {
+            bold: rgb.bold.unwrap_or(base.bold),
+            italic: rgb.italic.unwrap_or(base.italic),
+            underline: rgb.underline.unwrap_or(base.underline),
+            undercurl: rgb.undercurl.unwrap_or(base.undercurl),
+            dim: rgb.dim.unwrap_or(base.dim),
+            reverse: rgb.reverse.unwrap_or(base.reverse),
+        };
+        Some((annotation_type, style))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styles_from_file_override_defaults() {
+        let config: ColorsConfig = toml::from_str(
+            "keyword = { r = 1, g = 2, b = 3, bold = true, underline = true }\n\
+             todo = { r = 4, g = 5, b = 6, bold = false, reverse = true }\n\
+             string = { r = 7, g = 8, b = 9 }",
+        )
+        .expect("colors config");
+        let scheme = merge_color_scheme(&DEFAULT_COLOR_SCHEME, Some(&config));
+
+        let keyword = scheme.style(AnnotationType::Keyword);
+        assert!(keyword.bold && keyword.underline && !keyword.italic);
+        let todo = scheme.style(AnnotationType::Todo);
+        assert!(!todo.bold && todo.reverse);
+        assert!(scheme.style(AnnotationType::String) == TextStyle::default());
+        assert!(scheme.style(AnnotationType::DocComment).italic);
+    }
+
+    #[test]
+    fn reads_hex_colors_and_interface_colors_from_themes() {
+        let theme: ColorsConfigFile = toml::from_str(include_str!(
+            "../../../docs/examples/default/themes/light.toml"
+        ))
+        .expect("light theme");
+        let scheme = merge_color_scheme(&DEFAULT_COLOR_SCHEME, theme.colors.as_ref());
+
+        assert!(scheme.keyword == Color::Rgb { r: 0, g: 0, b: 255 });
+        assert!(
+            scheme.status_bar_bg
+                == Some(Color::Rgb {
+                    r: 0,
+                    g: 95,
+                    b: 184
+                })
+        );
+        assert!(
+            scheme.brackets[1]
+                == Color::Rgb {
+                    r: 4,
+                    g: 49,
+                    b: 250
+                }
+        );
+        assert!(scheme.style(AnnotationType::Todo).bold);
+        assert!(!scheme.style(AnnotationType::StatusBar).reverse);
+        assert!(toml::from_str::<ColorsConfig>("keyword = \"#12345\"").is_err());
     }
 }
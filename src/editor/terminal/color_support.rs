@@ -0,0 +1,264 @@
+use crossterm::style::Color;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::editor::highlight::config_file::load_editor_config;
+
+/// How many colors the terminal can show. Theme colors are quantized down to
+/// what it supports.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    /// No colors at all, as asked for by `NO_COLOR`.
+    NoColor,
+}
+
+static COLOR_SUPPORT: OnceLock<ColorSupport> = OnceLock::new();
+
+/// The xterm defaults for the 16 basic colors, in palette order.
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// The channel values of the xterm 6x6x6 color cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Settles the color support for this run: `terminal.colors` from
+/// `config.toml` if set, otherwise what the environment reports.
+pub fn initialize() {
+    let configured = load_editor_config(None)
+        .ok()
+        .and_then(|config| config.terminal)
+        .and_then(|terminal| terminal.colors)
+        .and_then(|colors| parse_color_support(&colors));
+    let _ = COLOR_SUPPORT.set(configured.unwrap_or_else(detect));
+}
+
+pub fn color_support() -> ColorSupport {
+    *COLOR_SUPPORT.get_or_init(detect)
+}
+
+/// `auto` and unknown values leave the choice to detection.
+fn parse_color_support(value: &str) -> Option<ColorSupport> {
+    match value {
+        "truecolor" | "24bit" => Some(ColorSupport::TrueColor),
+        "256" => Some(ColorSupport::Ansi256),
+        "16" => Some(ColorSupport::Ansi16),
+        "none" => Some(ColorSupport::NoColor),
+        _ => None,
+    }
+}
+
+fn detect() -> ColorSupport {
+    let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+    detect_from(
+        var("NO_COLOR").is_some(),
+        var("COLORTERM").as_deref(),
+        var("TERM").as_deref(),
+    )
+}
+
+fn detect_from(no_color: bool, colorterm: Option<&str>, term: Option<&str>) -> ColorSupport {
+    if no_color {
+        return ColorSupport::NoColor;
+    }
+    if matches!(colorterm, Some("truecolor" | "24bit")) {
+        return ColorSupport::TrueColor;
+    }
+    let Some(term) = term else {
+        // Windows consoles do not set TERM and have understood truecolor
+        // since Windows 10.
+        return if cfg!(windows) {
+            ColorSupport::TrueColor
+        } else {
+            ColorSupport::Ansi16
+        };
+    };
+    if term == "dumb" {
+        return ColorSupport::NoColor;
+    }
+    if term.ends_with("-direct") {
+        return ColorSupport::TrueColor;
+    }
+    if term.contains("256color") {
+        return ColorSupport::Ansi256;
+    }
+    match terminfo_max_colors(term) {
+        Some(colors) if colors >= 1 << 24 => ColorSupport::TrueColor,
+        Some(colors) if colors >= 256 => ColorSupport::Ansi256,
+        Some(colors) if colors < 8 => ColorSupport::NoColor,
+        _ => ColorSupport::Ansi16,
+    }
+}
+
+/// The `colors` capability from the compiled terminfo entry for `term`.
+fn terminfo_max_colors(term: &str) -> Option<u32> {
+    let first = term.chars().next()?;
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Ok(dir) = env::var("TERMINFO") {
+        dirs.push(dir.into());
+    }
+    if let Ok(home) = env::var("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    dirs.extend(
+        ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"]
+            .iter()
+            .map(PathBuf::from),
+    );
+    dirs.iter().find_map(|dir| {
+        // Most systems file entries under their first letter, macOS under
+        // its hex code.
+        [
+            dir.join(first.to_string()).join(term),
+            dir.join(format!("{:x}", u32::from(first))).join(term),
+        ]
+        .iter()
+        .find_map(|path| fs::read(path).ok())
+        .and_then(|entry| parse_max_colors(&entry))
+    })
+}
+
+/// Reads `max_colors`, the numeric capability at index 13, from a compiled
+/// terminfo entry in the legacy or the 32-bit number format.
+fn parse_max_colors(entry: &[u8]) -> Option<u32> {
+    const MAX_COLORS: usize = 13;
+    let header = |idx: usize| -> Option<usize> {
+        let bytes = entry.get(idx * 2..idx * 2 + 2)?;
+        usize::try_from(i16::from_le_bytes([bytes[0], bytes[1]])).ok()
+    };
+    let number_size = match i16::from_le_bytes([*entry.first()?, *entry.get(1)?]) {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let (names_size, bool_count, number_count) = (header(1)?, header(2)?, header(3)?);
+    if MAX_COLORS >= number_count {
+        return None;
+    }
+    let mut offset = 12 + names_size + bool_count;
+    offset += offset % 2;
+    offset += MAX_COLORS * number_size;
+    let bytes = entry.get(offset..offset + number_size)?;
+    let colors = if number_size == 2 {
+        i32::from(i16::from_le_bytes([bytes[0], bytes[1]]))
+    } else {
+        i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    u32::try_from(colors).ok()
+}
+
+/// `color` as the terminal can show it, or `None` when it shows no colors.
+pub fn quantize(color: Color, support: ColorSupport) -> Option<Color> {
+    let Color::Rgb { r, g, b } = color else {
+        return (support != ColorSupport::NoColor).then_some(color);
+    };
+    match support {
+        ColorSupport::TrueColor => Some(color),
+        ColorSupport::Ansi256 => Some(Color::AnsiValue(nearest_ansi_256((r, g, b)))),
+        ColorSupport::Ansi16 => ANSI_16
+            .iter()
+            .min_by_key(|(_, rgb)| distance(*rgb, (r, g, b)))
+            .map(|(color, _)| *color),
+        ColorSupport::NoColor => None,
+    }
+}
+
+/// The closer of the nearest color cube entry and the nearest grey on the
+/// 24-step ramp. The first 16 entries are left out, since terminals
+/// redefine them.
+fn nearest_ansi_256(rgb: (u8, u8, u8)) -> u8 {
+    let cube_idx = |channel: u8| {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| level.abs_diff(channel))
+            .map_or(0, |(idx, _)| idx)
+    };
+    let (r, g, b) = (cube_idx(rgb.0), cube_idx(rgb.1), cube_idx(rgb.2));
+    let cube = (CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+    let cube_value = 16 + 36 * r + 6 * g + b;
+
+    let average = (u16::from(rgb.0) + u16::from(rgb.1) + u16::from(rgb.2)) / 3;
+    let grey_idx = (average.saturating_sub(3) / 10).min(23);
+    let grey_level = u8::try_from(8 + grey_idx * 10).unwrap_or(u8::MAX);
+    let grey = (grey_level, grey_level, grey_level);
+
+    let value = if distance(grey, rgb) < distance(cube, rgb) {
+        232 + usize::from(grey_idx)
+    } else {
+        cube_value
+    };
+    u8::try_from(value).unwrap_or(u8::MAX)
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |x: u8, y: u8| u32::from(x.abs_diff(y)).pow(2);
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_support_and_quantizes_colors() {
+        assert_eq!(
+            detect_from(true, Some("truecolor"), Some("xterm-256color")),
+            ColorSupport::NoColor
+        );
+        assert_eq!(
+            detect_from(false, Some("24bit"), Some("xterm")),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            detect_from(false, None, Some("screen-256color")),
+            ColorSupport::Ansi256
+        );
+        assert_eq!(
+            detect_from(false, None, Some("dumb")),
+            ColorSupport::NoColor
+        );
+
+        let orange = Color::Rgb {
+            r: 255,
+            g: 135,
+            b: 0,
+        };
+        let grey = Color::Rgb {
+            r: 128,
+            g: 128,
+            b: 130,
+        };
+        assert_eq!(quantize(orange, ColorSupport::TrueColor), Some(orange));
+        assert_eq!(
+            quantize(orange, ColorSupport::Ansi256),
+            Some(Color::AnsiValue(208))
+        );
+        assert_eq!(
+            quantize(grey, ColorSupport::Ansi256),
+            Some(Color::AnsiValue(244))
+        );
+        assert_eq!(quantize(grey, ColorSupport::Ansi16), Some(Color::DarkGrey));
+        assert_eq!(quantize(orange, ColorSupport::NoColor), None);
+    }
+}
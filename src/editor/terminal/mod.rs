@@ -1,70 +1,72 @@
 use super::{Position, Size};
-use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::cursor::Show;
 use crossterm::event::{DisableBracketedPaste, EnableBracketedPaste};
 mod attribute;
-use super::AnnotatedString;
+mod color_support;
+mod screen;
+use super::{AnnotatedString, AnnotationType};
 use attribute::Attribute;
-use crossterm::style::{
-    Attribute::{Reset, Reverse},
-    Print, ResetColor, SetBackgroundColor, SetForegroundColor,
-};
+pub use attribute::{load_theme, theme_names};
 use crossterm::terminal::{
-    Clear, ClearType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen, LeaveAlternateScreen,
-    SetTitle, disable_raw_mode, enable_raw_mode, size,
+    DisableLineWrap, EnableLineWrap, EnterAlternateScreen, LeaveAlternateScreen, SetTitle,
+    disable_raw_mode, enable_raw_mode, size,
 };
 use crossterm::{Command, queue};
+use screen::{CellStyle, Screen};
 use std::io::{Error, Write, stdout};
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+/// Everything printed lands here first; `execute` sends the difference to
+/// the previous frame.
+static SCREEN: LazyLock<Mutex<Screen>> = LazyLock::new(|| Mutex::new(Screen::default()));
 
 pub struct Terminal {}
 
 impl Terminal {
     pub fn terminate() -> Result<(), Error> {
         Self::queue_command(DisableBracketedPaste)?;
-        Self::execute()?;
         Self::leave_alternate_screen()?;
         Self::enable_line_wrap()?;
-        Self::show_caret()?;
-        Self::execute()?;
+        Self::queue_command(Show)?;
+        stdout().flush()?;
         disable_raw_mode()?;
         Ok(())
     }
 
     pub fn initialize() -> Result<(), Error> {
+        color_support::initialize();
         enable_raw_mode()?;
         Self::disable_line_wrap()?;
         Self::enter_alternate_screen()?;
-        Self::clear_screen()?;
+        Self::resize(Self::size()?);
+        Self::clear_screen();
         Self::queue_command(EnableBracketedPaste)?;
-        Self::execute()?;
+        stdout().flush()?;
         Ok(())
     }
 
-    pub fn clear_screen() -> Result<(), Error> {
-        Self::queue_command(Clear(ClearType::All))?;
-        Ok(())
+    /// Sizes the frame that rows are printed into. Rows printed before a
+    /// resize are dropped, so this has to come before drawing.
+    pub fn resize(size: Size) {
+        Self::screen().resize(size);
     }
 
-    pub fn clear_line() -> Result<(), Error> {
-        Self::queue_command(Clear(ClearType::CurrentLine))?;
-        Ok(())
+    /// Blanks the whole screen on the next `execute`.
+    pub fn clear_screen() {
+        Self::screen().invalidate();
     }
 
-    pub fn move_caret_to(position: Position) -> Result<(), Error> {
-        Self::queue_command(MoveTo(
-            u16::try_from(position.col).unwrap_or(u16::MAX),
-            u16::try_from(position.row).unwrap_or(u16::MAX),
-        ))?;
-        Ok(())
+    /// Where the caret is placed once the frame is drawn.
+    pub fn move_caret_to(position: Position) {
+        Self::screen().set_caret(position);
     }
 
-    pub fn hide_caret() -> Result<(), Error> {
-        Self::queue_command(Hide)?;
-        Ok(())
+    pub fn hide_caret() {
+        Self::screen().set_caret_visible(false);
     }
 
-    pub fn show_caret() -> Result<(), Error> {
-        Self::queue_command(Show)?;
-        Ok(())
+    pub fn show_caret() {
+        Self::screen().set_caret_visible(true);
     }
 
     pub fn enter_alternate_screen() -> Result<(), Error> {
@@ -92,57 +94,49 @@ impl Terminal {
         Ok(())
     }
 
-    pub fn print_annotated_row(
-        row: usize,
-        annotated_string: &AnnotatedString,
-    ) -> Result<(), Error> {
-        Self::move_caret_to(Position { row, col: 0 })?;
-        Self::clear_line()?;
-        annotated_string
+    pub fn print_annotated_row(row: usize, annotated_string: &AnnotatedString) {
+        let parts: Vec<_> = annotated_string
             .into_iter()
-            .try_for_each(|part| -> Result<(), Error> {
-                if let Some(annotation_type) = part.annotation_type {
-                    let attribute: Attribute = annotation_type.into();
-                    Self::set_attribute(&attribute)?;
-                }
-
-                Self::print(part.string)?;
-                Self::reset_color()?;
-                Ok(())
-            })?;
-        Ok(())
-    }
-
-    fn set_attribute(attribute: &Attribute) -> Result<(), Error> {
-        if let Some(foreground_color) = attribute.foreground {
-            Self::queue_command(SetForegroundColor(foreground_color))?;
-        }
-        if let Some(background_color) = attribute.background {
-            Self::queue_command(SetBackgroundColor(background_color))?;
-        }
-        Ok(())
-    }
-
-    fn reset_color() -> Result<(), Error> {
-        Self::queue_command(ResetColor)?;
-        Ok(())
-    }
-
-    pub fn print_inverted_row(row: usize, line_text: &str) -> Result<(), Error> {
-        let width = Self::size()?.width;
-        Self::print_row(row, &format!("{Reverse}{line_text:width$.width$}{Reset}"))
-    }
-
-    pub fn print(string: &str) -> Result<(), Error> {
-        Self::queue_command(Print(string))?;
-        Ok(())
-    }
-
-    pub fn print_row(row: usize, line_text: &str) -> Result<(), Error> {
-        Self::move_caret_to(Position { row, col: 0 })?;
-        Self::clear_line()?;
-        Self::print(line_text)?;
-        Ok(())
+            .map(|part| {
+                let style =
+                    part.annotation_type
+                        .map_or_else(CellStyle::default, |annotation_type| {
+                            let attribute: Attribute = annotation_type.into();
+                            CellStyle {
+                                foreground: attribute.foreground,
+                                background: attribute.background,
+                                text: attribute.style,
+                            }
+                        });
+                (part.string, style)
+            })
+            .collect();
+        Self::screen().set_row(row, parts);
+    }
+
+    /// Prints `line_text` across the whole row in the theme's colors for
+    /// `annotation_type`.
+    pub fn print_themed_row(row: usize, line_text: &str, annotation_type: AnnotationType) {
+        let width = Self::screen().width();
+        let text = format!("{line_text:width$.width$}");
+        let mut annotated_string = AnnotatedString::from(&text);
+        annotated_string.add_annotation(annotation_type, 0, text.len());
+        Self::print_annotated_row(row, &annotated_string);
+    }
+
+    pub fn print_inverted_row(row: usize, line_text: &str) {
+        let mut screen = Self::screen();
+        let width = screen.width();
+        let mut style = CellStyle::default();
+        style.text.reverse = true;
+        screen.set_row(
+            row,
+            [(format!("{line_text:width$.width$}").as_str(), style)],
+        );
+    }
+
+    pub fn print_row(row: usize, line_text: &str) {
+        Self::screen().set_row(row, [(line_text, CellStyle::default())]);
     }
 
     pub fn size() -> Result<Size, Error> {
@@ -155,11 +149,19 @@ impl Terminal {
         }
     }
 
+    /// Draws the cells that changed since the last call and places the caret.
     pub fn execute() -> Result<(), Error> {
-        stdout().flush()?;
+        let mut screen = Self::screen();
+        let mut stdout = stdout().lock();
+        screen.flush(&mut stdout)?;
+        stdout.flush()?;
         Ok(())
     }
 
+    fn screen() -> MutexGuard<'static, Screen> {
+        SCREEN.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
     fn queue_command<T: Command>(command: T) -> Result<(), Error> {
         queue!(stdout(), command)?;
         Ok(())
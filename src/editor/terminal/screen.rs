@@ -0,0 +1,299 @@
+use super::attribute::TextStyle;
+use super::{Position, Size};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::queue;
+use crossterm::style::{
+    Attribute as TextAttribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor,
+    SetForegroundColor,
+};
+use crossterm::terminal::{BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate};
+use std::io::{Error, Write};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The most unchanged cells that are printed again rather than skipped with
+/// a cursor move.
+const MAX_REPRINT: usize = 4;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct CellStyle {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub text: TextStyle,
+}
+
+/// One terminal cell. A wide grapheme is stored in its first cell, and the
+/// cells it covers hold an empty string.
+#[derive(Clone, PartialEq, Eq)]
+struct Cell {
+    grapheme: String,
+    style: CellStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            grapheme: " ".to_string(),
+            style: CellStyle::default(),
+        }
+    }
+}
+
+/// The frame being drawn and the frame the terminal shows. Rows are written
+/// into `next` during a refresh, and `flush` sends only the cells that differ
+/// from `shown`.
+#[derive(Default)]
+pub struct Screen {
+    size: Size,
+    next: Vec<Vec<Cell>>,
+    shown: Vec<Vec<Cell>>,
+    /// Set when the terminal contents are unknown, after a resize or a
+    /// clear, so that the next flush redraws every cell.
+    invalidated: bool,
+    caret: Position,
+    caret_visible: bool,
+}
+
+impl Screen {
+    pub fn resize(&mut self, size: Size) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        self.next = vec![vec![Cell::default(); size.width]; size.height];
+        self.shown.clone_from(&self.next);
+        self.invalidated = true;
+    }
+
+    pub fn width(&self) -> usize {
+        self.size.width
+    }
+
+    pub fn invalidate(&mut self) {
+        self.invalidated = true;
+    }
+
+    pub fn set_caret(&mut self, caret: Position) {
+        self.caret = caret;
+    }
+
+    pub fn set_caret_visible(&mut self, visible: bool) {
+        self.caret_visible = visible;
+    }
+
+    /// Replaces `row` with `parts`, padding it with blank cells.
+    pub fn set_row<'a>(
+        &mut self,
+        row: usize,
+        parts: impl IntoIterator<Item = (&'a str, CellStyle)>,
+    ) {
+        let Some(cells) = self.next.get_mut(row) else {
+            return;
+        };
+        cells.fill(Cell::default());
+        let mut col: usize = 0;
+        for (text, style) in parts {
+            for grapheme in text.graphemes(true) {
+                let width = grapheme.width_cjk();
+                if width == 0 {
+                    if let Some(previous) = col.checked_sub(1).and_then(|prev| cells.get_mut(prev))
+                    {
+                        previous.grapheme.push_str(grapheme);
+                    }
+                    continue;
+                }
+                if col + width > cells.len() {
+                    return;
+                }
+                cells[col] = Cell {
+                    grapheme: grapheme.to_string(),
+                    style,
+                };
+                for covered in &mut cells[col + 1..col + width] {
+                    *covered = Cell {
+                        grapheme: String::new(),
+                        style,
+                    };
+                }
+                col += width;
+            }
+        }
+    }
+
+    /// Writes the changed cells to `out` inside a synchronized update, so
+    /// that terminals which support it show the frame at once. Others ignore
+    /// the markers.
+    pub fn flush(&mut self, out: &mut impl Write) -> Result<(), Error> {
+        queue!(out, BeginSynchronizedUpdate, Hide)?;
+        if self.invalidated {
+            queue!(
+                out,
+                ResetColor,
+                SetAttribute(TextAttribute::Reset),
+                Clear(ClearType::All)
+            )?;
+        }
+
+        let mut cursor: Option<Position> = None;
+        let mut style = CellStyle::default();
+        for (row, (next, shown)) in self.next.iter().zip(&mut self.shown).enumerate() {
+            for (col, (cell, shown_cell)) in next.iter().zip(shown.iter_mut()).enumerate() {
+                // Blank cells are already blank after a clear.
+                let unchanged = if self.invalidated {
+                    *cell == Cell::default()
+                } else {
+                    cell == shown_cell
+                };
+                if unchanged || cell.grapheme.is_empty() {
+                    continue;
+                }
+                match cursor {
+                    Some(at) if at.row == row && at.col == col => {}
+                    // Reprinting a short run of narrow cells is cheaper than
+                    // an escape sequence to jump over them.
+                    Some(at)
+                        if at.row == row
+                            && at.col < col
+                            && col - at.col <= MAX_REPRINT
+                            && next[at.col..col]
+                                .iter()
+                                .all(|skipped| skipped.grapheme.width_cjk() == 1) =>
+                    {
+                        for skipped in &next[at.col..col] {
+                            if skipped.style != style {
+                                set_style(out, style, skipped.style)?;
+                                style = skipped.style;
+                            }
+                            queue!(out, Print(&skipped.grapheme))?;
+                        }
+                    }
+                    _ => queue!(
+                        out,
+                        MoveTo(
+                            u16::try_from(col).unwrap_or(u16::MAX),
+                            u16::try_from(row).unwrap_or(u16::MAX)
+                        )
+                    )?,
+                }
+                if cell.style != style {
+                    set_style(out, style, cell.style)?;
+                    style = cell.style;
+                }
+                queue!(out, Print(&cell.grapheme))?;
+                cursor = Some(Position {
+                    col: col + cell.grapheme.width_cjk().max(1),
+                    row,
+                });
+            }
+            shown.clone_from(next);
+        }
+        if style != CellStyle::default() {
+            queue!(out, ResetColor, SetAttribute(TextAttribute::Reset))?;
+        }
+        self.invalidated = false;
+
+        queue!(
+            out,
+            MoveTo(
+                u16::try_from(self.caret.col).unwrap_or(u16::MAX),
+                u16::try_from(self.caret.row).unwrap_or(u16::MAX)
+            )
+        )?;
+        if self.caret_visible {
+            queue!(out, Show)?;
+        }
+        queue!(out, EndSynchronizedUpdate)?;
+        Ok(())
+    }
+}
+
+/// Moves the terminal from style `from` to `to`. Text attributes can only be
+/// turned off all at once, so dropping one resets everything.
+fn set_style(out: &mut impl Write, from: CellStyle, to: CellStyle) -> Result<(), Error> {
+    let mut from = from;
+    if !text_attributes(to.text)
+        .iter()
+        .zip(text_attributes(from.text))
+        .all(|((wanted, _), (had, _))| *wanted || !had)
+    {
+        queue!(out, SetAttribute(TextAttribute::Reset), ResetColor)?;
+        from = CellStyle::default();
+    }
+    for ((wanted, attribute), (had, _)) in text_attributes(to.text)
+        .into_iter()
+        .zip(text_attributes(from.text))
+    {
+        if wanted && !had {
+            queue!(out, SetAttribute(attribute))?;
+        }
+    }
+    if to.foreground != from.foreground {
+        queue!(
+            out,
+            SetForegroundColor(to.foreground.unwrap_or(Color::Reset))
+        )?;
+    }
+    if to.background != from.background {
+        queue!(
+            out,
+            SetBackgroundColor(to.background.unwrap_or(Color::Reset))
+        )?;
+    }
+    Ok(())
+}
+
+fn text_attributes(style: TextStyle) -> [(bool, TextAttribute); 6] {
+    [
+        (style.bold, TextAttribute::Bold),
+        (style.italic, TextAttribute::Italic),
+        (style.underline, TextAttribute::Underlined),
+        (style.undercurl, TextAttribute::Undercurled),
+        (style.dim, TextAttribute::Dim),
+        (style.reverse, TextAttribute::Reverse),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flushes_only_changed_cells() {
+        let mut screen = Screen::default();
+        screen.resize(Size {
+            height: 2,
+            width: 6,
+        });
+        screen.set_row(0, [("hello", CellStyle::default())]);
+        let mut first = Vec::new();
+        screen.flush(&mut first).expect("flush");
+        let first = String::from_utf8(first).expect("utf-8");
+        assert!(first.contains("hello"));
+
+        let bold = CellStyle {
+            text: TextStyle {
+                bold: true,
+                ..TextStyle::default()
+            },
+            ..CellStyle::default()
+        };
+        screen.set_row(
+            0,
+            [
+                ("he", CellStyle::default()),
+                ("y", bold),
+                ("lo", CellStyle::default()),
+            ],
+        );
+        let mut second = Vec::new();
+        screen.flush(&mut second).expect("flush");
+        let second = String::from_utf8(second).expect("utf-8");
+        assert!(second.contains("\u{1b}[1;3H\u{1b}[1my"));
+        assert!(!second.contains("he") && !second.contains("lo"));
+        assert!(!second.contains("\u{1b}[2J"));
+
+        let mut third = Vec::new();
+        screen.flush(&mut third).expect("flush");
+        assert!(!String::from_utf8(third).expect("utf-8").contains('y'));
+    }
+}
@@ -1,12 +1,22 @@
 use std::{cmp::min, io::Error};
 
-use super::super::{Line, Size, Terminal, command::Edit};
+use unicode_width::UnicodeWidthStr;
+
+use super::super::{
+    Line, Size, Terminal,
+    command::{Edit, Move, MoveDirection},
+};
 use super::UIComponent;
 
 #[derive(Default)]
 pub struct CommandBar {
     prompt: String,
     value: Line,
+    caret: usize,
+    status: String,
+    history: Vec<String>,
+    history_idx: Option<usize>,
+    draft: String,
     needs_redraw: bool,
     size: Size,
 }
@@ -14,32 +24,147 @@ pub struct CommandBar {
 impl CommandBar {
     pub fn handle_edit_command(&mut self, command: Edit) {
         match command {
-            Edit::Insert(character) => self.value.append_char(character),
-            Edit::Delete | Edit::InsertNewline => {}
-            Edit::Backspace => self.value.delete_last(),
-            Edit::Copy | Edit::Cut | Edit::Paste => {}
-            Edit::SelectAll => {}
+            Edit::Insert(character) => {
+                let old_len = self.value.grapheme_count();
+                self.value.insert_char(character, self.caret);
+                if self.value.grapheme_count() > old_len {
+                    self.caret = self.caret.saturating_add(1);
+                }
+            }
+            Edit::Backspace => {
+                if self.caret > 0 {
+                    self.caret -= 1;
+                    self.value.delete(self.caret);
+                }
+            }
+            Edit::Delete => self.value.delete(self.caret),
+            Edit::DeleteWord => {
+                let word_start = self.value.previous_word_start(self.caret);
+                self.value.delete_grapheme_range(word_start..self.caret);
+                self.caret = word_start;
+            }
+            Edit::InsertNewline | Edit::Copy | Edit::Cut | Edit::Paste | Edit::SelectAll => {}
+        }
+        self.mark_redraw(true);
+    }
+
+    /// Moves the caret within the value. Up/Down are left to the caller,
+    /// which decides whether they recall history or mean something else.
+    pub fn handle_move_command(&mut self, command: Move) {
+        match command.direction {
+            MoveDirection::Left => self.caret = self.caret.saturating_sub(1),
+            MoveDirection::Right => {
+                self.caret = min(self.caret.saturating_add(1), self.value.grapheme_count());
+            }
+            MoveDirection::LineStart => self.caret = 0,
+            MoveDirection::LineEnd => self.caret = self.value.grapheme_count(),
+            MoveDirection::Up
+            | MoveDirection::Down
+            | MoveDirection::PageUp
+            | MoveDirection::PageDown => {}
         }
         self.mark_redraw(true);
     }
+
     pub fn caret_position_col(&self) -> usize {
-        let max_width = self
+        let caret_col = self
             .prompt
-            .len()
-            .saturating_add(self.value.grapheme_count());
-        min(max_width, self.size.width)
+            .width()
+            .saturating_add(self.value.width_until(self.caret))
+            .saturating_sub(self.visible_value_start());
+        min(caret_col, self.size.width)
+    }
+
+    fn area_for_value(&self) -> usize {
+        let status_width = if self.status.is_empty() {
+            0
+        } else {
+            self.status.width().saturating_add(1)
+        };
+        self.size
+            .width
+            .saturating_sub(self.prompt.width())
+            .saturating_sub(status_width)
     }
+
+    /// Scrolls the value horizontally just enough to keep the caret visible.
+    fn visible_value_start(&self) -> usize {
+        let area_for_value = self.area_for_value();
+        let caret_width = self.value.width_until(self.caret);
+        if self.value.width() < area_for_value {
+            0
+        } else {
+            caret_width.saturating_sub(area_for_value.saturating_sub(1))
+        }
+    }
+
     pub fn value(&self) -> String {
         self.value.to_string()
     }
+
+    pub fn set_value(&mut self, value: &str) {
+        self.value = Line::from(value);
+        self.caret = self.value.grapheme_count();
+        self.mark_redraw(true);
+    }
+
     pub fn set_prompt(&mut self, prompt: &str) {
         self.prompt = prompt.to_string();
         self.mark_redraw(true);
     }
+
+    /// Sets text shown right-aligned after the value, e.g. a match counter.
+    pub fn set_status(&mut self, status: &str) {
+        if self.status != status {
+            self.status = status.to_string();
+            self.mark_redraw(true);
+        }
+    }
+
     pub fn clear_value(&mut self) {
         self.value = Line::default();
+        self.caret = 0;
+        self.status.clear();
+        self.history.clear();
+        self.history_idx = None;
+        self.draft.clear();
         self.mark_redraw(true);
     }
+
+    /// Sets the entries recalled with Up/Down, oldest first.
+    pub fn set_history(&mut self, history: &[String]) {
+        self.history = history.to_vec();
+        self.history_idx = None;
+    }
+
+    pub fn history_prev(&mut self) {
+        let idx = match self.history_idx {
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.value();
+                self.history.len() - 1
+            }
+            Some(idx) => idx.saturating_sub(1),
+        };
+        self.history_idx = Some(idx);
+        let entry = self.history[idx].clone();
+        self.set_value(&entry);
+    }
+
+    pub fn history_next(&mut self) {
+        let Some(idx) = self.history_idx else {
+            return;
+        };
+        if idx + 1 < self.history.len() {
+            self.history_idx = Some(idx + 1);
+            let entry = self.history[idx + 1].clone();
+            self.set_value(&entry);
+        } else {
+            self.history_idx = None;
+            let draft = std::mem::take(&mut self.draft);
+            self.set_value(&draft);
+        }
+    }
 }
 
 impl UIComponent for CommandBar {
@@ -53,19 +178,33 @@ impl UIComponent for CommandBar {
         self.size = size;
     }
     fn draw(&mut self, origin: usize) -> Result<(), Error> {
-        let area_for_value = self.size.width.saturating_sub(self.prompt.len());
-        let value_end = self.value.width();
-        let value_start = value_end.saturating_sub(area_for_value);
+        let value_start = self.visible_value_start();
+        let value_end = min(
+            self.value.width(),
+            value_start.saturating_add(self.area_for_value()),
+        );
         let message = format!(
             "{}{}",
             self.prompt,
             self.value.get_visible_graphemes(value_start..value_end)
         );
-        let to_print = if message.len() <= self.size.width {
+        let message = if self.status.is_empty() {
+            message
+        } else {
+            // `{:>width$}` pads by chars, not columns.
+            let padding = self
+                .size
+                .width
+                .saturating_sub(message.width())
+                .saturating_sub(self.status.width());
+            format!("{message}{}{}", " ".repeat(padding), self.status)
+        };
+        let to_print = if message.width() <= self.size.width {
             message
         } else {
             String::new()
         };
-        Terminal::print_row(origin, &to_print)
+        Terminal::print_row(origin, &to_print);
+        Ok(())
     }
 }
@@ -0,0 +1,86 @@
+use super::super::{AnnotatedString, AnnotationType, Size, Terminal};
+use super::UIComponent;
+use std::io::Error;
+use unicode_width::UnicodeWidthStr;
+
+const SEPARATOR: &str = "  ";
+
+/// A single row of completion candidates, drawn above the command bar in
+/// place of the status bar while a prompt offers completions.
+#[derive(Default)]
+pub struct CompletionList {
+    items: Vec<String>,
+    selected: Option<usize>,
+    needs_redraw: bool,
+    size: Size,
+}
+
+impl CompletionList {
+    pub fn set_items(&mut self, items: &[String], selected: Option<usize>) {
+        self.items = items.to_vec();
+        self.selected = selected;
+        self.mark_redraw(true);
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.selected = None;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        !self.items.is_empty()
+    }
+}
+
+impl UIComponent for CompletionList {
+    fn mark_redraw(&mut self, value: bool) {
+        self.needs_redraw = value;
+    }
+
+    fn needs_redraw(&self) -> bool {
+        self.needs_redraw
+    }
+
+    fn set_size(&mut self, size: Size) {
+        self.size = size;
+    }
+
+    fn draw(&mut self, origin_row: usize) -> Result<(), Error> {
+        // Start the row at the selected item when it would otherwise be cut off.
+        let first = self.selected.map_or(0, |selected| {
+            let width_until_selected: usize = self.items[..=selected]
+                .iter()
+                .map(|item| item.width() + SEPARATOR.len())
+                .sum();
+            if width_until_selected > self.size.width {
+                selected
+            } else {
+                0
+            }
+        });
+
+        let mut row = String::new();
+        let mut row_width = 0;
+        let mut selected_range = None;
+        for (idx, item) in self.items.iter().enumerate().skip(first) {
+            let item_width = item.width();
+            if row_width + item_width > self.size.width {
+                break;
+            }
+            // The annotation is placed by bytes, the fit is measured in columns.
+            if Some(idx) == self.selected {
+                selected_range = Some(row.len()..row.len() + item.len());
+            }
+            row.push_str(item);
+            row.push_str(SEPARATOR);
+            row_width += item_width + SEPARATOR.len();
+        }
+
+        let mut annotated_string = AnnotatedString::from(&row);
+        if let Some(range) = selected_range {
+            annotated_string.add_annotation(AnnotationType::Selection, range.start, range.end);
+        }
+        Terminal::print_annotated_row(origin_row, &annotated_string);
+        Ok(())
+    }
+}
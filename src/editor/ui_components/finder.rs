@@ -0,0 +1,117 @@
+use super::super::{AnnotatedString, AnnotationType, Line, Size, Terminal};
+use super::UIComponent;
+use std::{cmp::min, io::Error};
+
+const MAX_LIST_ROWS: usize = 10;
+
+/// A ranked list of matches drawn in place of the text area, with a preview
+/// of the selected entry above it.
+#[derive(Default)]
+pub struct Finder {
+    items: Vec<String>,
+    selected: usize,
+    total: usize,
+    preview: Vec<String>,
+    needs_redraw: bool,
+    size: Size,
+}
+
+impl Finder {
+    /// Sets the ranked matches, best first, out of `total` candidates.
+    pub fn set_items(&mut self, items: Vec<String>, total: usize) {
+        self.items = items;
+        self.total = total;
+        self.selected = 0;
+        self.mark_redraw(true);
+    }
+
+    pub fn set_preview(&mut self, preview: Vec<String>) {
+        self.preview = preview;
+        self.mark_redraw(true);
+    }
+
+    pub const fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_item(&self) -> Option<&str> {
+        self.items.get(self.selected).map(String::as_str)
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.items.len() {
+            self.selected += 1;
+            self.mark_redraw(true);
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+            self.mark_redraw(true);
+        }
+    }
+
+    /// The number of preview lines that fit above the list.
+    pub fn preview_height(&self) -> usize {
+        self.size
+            .height
+            .saturating_sub(self.list_height())
+            .saturating_sub(1)
+    }
+
+    fn list_height(&self) -> usize {
+        min(MAX_LIST_ROWS, self.size.height / 2)
+    }
+
+    fn fit_to_width(&self, text: &str) -> String {
+        Line::from(text).get_visible_graphemes(0..self.size.width)
+    }
+}
+
+impl UIComponent for Finder {
+    fn mark_redraw(&mut self, value: bool) {
+        self.needs_redraw = value;
+    }
+
+    fn needs_redraw(&self) -> bool {
+        self.needs_redraw
+    }
+
+    fn set_size(&mut self, size: Size) {
+        self.size = size;
+    }
+
+    fn draw(&mut self, origin_row: usize) -> Result<(), Error> {
+        let preview_height = self.preview_height();
+        for row in 0..preview_height {
+            let line = self
+                .preview
+                .get(row)
+                .map_or_else(String::new, |line| self.fit_to_width(line));
+            Terminal::print_row(origin_row + row, &line);
+        }
+
+        let separator_row = origin_row + preview_height;
+        Terminal::print_inverted_row(
+            separator_row,
+            &format!("{}/{} matches", self.items.len(), self.total),
+        );
+
+        let list_height = self.list_height();
+        let first = (self.selected + 1).saturating_sub(list_height);
+        for row in 0..list_height {
+            let idx = first + row;
+            let text = self
+                .items
+                .get(idx)
+                .map_or_else(String::new, |item| self.fit_to_width(&format!("  {item}")));
+            let mut annotated_string = AnnotatedString::from(&text);
+            if idx == self.selected && !text.is_empty() {
+                annotated_string.add_annotation(AnnotationType::Selection, 0, text.len());
+            }
+            Terminal::print_annotated_row(separator_row + 1 + row, &annotated_string);
+        }
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use super::super::{Size, Terminal};
+use super::super::{AnnotationType, Size, Terminal};
 use super::UIComponent;
 
 const DEFAULT_DURATION: Duration = Duration::new(5, 0);
@@ -74,6 +74,7 @@ impl UIComponent for MessageBar {
         } else {
             String::new()
         };
-        Terminal::print_row(origin, &to_print)
+        Terminal::print_themed_row(origin, &to_print, AnnotationType::MessageBar);
+        Ok(())
     }
 }
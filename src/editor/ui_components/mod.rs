@@ -1,10 +1,14 @@
 mod command_bar;
+mod completion_list;
+mod finder;
 mod message_bar;
 mod status_bar;
 mod ui_component;
 mod view;
 
 pub use command_bar::CommandBar;
+pub use completion_list::CompletionList;
+pub use finder::Finder;
 pub use message_bar::MessageBar;
 pub use status_bar::StatusBar;
 pub use ui_component::UIComponent;
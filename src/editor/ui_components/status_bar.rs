@@ -1,4 +1,4 @@
-use super::super::{DocumentStatus, Size, Terminal};
+use super::super::{AnnotationType, DocumentStatus, Size, Terminal};
 use super::UIComponent;
 use std::io::Error;
 
@@ -40,8 +40,19 @@ impl UIComponent for StatusBar {
             .map(|lang| format!(" ({lang})"))
             .unwrap_or_default();
 
+        let mode_indicator = self
+            .current_status
+            .mode
+            .map(|mode| format!("-- {mode} -- "))
+            .unwrap_or_default();
+        let recording_indicator = if self.current_status.is_recording {
+            "[REC] "
+        } else {
+            ""
+        };
+
         let beginning = format!(
-            "{}{language_indicator} - {line_count} {modified_indicator}",
+            "{recording_indicator}{mode_indicator}{}{language_indicator} - {line_count} {modified_indicator}",
             self.current_status.file_name
         );
 
@@ -54,7 +65,7 @@ impl UIComponent for StatusBar {
         } else {
             String::new()
         };
-        Terminal::print_inverted_row(origin_row, &to_print)?;
+        Terminal::print_themed_row(origin_row, &to_print, AnnotationType::StatusBar);
 
         Ok(())
     }
@@ -35,8 +35,9 @@ impl Buffer {
     }
 
     pub fn save_as(&mut self, file_name: &str) -> Result<(), Error> {
-        let file_info = FileInfo::from(file_name);
+        let mut file_info = FileInfo::from(file_name);
         self.save_to_file(&file_info)?;
+        file_info.refresh_disk_state();
         self.file_info = file_info;
         self.modified = false;
         Ok(())
@@ -44,6 +45,7 @@ impl Buffer {
 
     pub fn save(&mut self) -> Result<(), Error> {
         self.save_to_file(&self.file_info)?;
+        self.file_info.refresh_disk_state();
         self.modified = false;
         Ok(())
     }
@@ -99,6 +101,21 @@ impl Buffer {
         }
     }
 
+    pub fn match_locations(&self, query: &str) -> Vec<Location> {
+        self.lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line_idx, line)| {
+                line.match_grapheme_indices(query)
+                    .into_iter()
+                    .map(move |grapheme_idx| Location {
+                        grapheme_idx,
+                        line_idx,
+                    })
+            })
+            .collect()
+    }
+
     pub fn search_forward(&self, query: &str, from: Location) -> Option<Location> {
         if query.is_empty() {
             return None;
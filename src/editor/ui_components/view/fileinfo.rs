@@ -1,18 +1,30 @@
 use std::{
     fmt::{self, Display},
+    fs,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct FileInfo {
     path: Option<PathBuf>,
+    disk_state: Option<DiskState>,
 }
 
 impl FileInfo {
     pub fn from(file_name: &str) -> Self {
-        Self {
+        let mut file_info = Self {
             path: Some(PathBuf::from(file_name)),
-        }
+            disk_state: None,
+        };
+        file_info.refresh_disk_state();
+        file_info
     }
 
     pub fn get_path(&self) -> Option<&Path> {
@@ -22,6 +34,28 @@ impl FileInfo {
     pub const fn has_path(&self) -> bool {
         self.path.is_some()
     }
+
+    fn read_disk_state(&self) -> Option<DiskState> {
+        let metadata = fs::metadata(self.path.as_ref()?).ok()?;
+        Some(DiskState {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+
+    /// Records the file's current modification time and size as the known state.
+    pub fn refresh_disk_state(&mut self) {
+        self.disk_state = self.read_disk_state();
+    }
+
+    /// Returns true if the file on disk no longer matches the recorded state.
+    /// A file that has been deleted is not reported as changed.
+    pub fn changed_on_disk(&self) -> bool {
+        match (self.disk_state, self.read_disk_state()) {
+            (Some(known), Some(current)) => known != current,
+            _ => false,
+        }
+    }
 }
 
 impl Display for FileInfo {
@@ -0,0 +1,282 @@
+use super::super::super::{
+    Line,
+    folding::{FoldCommand, FoldRegion, bracket_regions, indent_regions},
+    highlight::HighlightState,
+    line::tab_width,
+};
+use super::super::UIComponent;
+use super::{Location, View};
+use std::{collections::BTreeMap, ops::Range};
+
+/// The closed folds of one buffer, by first line. Like marks, they are kept
+/// on their text when lines are inserted or removed.
+#[derive(Default)]
+pub struct Folds {
+    closed: BTreeMap<usize, usize>,
+}
+
+impl Folds {
+    pub fn is_empty(&self) -> bool {
+        self.closed.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.closed.len()
+    }
+
+    /// The outermost closed fold hiding `line_idx`, as `(start, end)`.
+    pub fn hiding(&self, line_idx: usize) -> Option<(usize, usize)> {
+        self.closed
+            .range(..line_idx)
+            .find(|(_, end)| **end >= line_idx)
+            .map(|(start, end)| (*start, *end))
+    }
+
+    pub fn is_hidden(&self, line_idx: usize) -> bool {
+        self.hiding(line_idx).is_some()
+    }
+
+    /// The line shown in place of `line_idx`: itself, or the first line of the
+    /// fold hiding it.
+    pub fn visible_line(&self, line_idx: usize) -> usize {
+        self.hiding(line_idx).map_or(line_idx, |(start, _)| start)
+    }
+
+    /// The last line of the closed fold starting at the visible `line_idx`.
+    pub fn folded_end(&self, line_idx: usize) -> Option<usize> {
+        if self.is_hidden(line_idx) {
+            return None;
+        }
+        self.closed.get(&line_idx).copied()
+    }
+
+    pub fn next_visible(&self, line_idx: usize) -> usize {
+        self.folded_end(line_idx)
+            .unwrap_or(line_idx)
+            .saturating_add(1)
+    }
+
+    pub fn prev_visible(&self, line_idx: usize) -> Option<usize> {
+        line_idx
+            .checked_sub(1)
+            .map(|line_idx| self.visible_line(line_idx))
+    }
+
+    /// Opens every fold hiding `line_idx`.
+    fn reveal(&mut self, line_idx: usize) -> bool {
+        let before = self.closed.len();
+        self.closed
+            .retain(|start, end| !(*start < line_idx && line_idx <= *end));
+        self.closed.len() != before
+    }
+
+    pub fn lines_inserted(&mut self, at: usize, count: usize) {
+        self.closed = std::mem::take(&mut self.closed)
+            .into_iter()
+            .map(|(start, end)| {
+                if start >= at {
+                    (start + count, end + count)
+                } else if end >= at {
+                    (start, end + count)
+                } else {
+                    (start, end)
+                }
+            })
+            .collect();
+    }
+
+    /// Folds starting on a removed line go away, folds around the removed
+    /// lines shrink.
+    pub fn lines_removed(&mut self, removed: Range<usize>) {
+        let count = removed.len();
+        self.closed = std::mem::take(&mut self.closed)
+            .into_iter()
+            .filter(|(start, _)| !removed.contains(start))
+            .filter_map(|(start, end)| {
+                let shift = |line_idx: usize| {
+                    if line_idx >= removed.end {
+                        line_idx - count
+                    } else if line_idx >= removed.start {
+                        removed.start.saturating_sub(1)
+                    } else {
+                        line_idx
+                    }
+                };
+                let (start, end) = (shift(start), shift(end));
+                (end > start).then_some((start, end))
+            })
+            .collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.closed.clear();
+    }
+}
+
+impl View {
+    /// Fold regions from bracket nesting when the language has a highlighter
+    /// that tracks brackets, from indentation otherwise. Bracket levels are
+    /// read from the highlight cache. While too much of the buffer is not
+    /// highlighted yet, that is left to the worker as when rendering, and
+    /// indentation is used meanwhile.
+    fn fold_regions(&mut self) -> Vec<FoldRegion> {
+        let highlighter = self
+            .highlighter_registry
+            .get_shared_highlighter(self.language.as_deref())
+            .filter(|_| !self.highlighting_disabled)
+            .cloned();
+        if let Some(hl) = highlighter {
+            self.update_document(hl.as_ref());
+            let line_count = self.buffer.height();
+            if self
+                .highlight_state_after(&hl, 0..line_count, HighlightState::default())
+                .is_none()
+            {
+                return self.indent_regions();
+            }
+            let mut state = HighlightState::default();
+            let mut depths = vec![0];
+            for (line_idx, line) in self.buffer.lines.iter().enumerate() {
+                state = self
+                    .highlight_cache
+                    .highlight(hl.as_ref(), line, line_idx, state)
+                    .1;
+                depths.push(state.paren_level + state.brace_level + state.bracket_level);
+            }
+            let regions = bracket_regions(&depths);
+            if !regions.is_empty() {
+                return regions;
+            }
+        }
+        self.indent_regions()
+    }
+
+    fn indent_regions(&self) -> Vec<FoldRegion> {
+        let indents: Vec<Option<usize>> = self.buffer.lines.iter().map(indentation).collect();
+        indent_regions(&indents)
+    }
+
+    /// Runs a fold command. Returns false when there is no fold at the caret
+    /// to close or open.
+    pub fn fold(&mut self, command: FoldCommand) -> bool {
+        let line_idx = self.text_location.line_idx;
+        let regions = self.fold_regions();
+        let done = match command {
+            FoldCommand::Close => self.close_fold_at(&regions, line_idx),
+            FoldCommand::Open => self.open_fold_at(line_idx),
+            FoldCommand::Toggle => {
+                self.open_fold_at(line_idx) || self.close_fold_at(&regions, line_idx)
+            }
+            FoldCommand::CloseAll => {
+                self.set_fold_level(&regions, 0);
+                !regions.is_empty()
+            }
+            FoldCommand::OpenAll => {
+                self.folds.clear();
+                true
+            }
+            FoldCommand::Level(level) => {
+                self.set_fold_level(&regions, level);
+                true
+            }
+        };
+        self.text_location = Location {
+            line_idx: self.folds.visible_line(line_idx),
+            ..self.text_location
+        };
+        self.snap_to_valid_grapheme();
+        self.scroll_offset.row = self.folds.visible_line(self.scroll_offset.row);
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+        done
+    }
+
+    fn close_fold_at(&mut self, regions: &[FoldRegion], line_idx: usize) -> bool {
+        let innermost_open = regions
+            .iter()
+            .filter(|region| {
+                region.contains(line_idx) && self.folds.folded_end(region.start).is_none()
+            })
+            .max_by_key(|region| region.level);
+        match innermost_open {
+            Some(region) => {
+                self.folds.closed.insert(region.start, region.end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn open_fold_at(&mut self, line_idx: usize) -> bool {
+        self.folds.closed.remove(&line_idx).is_some()
+    }
+
+    fn set_fold_level(&mut self, regions: &[FoldRegion], level: usize) {
+        self.folds.clear();
+        for region in regions.iter().filter(|region| region.level > level) {
+            self.folds.closed.insert(region.start, region.end);
+        }
+    }
+
+    /// Opens any fold hiding the caret, e.g. after a jump into folded text.
+    pub(super) fn reveal_text_location(&mut self) {
+        if self.folds.reveal(self.text_location.line_idx) {
+            self.mark_redraw(true);
+        }
+    }
+
+    /// The line reached by moving `count` visible lines down, or up, from
+    /// `line_idx`, skipping closed folds.
+    pub(super) fn step_visible_lines(&self, line_idx: usize, count: usize, down: bool) -> usize {
+        let last_line_idx = self.buffer.height().saturating_sub(1);
+        let mut line_idx = self.folds.visible_line(line_idx);
+        for _ in 0..count {
+            let next = if down {
+                Some(self.folds.next_visible(line_idx)).filter(|next| *next <= last_line_idx)
+            } else {
+                self.folds.prev_visible(line_idx)
+            };
+            match next {
+                Some(next) => line_idx = next,
+                None => break,
+            }
+        }
+        line_idx
+    }
+
+    /// The number of screen rows between the visible lines `from` and `to`.
+    pub(super) fn visible_rows_between(&self, from: usize, to: usize) -> usize {
+        if self.folds.is_empty() {
+            return to.saturating_sub(from);
+        }
+        let mut rows = 0;
+        let mut line_idx = from;
+        while line_idx < to {
+            line_idx = self.folds.next_visible(line_idx);
+            rows += 1;
+        }
+        rows
+    }
+
+    /// The number of lines a closed fold hides after the visible `line_idx`.
+    pub(super) fn hidden_after(&self, line_idx: usize) -> usize {
+        self.folds
+            .folded_end(line_idx)
+            .map_or(0, |end| end - line_idx)
+    }
+}
+
+/// The indentation width of a line, or `None` if it is blank.
+fn indentation(line: &Line) -> Option<usize> {
+    let text = line.to_string();
+    let content = text.trim_start();
+    if content.is_empty() {
+        return None;
+    }
+    Some(
+        text[..text.len() - content.len()]
+            .chars()
+            .map(|ch| if ch == '\t' { tab_width() } else { 1 })
+            .sum(),
+    )
+}
@@ -0,0 +1,268 @@
+use super::super::super::highlight::{HighlightAnnotation, HighlightState, Highlighter};
+use super::super::super::line::Line;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Range;
+
+/// Highlighting results by line. An entry is reused for as long as its line
+/// is unchanged and the line above still ends in the state the entry started
+/// from, so after an edit only the edited line and the lines whose start
+/// state it changed are highlighted again.
+#[derive(Default)]
+pub struct HighlightCache {
+    lines: Vec<Option<CachedLine>>,
+    /// Lines edited, or whose entries were stored from elsewhere, since they
+    /// were last checked. Entries outside it are still valid as long as they
+    /// start from the same state, so their lines are not hashed again.
+    dirty: Range<usize>,
+}
+
+pub struct CachedLine {
+    text_hash: u64,
+    revision: u64,
+    start_state: HighlightState,
+    end_state: HighlightState,
+    annotations: Vec<HighlightAnnotation>,
+}
+
+impl CachedLine {
+    pub fn new(
+        hl: &dyn Highlighter,
+        line: &str,
+        line_idx: usize,
+        start_state: HighlightState,
+    ) -> Self {
+        let revision = hl.line_revision(line_idx);
+        let (annotations, end_state) = hl.highlight_line(line, line_idx, start_state);
+        Self {
+            text_hash: hash_line(line),
+            revision,
+            start_state,
+            end_state,
+            annotations,
+        }
+    }
+
+    pub fn end_state(&self) -> HighlightState {
+        self.end_state
+    }
+
+    fn is_valid(&self, text_hash: u64, revision: u64) -> bool {
+        self.text_hash == text_hash && self.revision == revision
+    }
+}
+
+impl HighlightCache {
+    /// The annotations of `line` and the state at its end, from the cache
+    /// when they are still valid.
+    pub fn highlight(
+        &mut self,
+        hl: &dyn Highlighter,
+        line: &str,
+        line_idx: usize,
+        start_state: HighlightState,
+    ) -> (&[HighlightAnnotation], HighlightState) {
+        let valid = self.get(hl, line, line_idx, start_state).is_some();
+        if !valid {
+            self.checked(line_idx);
+        }
+        if self.lines.len() <= line_idx {
+            self.lines.resize_with(line_idx + 1, || None);
+        }
+        let slot = &mut self.lines[line_idx];
+        let cached = if valid && let Some(cached) = slot {
+            cached
+        } else {
+            slot.insert(CachedLine::new(hl, line, line_idx, start_state))
+        };
+        (&cached.annotations, cached.end_state)
+    }
+
+    /// The state at the end of `range` when every line in it is cached.
+    /// Otherwise the first line that is not, with the state it starts from.
+    pub fn cached_state(
+        &mut self,
+        hl: &dyn Highlighter,
+        lines: &[Line],
+        range: Range<usize>,
+        mut state: HighlightState,
+    ) -> Result<HighlightState, (usize, HighlightState)> {
+        for line_idx in range {
+            let Some(line) = lines.get(line_idx) else {
+                break;
+            };
+            match self.get(hl, line, line_idx, state) {
+                Some(cached) => state = cached.end_state,
+                None => return Err((line_idx, state)),
+            }
+        }
+        Ok(state)
+    }
+
+    /// Stores a line highlighted elsewhere. It is checked like any other
+    /// entry before it is used.
+    pub fn insert(&mut self, line_idx: usize, cached: CachedLine) {
+        if self.lines.len() <= line_idx {
+            self.lines.resize_with(line_idx + 1, || None);
+        }
+        self.lines[line_idx] = Some(cached);
+        self.lines_changed(line_idx..line_idx + 1);
+    }
+
+    fn get(
+        &mut self,
+        hl: &dyn Highlighter,
+        line: &str,
+        line_idx: usize,
+        start_state: HighlightState,
+    ) -> Option<&CachedLine> {
+        let cached = self.lines.get(line_idx)?.as_ref()?;
+        if cached.start_state != start_state {
+            return None;
+        }
+        if self.dirty.contains(&line_idx) {
+            if !cached.is_valid(hash_line(line), hl.line_revision(line_idx)) {
+                return None;
+            }
+            self.checked(line_idx);
+        }
+        self.lines[line_idx].as_ref()
+    }
+
+    /// The entry of `line_idx` is valid now. Checking lines in order from the
+    /// first dirty one moves the start of `dirty` down.
+    fn checked(&mut self, line_idx: usize) {
+        if self.dirty.start == line_idx && line_idx < self.dirty.end {
+            self.dirty.start += 1;
+        }
+    }
+
+    /// Lines `rows` were edited in place, or their annotations changed.
+    pub fn lines_changed(&mut self, rows: Range<usize>) {
+        if rows.is_empty() {
+            return;
+        }
+        self.dirty = if self.dirty.is_empty() {
+            rows
+        } else {
+            self.dirty.start.min(rows.start)..self.dirty.end.max(rows.end)
+        };
+    }
+
+    /// Moves entries down past `count` lines inserted at `at`.
+    pub fn lines_inserted(&mut self, at: usize, count: usize) {
+        if at <= self.lines.len() {
+            self.lines
+                .splice(at..at, std::iter::repeat_with(|| None).take(count));
+        }
+        let shift = |line_idx: usize| {
+            if line_idx > at {
+                line_idx + count
+            } else {
+                line_idx
+            }
+        };
+        self.dirty = shift(self.dirty.start)..shift(self.dirty.end);
+        self.lines_changed(at..at + count);
+    }
+
+    /// Moves entries up over the `removed` lines.
+    pub fn lines_removed(&mut self, removed: Range<usize>) {
+        let end = removed.end.min(self.lines.len());
+        if removed.start < end {
+            self.lines.drain(removed.start..end);
+        }
+        let shift = |line_idx: usize| {
+            if line_idx >= removed.end {
+                line_idx - removed.len()
+            } else {
+                line_idx.min(removed.start)
+            }
+        };
+        self.dirty = shift(self.dirty.start)..shift(self.dirty.end);
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.dirty = 0..0;
+    }
+}
+
+fn hash_line(line: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    line.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Opens a block comment on `/*` and counts the lines it highlights.
+    #[derive(Default)]
+    struct CountingHighlighter {
+        calls: AtomicUsize,
+    }
+
+    impl Highlighter for CountingHighlighter {
+        fn highlight_line(
+            &self,
+            line: &str,
+            _line_idx: usize,
+            mut state: HighlightState,
+        ) -> (Vec<HighlightAnnotation>, HighlightState) {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            if line.contains("/*") {
+                state.in_block_comment = true;
+            }
+            if line.contains("*/") {
+                state.in_block_comment = false;
+            }
+            (Vec::new(), state)
+        }
+
+        fn language_name(&self) -> &'static str {
+            "counting"
+        }
+    }
+
+    fn highlight_all(
+        cache: &mut HighlightCache,
+        hl: &CountingHighlighter,
+        lines: &[&str],
+    ) -> usize {
+        hl.calls.store(0, Ordering::Relaxed);
+        let mut state = HighlightState::default();
+        for (line_idx, line) in lines.iter().enumerate() {
+            state = cache.highlight(hl, line, line_idx, state).1;
+        }
+        hl.calls.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn highlights_again_only_until_states_match() {
+        let hl = CountingHighlighter::default();
+        let mut cache = HighlightCache::default();
+        let mut lines = vec!["a", "b", "c", "d"];
+        assert_eq!(highlight_all(&mut cache, &hl, &lines), 4);
+        assert_eq!(highlight_all(&mut cache, &hl, &lines), 0);
+
+        lines[1] = "b2";
+        cache.lines_changed(1..2);
+        assert_eq!(highlight_all(&mut cache, &hl, &lines), 1);
+        assert!(cache.dirty.is_empty());
+
+        // Opening a comment changes the start state of every line below.
+        lines[1] = "/* b";
+        cache.lines_changed(1..2);
+        assert_eq!(highlight_all(&mut cache, &hl, &lines), 3);
+
+        lines.insert(2, "*/");
+        cache.lines_inserted(2, 1);
+        assert_eq!(highlight_all(&mut cache, &hl, &lines), 3);
+
+        lines.remove(0);
+        cache.lines_removed(0..1);
+        assert_eq!(highlight_all(&mut cache, &hl, &lines), 0);
+    }
+}
@@ -0,0 +1,142 @@
+use super::super::super::highlight::{HighlightState, Highlighter};
+use super::super::super::line::Line;
+use super::highlight_cache::{CachedLine, HighlightCache};
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Highlights lines on a thread of its own, so that jumping far into a large
+/// file does not wait for every line above the viewport. Finished lines are
+/// moved into the cache, which checks them like any other entry.
+#[derive(Default)]
+pub struct HighlightWorker {
+    job: Option<Job>,
+}
+
+struct Job {
+    range: Range<usize>,
+    version: u64,
+    results: Receiver<(usize, CachedLine)>,
+}
+
+impl HighlightWorker {
+    /// Starts highlighting `range` of `lines` from `start_state`, unless the
+    /// running job already covers it for the same buffer `version`.
+    pub fn request(
+        &mut self,
+        hl: Arc<dyn Highlighter>,
+        lines: &[Line],
+        range: Range<usize>,
+        start_state: HighlightState,
+        version: u64,
+    ) {
+        if let Some(job) = &self.job
+            && job.version == version
+            && job.range.start <= range.start
+            && job.range.end >= range.end
+        {
+            return;
+        }
+        let first_line_idx = range.start;
+        let texts: Vec<String> = lines
+            .iter()
+            .take(range.end)
+            .skip(range.start)
+            .map(ToString::to_string)
+            .collect();
+        let (sender, results) = mpsc::channel();
+        thread::spawn(move || {
+            let mut state = start_state;
+            for (offset, text) in texts.iter().enumerate() {
+                let cached = CachedLine::new(hl.as_ref(), text, first_line_idx + offset, state);
+                state = cached.end_state();
+                // The job was cancelled or replaced.
+                if sender.send((first_line_idx + offset, cached)).is_err() {
+                    return;
+                }
+            }
+        });
+        self.job = Some(Job {
+            range,
+            version,
+            results,
+        });
+    }
+
+    /// Moves the lines finished so far into `cache`. Returns the last line
+    /// that arrived, if any did.
+    pub fn receive(&mut self, cache: &mut HighlightCache) -> Option<usize> {
+        let job = self.job.as_ref()?;
+        let mut received = None;
+        loop {
+            match job.results.try_recv() {
+                Ok((line_idx, cached)) => {
+                    cache.insert(line_idx, cached);
+                    received = Some(line_idx);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.job = None;
+                    break;
+                }
+            }
+        }
+        received
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.job.is_some()
+    }
+
+    /// Drops the running job. Its thread stops at the next line it finishes.
+    pub fn cancel(&mut self) {
+        self.job = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::highlight::HighlightAnnotation;
+    use std::time::{Duration, Instant};
+
+    struct BraceHighlighter;
+
+    impl Highlighter for BraceHighlighter {
+        fn highlight_line(
+            &self,
+            line: &str,
+            _line_idx: usize,
+            mut state: HighlightState,
+        ) -> (Vec<HighlightAnnotation>, HighlightState) {
+            state.brace_level += line.matches('{').count();
+            state.brace_level -= line.matches('}').count();
+            (Vec::new(), state)
+        }
+
+        fn language_name(&self) -> &'static str {
+            "braces"
+        }
+    }
+
+    #[test]
+    fn fills_cache_in_the_background() {
+        let hl: Arc<dyn Highlighter> = Arc::new(BraceHighlighter);
+        let lines: Vec<Line> = ["{", "{", "}", "x"].into_iter().map(Line::from).collect();
+        let mut cache = HighlightCache::default();
+        let mut worker = HighlightWorker::default();
+        worker.request(Arc::clone(&hl), &lines, 0..4, HighlightState::default(), 1);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while worker.is_busy() && Instant::now() < deadline {
+            worker.receive(&mut cache);
+        }
+        assert!(!worker.is_busy());
+        let state = cache
+            .cached_state(hl.as_ref(), &lines, 0..4, HighlightState::default())
+            .ok()
+            .expect("every line cached");
+        assert_eq!(state.brace_level, 1);
+    }
+}
@@ -0,0 +1,223 @@
+use super::Location;
+use std::{collections::BTreeMap, ops::Range};
+
+const MAX_JUMPS: usize = 100;
+
+/// Named marks and the jump list of one buffer. Both hold plain locations,
+/// so the view reports every insertion and removal of whole lines to keep
+/// them on the text they were set on.
+#[derive(Default)]
+pub struct Marks {
+    named: BTreeMap<char, Location>,
+    jumps: Vec<Location>,
+    /// Position in `jumps` while moving back and forth; `jumps.len()` when
+    /// not navigating.
+    jump_idx: usize,
+}
+
+impl Marks {
+    pub const fn is_valid_name(name: char) -> bool {
+        name.is_ascii_alphabetic()
+    }
+
+    pub fn set(&mut self, name: char, location: Location) {
+        self.named.insert(name, location);
+    }
+
+    pub fn get(&self, name: char) -> Option<Location> {
+        self.named.get(&name).copied()
+    }
+
+    pub fn remove(&mut self, name: char) -> bool {
+        self.named.remove(&name).is_some()
+    }
+
+    pub fn has_named(&self) -> bool {
+        !self.named.is_empty()
+    }
+
+    /// The first mark, alphabetically, set on the given line.
+    pub fn on_line(&self, line_idx: usize) -> Option<char> {
+        self.named
+            .iter()
+            .find(|(_, location)| location.line_idx == line_idx)
+            .map(|(name, _)| *name)
+    }
+
+    /// Records the location a large move started from. Going back and then
+    /// jumping somewhere new drops the entries that were ahead.
+    pub fn push_jump(&mut self, from: Location) {
+        self.jumps.truncate(self.jump_idx.saturating_add(1));
+        if self.jumps.last() != Some(&from) {
+            self.jumps.push(from);
+        }
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.jump_idx = self.jumps.len();
+    }
+
+    /// Steps back through the jump list. The first step also remembers
+    /// `current`, so that jumping forward again returns to it.
+    pub fn jump_back(&mut self, current: Location) -> Option<Location> {
+        if self.jump_idx >= self.jumps.len() {
+            if self.jumps.last() != Some(&current) {
+                self.jumps.push(current);
+            }
+            self.jump_idx = self.jumps.len().saturating_sub(1);
+        }
+        if self.jump_idx == 0 {
+            return None;
+        }
+        self.jump_idx -= 1;
+        self.jumps.get(self.jump_idx).copied()
+    }
+
+    pub fn jump_forward(&mut self) -> Option<Location> {
+        if self.jump_idx.saturating_add(1) >= self.jumps.len() {
+            return None;
+        }
+        self.jump_idx += 1;
+        self.jumps.get(self.jump_idx).copied()
+    }
+
+    /// `count` lines were inserted before line `at`.
+    pub fn lines_inserted(&mut self, at: usize, count: usize) {
+        for location in self.locations_mut() {
+            if location.line_idx >= at {
+                location.line_idx = location.line_idx.saturating_add(count);
+            }
+        }
+    }
+
+    /// The lines in `removed` were deleted. Named marks on them go away, jump
+    /// list entries move to the line that took their place.
+    pub fn lines_removed(&mut self, removed: Range<usize>) {
+        if removed.is_empty() {
+            return;
+        }
+        self.named
+            .retain(|_, location| !removed.contains(&location.line_idx));
+        let count = removed.len();
+        for location in self.locations_mut() {
+            if location.line_idx >= removed.end {
+                location.line_idx -= count;
+            } else if location.line_idx >= removed.start {
+                *location = Location {
+                    line_idx: removed.start,
+                    grapheme_idx: 0,
+                };
+            }
+        }
+    }
+
+    /// Line `line_idx + 1` was appended to line `line_idx`, whose old length
+    /// was `joined_at` graphemes.
+    pub fn lines_joined(&mut self, line_idx: usize, joined_at: usize) {
+        let next_line_idx = line_idx.saturating_add(1);
+        for location in self.locations_mut() {
+            if location.line_idx == next_line_idx {
+                *location = Location {
+                    line_idx,
+                    grapheme_idx: location.grapheme_idx.saturating_add(joined_at),
+                };
+            } else if location.line_idx > next_line_idx {
+                location.line_idx -= 1;
+            }
+        }
+    }
+
+    /// Line `line_idx` was split in two before grapheme `at_grapheme`.
+    /// Locations from there on move to the new line with the text after them.
+    pub fn line_split(&mut self, line_idx: usize, at_grapheme: usize) {
+        for location in self.locations_mut() {
+            if location.line_idx > line_idx {
+                location.line_idx = location.line_idx.saturating_add(1);
+            } else if location.line_idx == line_idx && location.grapheme_idx >= at_grapheme {
+                *location = Location {
+                    line_idx: line_idx.saturating_add(1),
+                    grapheme_idx: location.grapheme_idx - at_grapheme,
+                };
+            }
+        }
+    }
+
+    /// The buffer was replaced by one of `line_count` lines. Locations past
+    /// its end move to its last line.
+    pub fn clamp_to_lines(&mut self, line_count: usize) {
+        let last_line_idx = line_count.saturating_sub(1);
+        for location in self.locations_mut() {
+            if location.line_idx > last_line_idx {
+                *location = Location {
+                    line_idx: last_line_idx,
+                    grapheme_idx: 0,
+                };
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn locations_mut(&mut self) -> impl Iterator<Item = &mut Location> {
+        self.named.values_mut().chain(self.jumps.iter_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn at(line_idx: usize) -> Location {
+        Location {
+            line_idx,
+            grapheme_idx: 0,
+        }
+    }
+
+    #[test]
+    fn marks_follow_line_changes_and_jumps_navigate() {
+        let mut marks = Marks::default();
+        marks.set('a', at(5));
+        marks.set('b', at(2));
+        marks.lines_inserted(3, 2);
+        assert_eq!(marks.get('a'), Some(at(7)));
+        assert_eq!(marks.get('b'), Some(at(2)));
+        marks.lines_removed(1..3);
+        assert_eq!(marks.get('a'), Some(at(5)));
+        assert_eq!(marks.get('b'), None);
+        assert_eq!(marks.on_line(5), Some('a'));
+
+        marks.push_jump(at(1));
+        marks.push_jump(at(10));
+        assert_eq!(marks.jump_back(at(20)), Some(at(10)));
+        assert_eq!(marks.jump_back(at(10)), Some(at(1)));
+        assert_eq!(marks.jump_back(at(1)), None);
+        assert_eq!(marks.jump_forward(), Some(at(10)));
+        assert_eq!(marks.jump_forward(), Some(at(20)));
+        assert_eq!(marks.jump_forward(), None);
+
+        marks.clamp_to_lines(4);
+        assert_eq!(marks.get('a'), Some(at(3)));
+        assert_eq!(marks.jump_back(at(0)), Some(at(3)));
+
+        // Enter at the start of a marked line opens a line above it.
+        marks.line_split(3, 0);
+        assert_eq!(marks.get('a'), Some(at(4)));
+        let mid_line = Location {
+            line_idx: 4,
+            grapheme_idx: 6,
+        };
+        marks.set('c', mid_line);
+        marks.line_split(4, 2);
+        assert_eq!(marks.get('a'), Some(at(4)));
+        assert_eq!(
+            marks.get('c'),
+            Some(Location {
+                line_idx: 5,
+                grapheme_idx: 4,
+            })
+        );
+    }
+}
@@ -1,28 +1,51 @@
 use super::super::{
-    DocumentStatus, Line, NAME, Position, Size, VERSION,
+    AnnotatedString, AnnotationType, DocumentStatus, Line, NAME, Position, Size, VERSION,
     command::{Edit, Move, MoveDirection},
-    highlight::{HighlightAnnotation, HighlightState, HighlighterRegistry},
+    highlight::{HighlightState, Highlighter, HighlighterRegistry, LineEdit},
+    line::{set_indent_guides, set_show_whitespace, set_tab_width},
+    session::{FileState, SelectionState},
     terminal::Terminal,
 };
 use super::UIComponent;
 use arboard::Clipboard;
 use std::cmp::min;
-use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
 mod buffer;
 use buffer::Buffer;
 use std::io::Error;
+use std::path::Path;
+use std::sync::Arc;
 mod fileinfo;
 use fileinfo::FileInfo;
+mod folds;
+use folds::Folds;
+mod highlight_cache;
+use highlight_cache::HighlightCache;
+mod highlight_worker;
+use highlight_worker::HighlightWorker;
 mod searchinfo;
 use searchinfo::SearchInfo;
 mod location;
 use location::Location;
+mod marks;
+use marks::Marks;
+mod modal;
+use modal::Register;
 mod search_direction;
 use search_direction::SearchDirection;
 mod selection;
 use selection::Selection;
 
-type HighlightCache = HashMap<usize, (Vec<HighlightAnnotation>, HighlightState, u64)>;
+/// Columns taken by the mark indicator left of the text, shown while the
+/// buffer has any named marks.
+const GUTTER_WIDTH: usize = 2;
+/// Files up to this many bytes are highlighted unless the config says otherwise.
+const DEFAULT_MAX_HIGHLIGHT_SIZE: usize = 4 * 1024 * 1024;
+/// The most uncached lines that are highlighted while drawing. Longer
+/// stretches are left to the highlight worker.
+const MAX_BLOCKING_HIGHLIGHT_LINES: usize = 1000;
+/// Lines past the viewport that the worker highlights ahead of scrolling.
+const HIGHLIGHT_LOOKAHEAD: usize = 200;
 
 #[derive(Default)]
 pub struct View {
@@ -34,20 +57,39 @@ pub struct View {
     search_info: Option<SearchInfo>,
     highlighter_registry: HighlighterRegistry,
     highlight_cache: HighlightCache,
+    highlight_worker: HighlightWorker,
+    /// Bumped on every edit, for highlighters that parse the whole buffer.
     cache_version: u64,
+    /// The lines edited since the highlighter last parsed the buffer.
+    pending_edit: Option<LineEdit>,
+    /// Files larger than this many bytes are not highlighted. `None` is
+    /// `DEFAULT_MAX_HIGHLIGHT_SIZE`, 0 is no limit.
+    max_highlight_size: Option<usize>,
+    highlighting_disabled: bool,
     selection: Option<Selection>,
+    visual_anchor: Option<Location>,
+    register: Register,
+    marks: Marks,
+    folds: Folds,
+    /// The registered name of the buffer's language.
+    language: Option<String>,
+    /// Set by the `language` command, which detection then leaves alone.
+    language_set_manually: bool,
 }
 
 impl View {
+    fn highlighter(&self) -> Option<&dyn Highlighter> {
+        self.highlighter_registry
+            .get_highlighter(self.language.as_deref())
+    }
+
+    /// Changes whenever the buffer is edited or replaced.
+    pub const fn edit_version(&self) -> u64 {
+        self.cache_version
+    }
+
     pub fn get_status(&self) -> DocumentStatus {
-        let language_name = self
-            .buffer
-            .file_info
-            .get_path()
-            .and_then(|p| p.extension())
-            .and_then(|ext| ext.to_str())
-            .and_then(|ext| self.highlighter_registry.get_highlighter(Some(ext)))
-            .map(|h| h.language_name().to_string());
+        let language_name = self.highlighter().map(|h| h.language_name().to_string());
 
         DocumentStatus {
             total_lines: self.buffer.height(),
@@ -55,66 +97,52 @@ impl View {
             file_name: format!("{}", self.buffer.file_info),
             is_modified: self.buffer.modified,
             language_name,
+            mode: None,
+            is_recording: false,
         }
     }
 
-    fn render_welcome_screen(&self, origin_y: usize) -> Result<(), Error> {
+    fn render_welcome_screen(&self, origin_y: usize) {
         let Size { height, .. } = self.size;
         let vertical_center = height / 3;
 
         for row in 0..height {
             let draw_row = origin_y + row;
             if row == vertical_center {
-                Self::draw_welcome_message(draw_row)?;
+                Self::draw_welcome_message(draw_row);
             } else {
-                Self::draw_empty_row(draw_row)?;
+                Self::draw_empty_row(draw_row);
             }
         }
-        Ok(())
     }
 
-    fn render_buffer(&mut self, origin_y: usize) -> Result<(), Error> {
-        let Size { height, width } = self.size;
-        let top = self.scroll_offset.row;
+    #[allow(clippy::too_many_lines)]
+    fn render_buffer(&mut self, origin_y: usize) {
+        let height = self.size.height;
+        let width = self.text_width();
+        let gutter_width = self.gutter_width();
+        let top = self.folds.visible_line(self.scroll_offset.row);
 
         let highlighter = self
-            .buffer
-            .file_info
-            .get_path()
-            .and_then(|p| p.extension())
-            .and_then(|ext| ext.to_str())
-            .and_then(|ext| self.highlighter_registry.get_highlighter(Some(ext)));
-
-        let mut state = HighlightState::default();
-        if let Some(hl) = highlighter {
-            for line_idx in 0..top {
-                if let Some(line) = self.buffer.lines.get(line_idx) {
-                    if let Some((_, cached_state, cached_version)) =
-                        self.highlight_cache.get(&line_idx)
-                    {
-                        if *cached_version == self.cache_version {
-                            state = *cached_state;
-                        } else {
-                            let (annotations, new_state) = hl.highlight_line(line, line_idx, state);
-                            self.highlight_cache
-                                .insert(line_idx, (annotations, new_state, self.cache_version));
-                            state = new_state;
-                        }
-                    } else {
-                        let (annotations, new_state) = hl.highlight_line(line, line_idx, state);
-                        self.highlight_cache
-                            .insert(line_idx, (annotations, new_state, self.cache_version));
-                        state = new_state;
-                    }
-                }
-            }
+            .highlighter_registry
+            .get_shared_highlighter(self.language.as_deref())
+            .filter(|_| !self.highlighting_disabled)
+            .cloned();
+
+        // `None` while the worker has not yet highlighted up to `line_idx`.
+        let mut state = None;
+        if let Some(hl) = &highlighter {
+            self.update_document(hl.as_ref());
+            state = self.highlight_state_after(hl, 0..top, HighlightState::default());
         }
 
+        let mut line_idx = top;
         for screen_row in 0..height {
-            let line_idx = top + screen_row;
             let draw_row = origin_y + screen_row;
+            let hidden_lines = self.hidden_after(line_idx);
 
             if let Some(line) = self.buffer.lines.get(line_idx) {
+                let mark = self.marks.on_line(line_idx);
                 let selection_range = self
                     .selection
                     .and_then(|sel| Self::selection_byte_range_for_line(sel, line, line_idx));
@@ -128,90 +156,192 @@ impl View {
                 let selected_match = (self.text_location.line_idx == line_idx && query.is_some())
                     .then_some(self.text_location.grapheme_idx);
 
-                if let Some((cached_annotations, cached_state, cached_version)) =
-                    self.highlight_cache.get(&line_idx)
-                {
-                    if *cached_version == self.cache_version {
-                        let (annotated_string, new_state) = line.get_annotated_visible_substr(
-                            left..right,
-                            query,
-                            selected_match,
-                            highlighter,
-                            *cached_state,
-                            Some(cached_annotations),
-                            selection_range,
-                        );
-                        state = new_state;
-                        Terminal::print_annotated_row(screen_row, &annotated_string)?;
-                    } else if let Some(hl) = highlighter {
-                        let (annotations, new_state) = hl.highlight_line(line, line_idx, state);
-                        self.highlight_cache.insert(
-                            line_idx,
-                            (annotations.clone(), new_state, self.cache_version),
-                        );
-                        let (annotated_string, final_state) = line.get_annotated_visible_substr(
-                            left..right,
-                            query,
-                            selected_match,
-                            highlighter,
-                            new_state,
-                            Some(&annotations),
-                            selection_range,
-                        );
-                        state = final_state;
-                        Terminal::print_annotated_row(screen_row, &annotated_string)?;
-                    } else {
-                        let (annotated_string, new_state) = line.get_annotated_visible_substr(
-                            left..right,
-                            query,
-                            selected_match,
-                            None,
-                            state,
-                            None,
-                            selection_range,
-                        );
-                        state = new_state;
-                        Terminal::print_annotated_row(screen_row, &annotated_string)?;
+                let (annotations, end_state) = match (&highlighter, state) {
+                    (Some(hl), Some(state)) => {
+                        let (annotations, end_state) =
+                            self.highlight_cache
+                                .highlight(hl.as_ref(), line, line_idx, state);
+                        (Some(annotations), Some(end_state))
                     }
-                } else if let Some(hl) = highlighter {
-                    let (annotations, new_state) = hl.highlight_line(line, line_idx, state);
-                    self.highlight_cache.insert(
-                        line_idx,
-                        (annotations.clone(), new_state, self.cache_version),
-                    );
-                    let (annotated_string, final_state) = line.get_annotated_visible_substr(
-                        left..right,
-                        query,
-                        selected_match,
-                        highlighter,
-                        new_state,
-                        Some(&annotations),
-                        selection_range,
-                    );
-                    state = final_state;
-                    Terminal::print_annotated_row(screen_row, &annotated_string)?;
-                } else {
-                    let (annotated_string, new_state) = line.get_annotated_visible_substr(
-                        left..right,
-                        query,
-                        selected_match,
-                        None,
-                        state,
-                        None,
-                        selection_range,
-                    );
-                    state = new_state;
-                    Terminal::print_annotated_row(screen_row, &annotated_string)?;
-                }
+                    _ => (None, None),
+                };
+                let (annotated_string, _) = line.get_annotated_visible_substr(
+                    left..right,
+                    query,
+                    selected_match,
+                    None,
+                    end_state.unwrap_or_default(),
+                    annotations,
+                    selection_range,
+                );
+                state = end_state;
+                Self::print_buffer_row(
+                    screen_row,
+                    gutter_width,
+                    mark,
+                    hidden_lines,
+                    annotated_string,
+                );
             } else {
-                Self::render_line(draw_row, "~")?;
-                state = HighlightState::default();
+                Self::render_line(draw_row, "~");
             }
+
+            if hidden_lines > 0
+                && let Some(hl) = &highlighter
+                && let Some(start_state) = state
+            {
+                state = self.highlight_state_after(
+                    hl,
+                    line_idx + 1..line_idx + 1 + hidden_lines,
+                    start_state,
+                );
+            }
+            line_idx += hidden_lines + 1;
+        }
+
+        // Highlight the next few pages in the background, so that scrolling
+        // down finds them ready.
+        if let Some(hl) = highlighter
+            && let Some(state) = state
+            && let Err((from, state)) = self.highlight_cache.cached_state(
+                hl.as_ref(),
+                &self.buffer.lines,
+                line_idx..line_idx + HIGHLIGHT_LOOKAHEAD,
+                state,
+            )
+        {
+            self.highlight_worker.request(
+                hl,
+                &self.buffer.lines,
+                from..line_idx + HIGHLIGHT_LOOKAHEAD,
+                state,
+                self.cache_version,
+            );
         }
-        Ok(())
     }
 
-    fn draw_welcome_message(at: usize) -> Result<(), Error> {
+    /// Lets a highlighter that parses the whole buffer catch up with it, and
+    /// has the cache check again the lines whose annotations it changed.
+    fn update_document(&mut self, hl: &dyn Highlighter) {
+        let edit = self.pending_edit.take();
+        if let Some(rows) =
+            hl.update_document(&self.buffer.lines, edit.as_ref(), self.cache_version)
+        {
+            self.highlight_cache.lines_changed(rows);
+        }
+    }
+
+    /// The state at the end of `range`, highlighting the lines in it that
+    /// are not cached yet. A long stretch of them, as after a jump to the
+    /// end of a large file, is left to the worker, and `None` is returned
+    /// until it gets there.
+    fn highlight_state_after(
+        &mut self,
+        hl: &Arc<dyn Highlighter>,
+        range: Range<usize>,
+        state: HighlightState,
+    ) -> Option<HighlightState> {
+        let lines = &self.buffer.lines;
+        match self
+            .highlight_cache
+            .cached_state(hl.as_ref(), lines, range.clone(), state)
+        {
+            Ok(state) => Some(state),
+            Err((from, mut state)) if range.end - from <= MAX_BLOCKING_HIGHLIGHT_LINES => {
+                for (line_idx, line) in lines.iter().enumerate().take(range.end).skip(from) {
+                    state = self
+                        .highlight_cache
+                        .highlight(hl.as_ref(), line, line_idx, state)
+                        .1;
+                }
+                Some(state)
+            }
+            Err((from, state)) => {
+                self.highlight_worker.request(
+                    Arc::clone(hl),
+                    lines,
+                    from..range.end + HIGHLIGHT_LOOKAHEAD,
+                    state,
+                    self.cache_version,
+                );
+                None
+            }
+        }
+    }
+
+    /// Moves lines the worker has highlighted into the cache. Lines above
+    /// the viewport only matter once the one just above it is done, so the
+    /// view is redrawn from then on.
+    pub fn receive_highlights(&mut self) {
+        let top = self.folds.visible_line(self.scroll_offset.row);
+        if self
+            .highlight_worker
+            .receive(&mut self.highlight_cache)
+            .is_some_and(|line_idx| line_idx + 1 >= top)
+        {
+            self.mark_redraw(true);
+        }
+    }
+
+    pub fn is_highlighting(&self) -> bool {
+        self.highlight_worker.is_busy()
+    }
+
+    /// Sets the size in bytes above which files are not highlighted, 0 for
+    /// no limit.
+    pub fn set_max_highlight_size(&mut self, max_size: usize) {
+        self.max_highlight_size = Some(max_size);
+        self.update_highlighting_disabled();
+    }
+
+    fn update_highlighting_disabled(&mut self) {
+        let max_size = self
+            .max_highlight_size
+            .unwrap_or(DEFAULT_MAX_HIGHLIGHT_SIZE);
+        let size: usize = self.buffer.lines.iter().map(|line| line.len() + 1).sum();
+        self.highlighting_disabled = max_size > 0 && size > max_size;
+        if self.highlighting_disabled {
+            self.highlight_worker.cancel();
+        }
+        self.mark_redraw(true);
+    }
+
+    fn print_buffer_row(
+        row: usize,
+        gutter_width: usize,
+        mark: Option<char>,
+        hidden_lines: usize,
+        mut annotated_string: AnnotatedString,
+    ) {
+        if hidden_lines > 0 {
+            annotated_string.push_annotated(
+                &format!("  [{hidden_lines} lines folded]"),
+                AnnotationType::Fold,
+            );
+        }
+        if gutter_width > 0 {
+            let gutter = mark.map_or_else(String::new, String::from);
+            annotated_string.replace(0, 0, &format!("{gutter:gutter_width$}"));
+            annotated_string.add_annotation(AnnotationType::Gutter, 0, gutter_width);
+            annotated_string.add_annotation(AnnotationType::Mark, 0, gutter.len());
+        }
+        Terminal::print_annotated_row(row, &annotated_string);
+    }
+
+    fn gutter_width(&self) -> usize {
+        if self.marks.has_named() {
+            GUTTER_WIDTH
+        } else {
+            0
+        }
+    }
+
+    /// The number of columns available for text, right of the gutter.
+    fn text_width(&self) -> usize {
+        self.size.width.saturating_sub(self.gutter_width())
+    }
+
+    fn draw_welcome_message(at: usize) {
         let mut welcome_message = format!("{NAME} -- version {VERSION}");
         let width = Terminal::size().unwrap().width;
         let len = welcome_message.len();
@@ -219,25 +349,26 @@ impl View {
         let spaces = " ".repeat(padding - 1);
         welcome_message = format!("~{spaces}{welcome_message}");
         welcome_message.truncate(width);
-        Self::render_line(at, &welcome_message)?;
-        Ok(())
+        Self::render_line(at, &welcome_message);
     }
 
-    fn draw_empty_row(at: usize) -> Result<(), Error> {
-        Self::render_line(at, "~")?;
-        Ok(())
+    fn draw_empty_row(at: usize) {
+        Self::render_line(at, "~");
     }
 
-    fn render_line(at: usize, line_text: &str) -> Result<(), Error> {
-        Terminal::print_row(at, line_text)
+    fn render_line(at: usize, line_text: &str) {
+        Terminal::print_row(at, line_text);
     }
 
     pub fn caret_position(&self) -> Position {
-        let Position { row, col } = self
-            .text_location_to_position()
-            .saturating_sub(self.scroll_offset);
+        let position = self.text_location_to_position();
+        let Position { col, .. } = position.saturating_sub(self.scroll_offset);
+        let top = self.folds.visible_line(self.scroll_offset.row);
 
-        Position { row, col }
+        Position {
+            col: col.saturating_add(self.gutter_width()),
+            row: self.visible_rows_between(top, position.row),
+        }
     }
 
     fn text_location_to_position(&self) -> Position {
@@ -255,6 +386,13 @@ impl View {
     pub fn handle_move_command(&mut self, move_cmd: Move) {
         let Size { height, .. } = self.size;
 
+        if matches!(
+            move_cmd.direction,
+            MoveDirection::PageUp | MoveDirection::PageDown
+        ) {
+            self.record_jump();
+        }
+
         if move_cmd.is_selection {
             if self.selection.is_none() {
                 self.start_selection();
@@ -282,12 +420,17 @@ impl View {
     }
 
     fn move_up(&mut self, step: usize) {
-        self.text_location.line_idx = self.text_location.line_idx.saturating_sub(step);
+        self.text_location.line_idx =
+            self.step_visible_lines(self.text_location.line_idx, step, false);
         self.snap_to_valid_grapheme();
     }
 
     fn move_down(&mut self, step: usize) {
-        self.text_location.line_idx = self.text_location.line_idx.saturating_add(step);
+        self.text_location.line_idx = if self.text_location.line_idx < self.buffer.height() {
+            self.step_visible_lines(self.text_location.line_idx, step, true)
+        } else {
+            self.text_location.line_idx.saturating_add(step)
+        };
         self.snap_to_valid_grapheme();
         self.snap_to_valid_line();
     }
@@ -343,11 +486,12 @@ impl View {
 
     fn scroll_vertically(&mut self, to: usize) {
         let Size { height, .. } = self.size;
-        let offset_changed = if to < self.scroll_offset.row {
+        let top = self.folds.visible_line(self.scroll_offset.row);
+        let offset_changed = if to < top {
             self.scroll_offset.row = to;
             true
-        } else if to >= self.scroll_offset.row.saturating_add(height) {
-            self.scroll_offset.row = to.saturating_sub(height).saturating_add(1);
+        } else if self.visible_rows_between(top, to) >= height {
+            self.scroll_offset.row = self.step_visible_lines(to, height.saturating_sub(1), false);
             true
         } else {
             false
@@ -359,7 +503,7 @@ impl View {
     }
 
     fn scroll_horizontally(&mut self, to: usize) {
-        let Size { width, .. } = self.size;
+        let width = self.text_width();
         let offset_changed = if to < self.scroll_offset.col {
             self.scroll_offset.col = to;
             true
@@ -376,6 +520,7 @@ impl View {
     }
 
     fn scroll_text_location_into_view(&mut self) {
+        self.reveal_text_location();
         let Position { row, col } = self.text_location_to_position();
         self.scroll_vertically(row);
         self.scroll_horizontally(col);
@@ -450,12 +595,15 @@ impl View {
         }
 
         ranges.sort_by(|(a_idx, _), (b_idx, _)| b_idx.cmp(a_idx));
+        let changed = ranges.last().map_or(0, |(line_idx, _)| *line_idx)
+            ..ranges.first().map_or(0, |(line_idx, _)| line_idx + 1);
 
         for (line_idx, byte_range) in ranges {
             if let Some(line) = self.buffer.lines.get_mut(line_idx) {
                 line.delete_byte_range(byte_range);
             }
         }
+        self.lines_changed(changed);
 
         self.text_location = normalized.start;
         self.selection = None;
@@ -505,7 +653,7 @@ impl View {
     }
 
     /// Inserts the given text at the current cursor (or replaces selection).
-    /// Used by both Ctrl+V paste and bracketed paste (Event::Paste).
+    /// Used by both Ctrl+V paste and bracketed paste (`Event::Paste`).
     pub fn paste_text(&mut self, text: &str) {
         if text.is_empty() {
             return;
@@ -525,6 +673,270 @@ impl View {
         }
     }
 
+    pub fn has_selection(&self) -> bool {
+        self.selection
+            .is_some_and(|selection| !selection.is_empty())
+    }
+
+    /// Returns the selected text, or the whole buffer (newline-terminated, as it
+    /// would be written to disk) when nothing is selected.
+    pub fn selection_or_buffer_text(&self) -> String {
+        if let Some(selection) = self.selection.filter(|selection| !selection.is_empty()) {
+            return self.selection_to_string(&selection).unwrap_or_default();
+        }
+        self.buffer_text()
+    }
+
+    pub fn buffer_text(&self) -> String {
+        let mut text = String::new();
+        for line in &self.buffer.lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Replaces the selection, or the whole buffer when nothing is selected,
+    /// with `text`.
+    pub fn replace_selection_or_buffer(&mut self, text: &str) {
+        if self.has_selection() {
+            self.paste_text(text);
+        } else {
+            self.replace_buffer(text);
+        }
+    }
+
+    /// Replaces the whole buffer with `text`, keeping the caret on the same line.
+    pub fn replace_buffer(&mut self, text: &str) {
+        let previous_location = self.text_location;
+        self.buffer.lines = text
+            .replace("\r\n", "\n")
+            .split('\n')
+            .map(Line::from)
+            .collect();
+        self.buffer.modified = true;
+        self.selection = None;
+        self.folds.clear();
+        self.marks.clamp_to_lines(self.buffer.height());
+        self.text_location = previous_location;
+        self.snap_to_valid_line();
+        self.snap_to_valid_grapheme();
+        self.highlight_cache.clear();
+        self.highlight_worker.cancel();
+        self.pending_edit = None;
+        self.cache_version += 1;
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+    }
+
+    /// Replaces `pattern` with `replacement` on the given lines, either the
+    /// first occurrence per line or every one. Returns the number of lines changed.
+    pub fn substitute(
+        &mut self,
+        lines: RangeInclusive<usize>,
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+    ) -> usize {
+        let mut changed_lines = 0;
+        for line in self
+            .buffer
+            .lines
+            .iter_mut()
+            .take(lines.end() + 1)
+            .skip(*lines.start())
+        {
+            let text = line.to_string();
+            if !text.contains(pattern) {
+                continue;
+            }
+            let replaced = if global {
+                text.replace(pattern, replacement)
+            } else {
+                text.replacen(pattern, replacement, 1)
+            };
+            *line = Line::from(&replaced);
+            changed_lines += 1;
+        }
+        if changed_lines > 0 {
+            self.lines_changed(*lines.start()..min(lines.end() + 1, self.buffer.height()));
+            self.buffer.modified = true;
+            self.selection = None;
+            self.snap_to_valid_grapheme();
+            self.cache_version += 1;
+            self.mark_redraw(true);
+        }
+        changed_lines
+    }
+
+    /// Moves the caret to the start of the given 0-based line.
+    pub fn goto_line(&mut self, line_idx: usize) {
+        self.record_jump();
+        self.clear_selection();
+        self.text_location = self.clamp_location(line_idx, 0);
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+    }
+
+    pub fn set_tab_width(&mut self, width: usize) {
+        set_tab_width(width);
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+    }
+
+    pub fn set_show_whitespace(&mut self, enabled: bool) {
+        set_show_whitespace(enabled);
+        self.mark_redraw(true);
+    }
+
+    pub fn set_indent_guides(&mut self, enabled: bool) {
+        set_indent_guides(enabled);
+        self.mark_redraw(true);
+    }
+
+    /// Sets mark `name` at the caret. Returns false for names other than letters.
+    pub fn set_mark(&mut self, name: char) -> bool {
+        if !Marks::is_valid_name(name) {
+            return false;
+        }
+        self.marks.set(name, self.text_location);
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+        true
+    }
+
+    pub fn delete_mark(&mut self, name: char) -> bool {
+        let removed = self.marks.remove(name);
+        if removed {
+            self.scroll_text_location_into_view();
+            self.mark_redraw(true);
+        }
+        removed
+    }
+
+    /// Moves the caret to mark `name`, recording the jump. Returns false if
+    /// the mark isn't set.
+    pub fn jump_to_mark(&mut self, name: char) -> bool {
+        let Some(location) = self.marks.get(name) else {
+            return false;
+        };
+        self.record_jump();
+        self.move_to(location);
+        true
+    }
+
+    /// Returns to the location before the last large move, if any.
+    pub fn jump_back(&mut self) -> bool {
+        let Some(location) = self.marks.jump_back(self.text_location) else {
+            return false;
+        };
+        self.move_to(location);
+        true
+    }
+
+    pub fn jump_forward(&mut self) -> bool {
+        let Some(location) = self.marks.jump_forward() else {
+            return false;
+        };
+        self.move_to(location);
+        true
+    }
+
+    /// Keeps marks and folds on their text after `count` lines were inserted
+    /// before line `at`.
+    fn lines_inserted(&mut self, at: usize, count: usize) {
+        self.record_edit(at, at, at + count);
+        self.marks.lines_inserted(at, count);
+        self.folds.lines_inserted(at, count);
+        self.highlight_cache.lines_inserted(at, count);
+        self.highlight_worker.cancel();
+    }
+
+    fn lines_removed(&mut self, removed: Range<usize>) {
+        self.record_edit(removed.start, removed.end, removed.start);
+        self.marks.lines_removed(removed.clone());
+        self.folds.lines_removed(removed.clone());
+        self.highlight_cache.lines_removed(removed);
+        self.highlight_worker.cancel();
+    }
+
+    /// Lines `rows` were edited without adding or removing lines.
+    fn lines_changed(&mut self, rows: Range<usize>) {
+        self.record_edit(rows.start, rows.end, rows.end);
+        self.highlight_cache.lines_changed(rows);
+    }
+
+    /// Adds lines `start..old_end` becoming `start..new_end` to the edit the
+    /// highlighter gets next, so that it parses only those lines again.
+    fn record_edit(&mut self, start: usize, old_end: usize, new_end: usize) {
+        self.pending_edit = Some(match self.pending_edit {
+            Some(edit) => edit.then(start, old_end, new_end),
+            None => LineEdit {
+                since: self.cache_version,
+                start,
+                old_end,
+                new_end,
+            },
+        });
+    }
+
+    /// Line `line_idx + 1` was appended to line `line_idx`, which was
+    /// `joined_at` graphemes long.
+    fn lines_joined(&mut self, line_idx: usize, joined_at: usize) {
+        self.lines_changed(line_idx..line_idx.saturating_add(1));
+        self.marks.lines_joined(line_idx, joined_at);
+        let next_line_idx = line_idx.saturating_add(1);
+        self.record_edit(next_line_idx, next_line_idx + 1, next_line_idx);
+        self.folds.lines_removed(next_line_idx..next_line_idx + 1);
+        self.highlight_cache
+            .lines_removed(next_line_idx..next_line_idx + 1);
+        self.highlight_worker.cancel();
+    }
+
+    /// Line `line_idx` was split before grapheme `at_grapheme`. Split at its
+    /// start, the line's text moves down and its fold with it. Split at its
+    /// end, as by `o`, nothing moves.
+    fn line_split(&mut self, line_idx: usize, at_grapheme: usize) {
+        if self
+            .buffer
+            .lines
+            .get(line_idx.saturating_add(1))
+            .is_none_or(|line| line.grapheme_count() == 0)
+        {
+            self.lines_inserted(line_idx.saturating_add(1), 1);
+            return;
+        }
+        self.marks.line_split(line_idx, at_grapheme);
+        let inserted_at = if at_grapheme == 0 {
+            line_idx
+        } else {
+            self.lines_changed(line_idx..line_idx.saturating_add(1));
+            line_idx.saturating_add(1)
+        };
+        self.record_edit(inserted_at, inserted_at, inserted_at + 1);
+        self.folds.lines_inserted(inserted_at, 1);
+        self.highlight_cache.lines_inserted(inserted_at, 1);
+        self.highlight_worker.cancel();
+    }
+
+    /// Remembers the caret location in the jump list, before a large move.
+    fn record_jump(&mut self) {
+        self.marks.push_jump(self.text_location);
+    }
+
+    fn move_to(&mut self, location: Location) {
+        self.clear_selection();
+        self.text_location = self.clamp_location(location.line_idx, location.grapheme_idx);
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+    }
+
+    /// Inserts `text` at the caret, leaving any selected text in place.
+    pub fn insert_text(&mut self, text: &str) {
+        self.clear_selection();
+        self.paste_text(text);
+    }
+
     fn paste_clipboard(&mut self) {
         let Ok(mut clipboard) = Clipboard::new() else {
             return;
@@ -543,6 +955,7 @@ impl View {
             Edit::InsertNewline => self.insert_newline(),
             Edit::Backspace => self.backspace(),
             Edit::Delete => self.delete(),
+            Edit::DeleteWord => self.delete_word(),
             Edit::Copy => self.copy_selection(),
             Edit::Cut => self.cut_selection(),
             Edit::Paste => self.paste_clipboard(),
@@ -571,12 +984,19 @@ impl View {
     fn insert_char(&mut self, character: char) {
         let _ = self.delete_selection();
 
+        let line_idx = self.text_location.line_idx;
         let old_len = self
             .buffer
             .lines
-            .get(self.text_location.line_idx)
+            .get(line_idx)
             .map_or(0, Line::grapheme_count);
+        let height = self.buffer.height();
         self.buffer.insert_char(character, self.text_location);
+        if self.buffer.height() > height {
+            self.lines_inserted(line_idx, 1);
+        } else {
+            self.lines_changed(line_idx..line_idx.saturating_add(1));
+        }
         let new_len = self
             .buffer
             .lines
@@ -594,8 +1014,20 @@ impl View {
     fn insert_newline(&mut self) {
         let _ = self.delete_selection();
 
+        let height = self.buffer.height();
         self.buffer.insert_newline(self.text_location);
-        self.handle_move_command(Move::right(false));
+        if self.text_location.line_idx == height {
+            self.lines_inserted(height, 1);
+        } else {
+            self.line_split(self.text_location.line_idx, self.text_location.grapheme_idx);
+        }
+        // Straight onto the new line, even when it opens a closed fold.
+        self.text_location = Location {
+            line_idx: self.text_location.line_idx.saturating_add(1),
+            grapheme_idx: 0,
+        };
+        self.snap_to_valid_line();
+        self.scroll_text_location_into_view();
         self.cache_version += 1;
         self.mark_redraw(true);
     }
@@ -603,12 +1035,108 @@ impl View {
     pub fn load(&mut self, file_name: &str) -> Result<(), Error> {
         let buffer = Buffer::load(file_name)?;
         self.buffer = buffer;
+        self.text_location = Location::default();
+        self.scroll_offset = Position::default();
+        self.selection = None;
+        self.search_info = None;
+        self.marks.clear();
+        self.folds.clear();
         self.highlight_cache.clear();
+        self.highlight_worker.cancel();
+        self.pending_edit = None;
         self.cache_version += 1;
-        self.mark_redraw(true);
+        self.language_set_manually = false;
+        self.detect_language();
+        self.update_highlighting_disabled();
         Ok(())
     }
 
+    /// Re-reads the current file from disk, keeping the caret and scroll position.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        let Some(file_name) = self
+            .buffer
+            .file_info
+            .get_path()
+            .and_then(|path| path.to_str())
+            .map(str::to_string)
+        else {
+            return Ok(());
+        };
+        self.buffer = Buffer::load(&file_name)?;
+        self.selection = None;
+        self.folds.clear();
+        self.marks.clamp_to_lines(self.buffer.height());
+        self.snap_to_valid_line();
+        self.snap_to_valid_grapheme();
+        self.highlight_cache.clear();
+        self.highlight_worker.cancel();
+        self.pending_edit = None;
+        self.cache_version += 1;
+        self.detect_language();
+        self.update_highlighting_disabled();
+        self.scroll_text_location_into_view();
+        Ok(())
+    }
+
+    pub fn file_state(&self) -> FileState {
+        FileState {
+            line_idx: self.text_location.line_idx,
+            grapheme_idx: self.text_location.grapheme_idx,
+            scroll_row: self.scroll_offset.row,
+            scroll_col: self.scroll_offset.col,
+            selection: self.selection.map(|selection| SelectionState {
+                start_line: selection.start.line_idx,
+                start_grapheme: selection.start.grapheme_idx,
+                end_line: selection.end.line_idx,
+                end_grapheme: selection.end.grapheme_idx,
+            }),
+        }
+    }
+
+    /// Restores a saved caret, scroll offset and selection, clamped to the
+    /// current buffer in case the file changed since the state was saved.
+    pub fn restore_file_state(&mut self, state: FileState) {
+        self.text_location = self.clamp_location(state.line_idx, state.grapheme_idx);
+        self.scroll_offset = Position {
+            row: state.scroll_row,
+            col: state.scroll_col,
+        };
+        self.selection = state.selection.map(|selection| {
+            Selection::new(
+                self.clamp_location(selection.start_line, selection.start_grapheme),
+                self.clamp_location(selection.end_line, selection.end_grapheme),
+            )
+        });
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+    }
+
+    fn clamp_location(&self, line_idx: usize, grapheme_idx: usize) -> Location {
+        let line_idx = min(line_idx, self.buffer.height().saturating_sub(1));
+        let grapheme_idx = self
+            .buffer
+            .lines
+            .get(line_idx)
+            .map_or(0, |line| min(line.grapheme_count(), grapheme_idx));
+        Location {
+            grapheme_idx,
+            line_idx,
+        }
+    }
+
+    pub fn is_changed_on_disk(&self) -> bool {
+        self.buffer.file_info.changed_on_disk()
+    }
+
+    /// Accepts the current on-disk state so the same change isn't reported again.
+    pub fn acknowledge_disk_change(&mut self) {
+        self.buffer.file_info.refresh_disk_state();
+    }
+
+    pub fn file_path(&self) -> Option<&Path> {
+        self.buffer.file_info.get_path()
+    }
+
     fn backspace(&mut self) {
         if self.delete_selection() {
             return;
@@ -620,18 +1148,68 @@ impl View {
         }
     }
 
+    fn delete_word(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        if self.text_location.grapheme_idx == 0 {
+            self.backspace();
+            return;
+        }
+        let Some(line) = self.buffer.lines.get_mut(self.text_location.line_idx) else {
+            return;
+        };
+        let word_start = line.previous_word_start(self.text_location.grapheme_idx);
+        line.delete_grapheme_range(word_start..self.text_location.grapheme_idx);
+        self.lines_changed(self.text_location.line_idx..self.text_location.line_idx + 1);
+        self.buffer.modified = true;
+        self.text_location.grapheme_idx = word_start;
+        self.scroll_text_location_into_view();
+        self.cache_version += 1;
+        self.mark_redraw(true);
+    }
+
     fn delete(&mut self) {
         if self.delete_selection() {
             return;
         }
 
+        let height = self.buffer.height();
+        let line_idx = self.text_location.line_idx;
+        let joined_at = self
+            .buffer
+            .lines
+            .get(line_idx)
+            .map_or(0, Line::grapheme_count);
         self.buffer.delete(self.text_location);
+        if self.buffer.height() < height {
+            self.lines_joined(line_idx, joined_at);
+        } else {
+            self.lines_changed(line_idx..line_idx.saturating_add(1));
+        }
         self.cache_version += 1;
         self.mark_redraw(true);
     }
 
+    /// Returns the formatter configured for the language of `file_name`,
+    /// or of the current file when no name is given.
+    pub fn formatter_command(&self, file_name: Option<&str>) -> Option<String> {
+        let language = match file_name {
+            Some(name) if !self.language_set_manually => self
+                .highlighter_registry
+                .detect_language(Some(Path::new(name)), &self.buffer.lines),
+            _ => self.language.as_deref(),
+        };
+        self.highlighter_registry
+            .get_highlighter(language)
+            .and_then(|highlighter| highlighter.formatter())
+            .map(str::to_string)
+    }
+
     pub fn save(&mut self) -> Result<(), Error> {
-        self.buffer.save()
+        self.buffer.save()?;
+        self.detect_language();
+        Ok(())
     }
 
     pub const fn is_file_loaded(&self) -> bool {
@@ -639,7 +1217,49 @@ impl View {
     }
 
     pub fn save_as(&mut self, file_name: &str) -> Result<(), Error> {
-        self.buffer.save_as(file_name)
+        self.buffer.save_as(file_name)?;
+        self.detect_language();
+        Ok(())
+    }
+
+    /// Picks the language from the modeline, file name or first line, unless
+    /// it was set with `set_language`.
+    fn detect_language(&mut self) {
+        if self.language_set_manually {
+            return;
+        }
+        let language = self
+            .highlighter_registry
+            .detect_language(self.buffer.file_info.get_path(), &self.buffer.lines)
+            .map(str::to_string);
+        self.switch_language(language);
+    }
+
+    /// Sets the buffer's language by name, overriding detection. Returns the
+    /// registered name, or `None` when there is no such language.
+    pub fn set_language(&mut self, name: &str) -> Option<String> {
+        let language = self.highlighter_registry.find_language(name)?.to_string();
+        self.language_set_manually = true;
+        self.switch_language(Some(language.clone()));
+        Some(language)
+    }
+
+    pub fn language_names(&self) -> Vec<&str> {
+        self.highlighter_registry.language_names()
+    }
+
+    fn switch_language(&mut self, language: Option<String>) {
+        if language == self.language {
+            return;
+        }
+        self.language = language;
+        // Cached lines hold the old highlighter's annotations.
+        self.highlight_cache.clear();
+        self.highlight_worker.cancel();
+        self.pending_edit = None;
+        self.folds.clear();
+        self.cache_version += 1;
+        self.mark_redraw(true);
     }
 
     pub fn enter_search(&mut self) {
@@ -651,7 +1271,11 @@ impl View {
     }
 
     pub fn exit_search(&mut self) {
-        self.search_info = None;
+        if let Some(search_info) = self.search_info.take()
+            && search_info.prev_location != self.text_location
+        {
+            self.marks.push_jump(search_info.prev_location);
+        }
         self.mark_redraw(true);
     }
 
@@ -686,21 +1310,62 @@ impl View {
     }
 
     fn search_in_direction(&mut self, from: Location, direction: SearchDirection) {
-        if let Some(location) = self.get_search_query().and_then(|query| {
-            if query.is_empty() {
-                None
-            } else if direction == SearchDirection::Forward {
-                self.buffer.search_forward(query, from)
-            } else {
-                self.buffer.search_backward(query, from)
-            }
-        }) {
+        if let Some(location) = self.find_visible_match(from, direction) {
             self.text_location = location;
             self.center_text_location();
         }
         self.mark_redraw(true);
     }
 
+    /// Finds the next match that isn't hidden in a closed fold, searching on
+    /// past each fold that hides one.
+    fn find_visible_match(&self, from: Location, direction: SearchDirection) -> Option<Location> {
+        let query = self.get_search_query().filter(|query| !query.is_empty())?;
+        let mut from = from;
+        for _ in 0..=self.folds.len() {
+            let location = if direction == SearchDirection::Forward {
+                self.buffer.search_forward(query, from)
+            } else {
+                self.buffer.search_backward(query, from)
+            }?;
+            let Some((start, end)) = self.folds.hiding(location.line_idx) else {
+                return Some(location);
+            };
+            from = if direction == SearchDirection::Forward {
+                Location {
+                    line_idx: end.saturating_add(1),
+                    grapheme_idx: 0,
+                }
+            } else {
+                Location {
+                    line_idx: start,
+                    grapheme_idx: self.buffer.lines.get(start).map_or(0, Line::grapheme_count),
+                }
+            };
+        }
+        None
+    }
+
+    /// Returns the 1-based index of the match under the caret (0 if the caret
+    /// is not on a match) and the total number of matches in the buffer.
+    pub fn search_match_counter(&self) -> Option<(usize, usize)> {
+        let query = self
+            .search_info
+            .as_ref()
+            .and_then(|search_info| search_info.query.as_ref())?;
+        let matches: Vec<Location> = self
+            .buffer
+            .match_locations(query)
+            .into_iter()
+            .filter(|location| !self.folds.is_hidden(location.line_idx))
+            .collect();
+        let current = matches
+            .iter()
+            .position(|location| *location == self.text_location)
+            .map_or(0, |idx| idx.saturating_add(1));
+        Some((current, matches.len()))
+    }
+
     pub fn search_next(&mut self) {
         let step_right = self
             .get_search_query()
@@ -718,11 +1383,13 @@ impl View {
     }
 
     fn center_text_location(&mut self) {
-        let Size { height, width } = self.size;
+        let height = self.size.height;
+        let width = self.text_width();
         let Position { row, col } = self.text_location_to_position();
         let vertical_mid = height.div_ceil(2);
         let horizontal_mid = width.div_ceil(2);
-        self.scroll_offset.row = row.saturating_sub(vertical_mid);
+        self.reveal_text_location();
+        self.scroll_offset.row = self.step_visible_lines(row, vertical_mid, false);
         self.scroll_offset.col = col.saturating_sub(horizontal_mid);
         self.mark_redraw(true);
     }
@@ -743,9 +1410,10 @@ impl UIComponent for View {
 
     fn draw(&mut self, origin_y: usize) -> Result<(), Error> {
         if self.buffer.is_empty() {
-            self.render_welcome_screen(origin_y)
+            self.render_welcome_screen(origin_y);
         } else {
-            self.render_buffer(origin_y)
+            self.render_buffer(origin_y);
         }
+        Ok(())
     }
 }
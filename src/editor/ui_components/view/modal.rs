@@ -0,0 +1,447 @@
+use super::super::super::{
+    Line,
+    line::CharClass,
+    modal::{InsertAt, Motion, Operator},
+};
+use super::super::UIComponent;
+use super::{Location, Selection, View};
+use arboard::Clipboard;
+use std::cmp::min;
+
+/// Text removed or copied by an operator, kept for `p`/`P`.
+#[derive(Clone, Default)]
+pub struct Register {
+    text: String,
+    linewise: bool,
+}
+
+const fn ordered(a: Location, b: Location) -> (Location, Location) {
+    if a.line_idx < b.line_idx || (a.line_idx == b.line_idx && a.grapheme_idx <= b.grapheme_idx) {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+impl View {
+    fn line_len(&self, line_idx: usize) -> usize {
+        self.buffer
+            .lines
+            .get(line_idx)
+            .map_or(0, Line::grapheme_count)
+    }
+
+    fn char_class(&self, location: Location) -> CharClass {
+        self.buffer
+            .lines
+            .get(location.line_idx)
+            .and_then(|line| line.char_class_at(location.grapheme_idx))
+            .unwrap_or(CharClass::Space)
+    }
+
+    fn last_line_idx(&self) -> usize {
+        self.buffer.height().saturating_sub(1)
+    }
+
+    /// `w`: the start of the next word, where an empty line also counts as a word.
+    fn next_word_start(&self, from: Location) -> Location {
+        let mut location = from;
+        let start_class = self.char_class(location);
+        if start_class != CharClass::Space {
+            while location.grapheme_idx < self.line_len(location.line_idx)
+                && self.char_class(location) == start_class
+            {
+                location.grapheme_idx += 1;
+            }
+        }
+        loop {
+            if location.grapheme_idx >= self.line_len(location.line_idx) {
+                if location.line_idx >= self.last_line_idx() {
+                    location.grapheme_idx = self.line_len(location.line_idx);
+                    return location;
+                }
+                location = Location {
+                    line_idx: location.line_idx + 1,
+                    grapheme_idx: 0,
+                };
+                if self.line_len(location.line_idx) == 0 {
+                    return location;
+                }
+            } else if self.char_class(location) == CharClass::Space {
+                location.grapheme_idx += 1;
+            } else {
+                return location;
+            }
+        }
+    }
+
+    /// `b`: the start of the current or previous word.
+    fn previous_word_start(&self, from: Location) -> Location {
+        let mut location = from;
+        loop {
+            if location.grapheme_idx == 0 {
+                if location.line_idx == 0 {
+                    return location;
+                }
+                location.line_idx -= 1;
+                location.grapheme_idx = self.line_len(location.line_idx);
+                if location.grapheme_idx == 0 {
+                    return location;
+                }
+            }
+            location.grapheme_idx -= 1;
+            if self.char_class(location) != CharClass::Space {
+                break;
+            }
+        }
+        let word_class = self.char_class(location);
+        while location.grapheme_idx > 0
+            && self.char_class(Location {
+                grapheme_idx: location.grapheme_idx - 1,
+                ..location
+            }) == word_class
+        {
+            location.grapheme_idx -= 1;
+        }
+        location
+    }
+
+    /// `e`: the last character of the current or next word.
+    fn word_end(&self, from: Location) -> Location {
+        let mut location = Location {
+            grapheme_idx: from.grapheme_idx + 1,
+            ..from
+        };
+        loop {
+            if location.grapheme_idx >= self.line_len(location.line_idx) {
+                if location.line_idx >= self.last_line_idx() {
+                    return from;
+                }
+                location = Location {
+                    line_idx: location.line_idx + 1,
+                    grapheme_idx: 0,
+                };
+            } else if self.char_class(location) == CharClass::Space {
+                location.grapheme_idx += 1;
+            } else {
+                break;
+            }
+        }
+        let word_class = self.char_class(location);
+        while location.grapheme_idx + 1 < self.line_len(location.line_idx)
+            && self.char_class(Location {
+                grapheme_idx: location.grapheme_idx + 1,
+                ..location
+            }) == word_class
+        {
+            location.grapheme_idx += 1;
+        }
+        location
+    }
+
+    fn first_non_blank(&self, line_idx: usize) -> Location {
+        let mut location = Location {
+            line_idx,
+            grapheme_idx: 0,
+        };
+        while location.grapheme_idx < self.line_len(line_idx)
+            && self.char_class(location) == CharClass::Space
+        {
+            location.grapheme_idx += 1;
+        }
+        location
+    }
+
+    fn motion_target(&self, motion: Motion, count: usize) -> Location {
+        let from = self.text_location;
+        let repeat = |step: &dyn Fn(Location) -> Location| {
+            (0..count).fold(from, |location, _| step(location))
+        };
+        let on_line = |line_idx: usize| {
+            self.clamp_location(min(line_idx, self.last_line_idx()), from.grapheme_idx)
+        };
+        match motion {
+            Motion::Left => Location {
+                grapheme_idx: from.grapheme_idx.saturating_sub(count),
+                ..from
+            },
+            Motion::Right => Location {
+                grapheme_idx: min(
+                    from.grapheme_idx.saturating_add(count),
+                    self.line_len(from.line_idx),
+                ),
+                ..from
+            },
+            Motion::Up => on_line(self.step_visible_lines(from.line_idx, count, false)),
+            Motion::Down => on_line(self.step_visible_lines(from.line_idx, count, true)),
+            Motion::WordForward => repeat(&|location| self.next_word_start(location)),
+            Motion::WordBackward => repeat(&|location| self.previous_word_start(location)),
+            Motion::WordEnd => repeat(&|location| self.word_end(location)),
+            Motion::LineStart => Location {
+                grapheme_idx: 0,
+                ..from
+            },
+            Motion::LineEnd => {
+                let line_idx = min(
+                    (from.line_idx + count).saturating_sub(1),
+                    self.last_line_idx(),
+                );
+                Location {
+                    line_idx,
+                    grapheme_idx: self.line_len(line_idx),
+                }
+            }
+            Motion::FirstLine => self.first_non_blank(0),
+            Motion::LastLine => self.first_non_blank(self.last_line_idx()),
+            Motion::Line(line) => {
+                self.first_non_blank(min(line.saturating_sub(1), self.last_line_idx()))
+            }
+        }
+    }
+
+    pub fn apply_motion(&mut self, motion: Motion, count: usize) {
+        if matches!(
+            motion,
+            Motion::FirstLine | Motion::LastLine | Motion::Line(_)
+        ) {
+            self.record_jump();
+        }
+        self.text_location = self.motion_target(motion, count);
+        if self.visual_anchor.is_some() {
+            self.update_visual_selection();
+        } else {
+            self.clear_selection();
+        }
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+    }
+
+    pub fn apply_operator(&mut self, operator: Operator, motion: Motion, count: usize) {
+        let from = self.text_location;
+        if motion.is_linewise() {
+            let target = self.motion_target(motion, count);
+            let first = min(from.line_idx, target.line_idx);
+            let last = from.line_idx.max(target.line_idx);
+            self.operate_on_lines(operator, first, last);
+            return;
+        }
+
+        // `cw` behaves like `ce`, as in vi.
+        let motion = if operator == Operator::Change && motion == Motion::WordForward {
+            Motion::WordEnd
+        } else {
+            motion
+        };
+        let mut target = self.motion_target(motion, count);
+        if motion == Motion::WordEnd {
+            target.grapheme_idx = min(target.grapheme_idx + 1, self.line_len(target.line_idx));
+        }
+        // A word motion never makes an operator join the following line.
+        if motion == Motion::WordForward && target.line_idx > from.line_idx {
+            target = Location {
+                line_idx: target.line_idx - 1,
+                grapheme_idx: self.line_len(target.line_idx - 1),
+            };
+        }
+        let (start, end) = ordered(from, target);
+        self.operate_on_range(operator, start, end);
+    }
+
+    /// `dd`, `cc` and `yy` on `count` lines starting at the caret.
+    pub fn operate_on_current_lines(&mut self, operator: Operator, count: usize) {
+        let first = self.text_location.line_idx;
+        let last = min(first + count.saturating_sub(1), self.last_line_idx());
+        self.operate_on_lines(operator, first, last);
+    }
+
+    pub fn operate_on_selection(&mut self, operator: Operator) {
+        let selection = self.selection.map(|selection| selection.normalize());
+        self.visual_anchor = None;
+        self.clear_selection();
+        if let Some(selection) = selection {
+            self.operate_on_range(operator, selection.start, selection.end);
+        }
+    }
+
+    fn operate_on_range(&mut self, operator: Operator, start: Location, end: Location) {
+        let text = self.text_in_range(start, end);
+        self.set_register(Register {
+            text,
+            linewise: false,
+        });
+        if operator != Operator::Yank {
+            self.delete_range(start, end);
+        }
+        self.text_location = start;
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+    }
+
+    fn operate_on_lines(&mut self, operator: Operator, first: usize, last: usize) {
+        if self.buffer.height() == 0 {
+            return;
+        }
+        let mut text: String = self.buffer.lines[first..=last]
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+        text.push('\n');
+        self.set_register(Register {
+            text,
+            linewise: true,
+        });
+
+        match operator {
+            Operator::Yank => {}
+            Operator::Delete => {
+                self.buffer.lines.drain(first..=last);
+                self.lines_removed(first..last + 1);
+                if self.buffer.lines.is_empty() {
+                    self.buffer.lines.push(Line::default());
+                    self.lines_changed(0..1);
+                }
+            }
+            Operator::Change => {
+                self.buffer.lines.drain(first + 1..=last);
+                self.lines_removed(first + 1..last + 1);
+                self.buffer.lines[first] = Line::default();
+                self.lines_changed(first..first + 1);
+            }
+        }
+        if operator != Operator::Yank {
+            self.buffer.modified = true;
+            self.cache_version += 1;
+        }
+        self.text_location = self.first_non_blank(min(first, self.last_line_idx()));
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+    }
+
+    fn text_in_range(&self, start: Location, end: Location) -> String {
+        let selection = Selection::new(start, end);
+        self.selection_to_string(&selection).unwrap_or_default()
+    }
+
+    /// Removes the text between two ordered locations, joining lines as needed.
+    fn delete_range(&mut self, start: Location, end: Location) {
+        if start == end || start.line_idx >= self.buffer.height() {
+            return;
+        }
+        if start.line_idx == end.line_idx {
+            self.buffer.lines[start.line_idx]
+                .delete_grapheme_range(start.grapheme_idx..end.grapheme_idx);
+            self.lines_changed(start.line_idx..start.line_idx + 1);
+        } else {
+            let end_line_idx = min(end.line_idx, self.last_line_idx());
+            let tail = self.buffer.lines[end_line_idx].split(end.grapheme_idx);
+            let start_line = &mut self.buffer.lines[start.line_idx];
+            let _ = start_line.split(start.grapheme_idx);
+            start_line.append(&tail);
+            self.lines_changed(start.line_idx..start.line_idx + 1);
+            self.buffer.lines.drain(start.line_idx + 1..=end_line_idx);
+            self.lines_removed(start.line_idx + 1..end_line_idx + 1);
+        }
+        self.buffer.modified = true;
+        self.cache_version += 1;
+    }
+
+    fn set_register(&mut self, register: Register) {
+        if let Ok(mut clipboard) = Clipboard::new() {
+            let _ = clipboard.set_text(register.text.clone());
+        }
+        self.register = register;
+    }
+
+    /// `p` pastes after the caret (or below the line for whole lines),
+    /// `P` before it (or above).
+    pub fn paste_register(&mut self, before: bool) {
+        let register = self.register.clone();
+        if register.text.is_empty() {
+            return;
+        }
+        self.clear_selection();
+        if register.linewise {
+            let line_idx = if before {
+                self.text_location.line_idx
+            } else {
+                min(self.text_location.line_idx + 1, self.buffer.height())
+            };
+            let lines = register.text.strip_suffix('\n').unwrap_or(&register.text);
+            for (offset, line) in lines.split('\n').enumerate() {
+                self.buffer
+                    .lines
+                    .insert(line_idx + offset, Line::from(line));
+            }
+            self.lines_inserted(line_idx, lines.split('\n').count());
+            self.buffer.modified = true;
+            self.cache_version += 1;
+            self.text_location = self.first_non_blank(line_idx);
+            self.scroll_text_location_into_view();
+            self.mark_redraw(true);
+        } else {
+            if !before
+                && self.text_location.grapheme_idx < self.line_len(self.text_location.line_idx)
+            {
+                self.text_location.grapheme_idx += 1;
+            }
+            self.paste_text(&register.text);
+        }
+    }
+
+    pub fn start_insert(&mut self, at: InsertAt) {
+        self.clear_selection();
+        let line_idx = self.text_location.line_idx;
+        match at {
+            InsertAt::Caret => {}
+            InsertAt::AfterCaret => {
+                self.text_location.grapheme_idx =
+                    min(self.text_location.grapheme_idx + 1, self.line_len(line_idx));
+            }
+            InsertAt::LineStart => self.text_location = self.first_non_blank(line_idx),
+            InsertAt::LineEnd => self.text_location.grapheme_idx = self.line_len(line_idx),
+            InsertAt::LineBelow => {
+                self.text_location.grapheme_idx = self.line_len(line_idx);
+                self.insert_newline();
+            }
+            InsertAt::LineAbove => {
+                self.text_location.grapheme_idx = 0;
+                self.insert_newline();
+                self.text_location = Location {
+                    line_idx,
+                    grapheme_idx: 0,
+                };
+            }
+        }
+        self.scroll_text_location_into_view();
+        self.mark_redraw(true);
+    }
+
+    pub fn start_visual(&mut self) {
+        self.visual_anchor = Some(self.text_location);
+        self.update_visual_selection();
+    }
+
+    /// Leaves insert or visual mode. Like vi, leaving insert mode steps the
+    /// caret back onto the last inserted character.
+    pub fn enter_normal(&mut self) {
+        if self.visual_anchor.take().is_some() {
+            self.clear_selection();
+        } else {
+            self.text_location.grapheme_idx = self.text_location.grapheme_idx.saturating_sub(1);
+            self.scroll_text_location_into_view();
+        }
+        self.mark_redraw(true);
+    }
+
+    /// Visual selections include the character under the caret.
+    fn update_visual_selection(&mut self) {
+        let Some(anchor) = self.visual_anchor else {
+            return;
+        };
+        let (start, mut end) = ordered(anchor, self.text_location);
+        end.grapheme_idx = min(end.grapheme_idx + 1, self.line_len(end.line_idx));
+        self.selection = Some(Selection::new(start, end));
+        self.mark_redraw(true);
+    }
+}